@@ -0,0 +1,93 @@
+// Compares a linear loop over every entity with the BVH used by Scene::trace.
+// Run with `cargo bench --bench bvh`.
+
+use pathtracer_rs::camera::Ray;
+use pathtracer_rs::material::DiffuseMaterial;
+use pathtracer_rs::scene::{Entity, Scene};
+use pathtracer_rs::shape::{Shape, Sphere};
+use pathtracer_rs::vector::Vector3;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::time::Instant;
+
+const NUM_OF_SPHERES: usize = 10_000;
+const NUM_OF_RAYS: usize = 20_000;
+
+fn main() {
+    let mut rgen = StdRng::seed_from_u64(1);
+    let spheres: Vec<Sphere> = (0..NUM_OF_SPHERES)
+        .map(|_| Sphere {
+            position: Vector3::new(
+                rgen.gen_range(-50.0..50.0),
+                rgen.gen_range(-50.0..50.0),
+                rgen.gen_range(-50.0..50.0),
+            ),
+            radius: rgen.gen_range(0.1..1.0),
+        })
+        .collect();
+    let rays: Vec<Ray> = (0..NUM_OF_RAYS)
+        .map(|_| Ray {
+            origin: Vector3::new(0.0, 0.0, -100.0),
+            direction: Vector3::new(rgen.gen_range(-0.5..0.5), rgen.gen_range(-0.5..0.5), 1.0)
+                .unit(),
+            time: 0.0,
+        })
+        .collect();
+
+    let material = DiffuseMaterial {
+        color: Vector3::new(0.8, 0.8, 0.8),
+    };
+    let mut scene = Scene::new(Vector3::zero_vector());
+    for sphere in spheres.iter() {
+        scene.add_entity(Entity {
+            material: &material,
+            shape: sphere,
+        });
+    }
+
+    let start = Instant::now();
+    let mut linear_hits = 0;
+    for ray in rays.iter() {
+        let mut t = f32::MAX;
+        for sphere in spheres.iter() {
+            let intersection = sphere.intersect(ray);
+            if intersection.t >= 0.0 && intersection.t < t {
+                t = intersection.t;
+            }
+        }
+        if t < f32::MAX {
+            linear_hits += 1;
+        }
+    }
+    let linear_time = start.elapsed();
+
+    let start = Instant::now();
+    scene.trace(&rays[0]); // builds the BVH
+    let build_time = start.elapsed();
+
+    let start = Instant::now();
+    let mut bvh_hits = 0;
+    for ray in rays.iter() {
+        if scene.trace(ray).shape_intersection.t >= 0.0 {
+            bvh_hits += 1;
+        }
+    }
+    let bvh_time = start.elapsed();
+
+    assert_eq!(linear_hits, bvh_hits);
+    println!(
+        "{} spheres, {} rays ({} hits)",
+        NUM_OF_SPHERES, NUM_OF_RAYS, bvh_hits
+    );
+    println!(
+        "linear: {:?} ({:.0} rays/s)",
+        linear_time,
+        NUM_OF_RAYS as f64 / linear_time.as_secs_f64()
+    );
+    println!(
+        "bvh:    {:?} ({:.0} rays/s), built in {:?}",
+        bvh_time,
+        NUM_OF_RAYS as f64 / bvh_time.as_secs_f64(),
+        build_time
+    );
+}
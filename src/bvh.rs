@@ -0,0 +1,440 @@
+use crate::camera::Ray;
+use crate::vector::Vector3;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: Vector3,
+    pub max: Vector3,
+}
+
+// Nodes are stored depth first: the first child of an interior node directly follows it and
+// offset points to the second child. Leaves use offset as the first primitive of their range.
+#[derive(Debug, Clone, Copy)]
+struct BvhNode {
+    bounds: Aabb,
+    offset: u32,
+    num_of_primitives: u16, // 0 for interior nodes
+    axis: u8,
+}
+
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    primitives: Vec<u32>,
+}
+
+struct BuildPrimitive {
+    bounds: Aabb,
+    centroid: Vector3,
+    index: u32,
+}
+
+const NUM_OF_BUCKETS: usize = 12;
+const MAX_PRIMITIVES_IN_LEAF: usize = 4;
+const TRAVERSAL_COST: f32 = 0.125; // relative to the cost of intersecting a primitive
+const MAX_DEPTH: usize = 64;
+// from this depth the primitives are split in halves, so that even 2^32 of them fit the u16
+// counts of the leaves by MAX_DEPTH, which bounds the traversal stack
+const MEDIAN_SPLIT_DEPTH: usize = MAX_DEPTH - 17;
+
+impl Aabb {
+    pub fn empty() -> Aabb {
+        Aabb {
+            min: Vector3::new(f32::MAX, f32::MAX, f32::MAX),
+            max: Vector3::new(f32::MIN, f32::MIN, f32::MIN),
+        }
+    }
+
+    pub fn from_points(points: &[Vector3]) -> Aabb {
+        let mut bounds = Aabb::empty();
+        for point in points {
+            bounds.grow(point);
+        }
+        bounds
+    }
+
+    #[inline(always)]
+    pub fn grow(&mut self, point: &Vector3) {
+        self.min = Vector3::new(
+            self.min.x.min(point.x),
+            self.min.y.min(point.y),
+            self.min.z.min(point.z),
+        );
+        self.max = Vector3::new(
+            self.max.x.max(point.x),
+            self.max.y.max(point.y),
+            self.max.z.max(point.z),
+        );
+    }
+
+    #[inline(always)]
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        let mut bounds = *self;
+        bounds.grow(&other.min);
+        bounds.grow(&other.max);
+        bounds
+    }
+
+    #[inline(always)]
+    pub fn centroid(&self) -> Vector3 {
+        &(&self.min + &self.max) * 0.5
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        if self.min.x > self.max.x {
+            return 0.0;
+        }
+        let extent = &self.max - &self.min;
+        2.0 * (extent.x * extent.y + extent.y * extent.z + extent.z * extent.x)
+    }
+
+    pub fn largest_axis(&self) -> usize {
+        let extent = &self.max - &self.min;
+        if extent.x > extent.y && extent.x > extent.z {
+            0
+        } else if extent.y > extent.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    // slab test, inv_direction is 1 / ray.direction
+    #[inline(always)]
+    pub fn intersect(&self, ray: &Ray, inv_direction: &Vector3, t_max: f32) -> bool {
+        let mut t0 = 0.0_f32;
+        let mut t1 = t_max;
+        for axis in 0..3 {
+            if inv_direction[axis].is_infinite() {
+                // ray parallel to the slab
+                if ray.origin[axis] < self.min[axis] || ray.origin[axis] > self.max[axis] {
+                    return false;
+                }
+                continue;
+            }
+            let t_near = (self.min[axis] - ray.origin[axis]) * inv_direction[axis];
+            let t_far = (self.max[axis] - ray.origin[axis]) * inv_direction[axis];
+            t0 = t0.max(t_near.min(t_far));
+            t1 = t1.min(t_near.max(t_far));
+        }
+        t0 <= t1
+    }
+}
+
+impl Bvh {
+    pub fn build(bounds: &[Aabb]) -> Bvh {
+        let mut build_primitives: Vec<BuildPrimitive> = bounds
+            .iter()
+            .enumerate()
+            .map(|(index, bounds)| BuildPrimitive {
+                bounds: *bounds,
+                centroid: bounds.centroid(),
+                index: index as u32,
+            })
+            .collect();
+
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(2 * bounds.len()),
+            primitives: Vec::with_capacity(bounds.len()),
+        };
+        if !build_primitives.is_empty() {
+            bvh.build_recursive(&mut build_primitives, 0);
+        }
+        bvh
+    }
+
+    fn push_leaf(&mut self, bounds: Aabb, primitives: &[BuildPrimitive]) -> usize {
+        self.nodes.push(BvhNode {
+            bounds,
+            offset: self.primitives.len() as u32,
+            num_of_primitives: primitives.len() as u16,
+            axis: 0,
+        });
+        self.primitives
+            .extend(primitives.iter().map(|primitive| primitive.index));
+        self.nodes.len() - 1
+    }
+
+    fn build_recursive(&mut self, primitives: &mut [BuildPrimitive], depth: usize) -> usize {
+        let bounds = primitives.iter().fold(Aabb::empty(), |bounds, primitive| {
+            bounds.union(&primitive.bounds)
+        });
+        let count = primitives.len();
+        if count == 1 {
+            return self.push_leaf(bounds, primitives);
+        }
+
+        let centroid_bounds = primitives
+            .iter()
+            .fold(Aabb::empty(), |mut bounds, primitive| {
+                bounds.grow(&primitive.centroid);
+                bounds
+            });
+        let axis = centroid_bounds.largest_axis();
+        let axis_min = centroid_bounds.min[axis];
+        let axis_extent = centroid_bounds.max[axis] - axis_min;
+        let leaf_allowed = count <= u16::MAX as usize;
+        if (axis_extent <= 0.0 || depth >= MAX_DEPTH) && leaf_allowed {
+            return self.push_leaf(bounds, primitives);
+        }
+
+        let mid = if count <= 2 || axis_extent <= 0.0 || depth >= MEDIAN_SPLIT_DEPTH {
+            primitives.sort_by(|a, b| a.centroid[axis].total_cmp(&b.centroid[axis]));
+            count / 2
+        } else {
+            // binned surface area heuristic
+            let bucket_of = |primitive: &BuildPrimitive| {
+                let bucket = ((primitive.centroid[axis] - axis_min) / axis_extent
+                    * NUM_OF_BUCKETS as f32) as usize;
+                bucket.min(NUM_OF_BUCKETS - 1)
+            };
+            let mut bucket_counts = [0_usize; NUM_OF_BUCKETS];
+            let mut bucket_bounds = [Aabb::empty(); NUM_OF_BUCKETS];
+            for primitive in primitives.iter() {
+                let bucket = bucket_of(primitive);
+                bucket_counts[bucket] += 1;
+                bucket_bounds[bucket] = bucket_bounds[bucket].union(&primitive.bounds);
+            }
+
+            let mut best_split = 0;
+            let mut best_cost = f32::MAX;
+            for split in 0..NUM_OF_BUCKETS - 1 {
+                let (mut left, mut right) = (Aabb::empty(), Aabb::empty());
+                let (mut left_count, mut right_count) = (0, 0);
+                for bucket in 0..=split {
+                    left = left.union(&bucket_bounds[bucket]);
+                    left_count += bucket_counts[bucket];
+                }
+                for bucket in split + 1..NUM_OF_BUCKETS {
+                    right = right.union(&bucket_bounds[bucket]);
+                    right_count += bucket_counts[bucket];
+                }
+                let cost = left_count as f32 * left.surface_area()
+                    + right_count as f32 * right.surface_area();
+                if cost < best_cost {
+                    best_cost = cost;
+                    best_split = split;
+                }
+            }
+
+            let surface_area = bounds.surface_area();
+            let split_cost = if surface_area > 0.0 {
+                TRAVERSAL_COST + best_cost / surface_area
+            } else {
+                TRAVERSAL_COST + count as f32
+            };
+            if count <= MAX_PRIMITIVES_IN_LEAF && split_cost >= count as f32 {
+                return self.push_leaf(bounds, primitives);
+            }
+
+            let mut mid = 0;
+            for i in 0..count {
+                if bucket_of(&primitives[i]) <= best_split {
+                    primitives.swap(i, mid);
+                    mid += 1;
+                }
+            }
+            mid
+        };
+
+        let node_index = self.nodes.len();
+        self.nodes.push(BvhNode {
+            bounds,
+            offset: 0,
+            num_of_primitives: 0,
+            axis: axis as u8,
+        });
+        let (left, right) = primitives.split_at_mut(mid);
+        self.build_recursive(left, depth + 1);
+        let second_child = self.build_recursive(right, depth + 1);
+        self.nodes[node_index].offset = second_child as u32;
+        node_index
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.nodes.first().map_or(Aabb::empty(), |node| node.bounds)
+    }
+
+    // Calls intersect_primitive for every primitive whose bounds are hit before the closest
+    // intersection so far. intersect_primitive returns the intersection t, negative for a miss.
+    #[inline(always)]
+    pub fn traverse<F>(&self, ray: &Ray, mut intersect_primitive: F)
+    where
+        F: FnMut(usize) -> f32,
+    {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let inv_direction = Vector3::new(
+            1.0 / ray.direction.x,
+            1.0 / ray.direction.y,
+            1.0 / ray.direction.z,
+        );
+        let direction_is_negative = [
+            inv_direction.x < 0.0,
+            inv_direction.y < 0.0,
+            inv_direction.z < 0.0,
+        ];
+        let mut t_max = f32::MAX;
+        let mut stack = [0_u32; MAX_DEPTH + 1];
+        let mut stack_size = 0;
+        let mut node_index = 0;
+        loop {
+            let node = &self.nodes[node_index];
+            if node.bounds.intersect(ray, &inv_direction, t_max) {
+                if node.num_of_primitives > 0 {
+                    let first = node.offset as usize;
+                    for primitive in
+                        &self.primitives[first..first + node.num_of_primitives as usize]
+                    {
+                        let t = intersect_primitive(*primitive as usize);
+                        if t >= 0.0 && t < t_max {
+                            t_max = t;
+                        }
+                    }
+                } else if direction_is_negative[node.axis as usize] {
+                    // visit the second child first, it is closer along the ray
+                    stack[stack_size] = node_index as u32 + 1;
+                    stack_size += 1;
+                    node_index = node.offset as usize;
+                    continue;
+                } else {
+                    stack[stack_size] = node.offset;
+                    stack_size += 1;
+                    node_index += 1;
+                    continue;
+                }
+            }
+
+            if stack_size == 0 {
+                break;
+            }
+            stack_size -= 1;
+            node_index = stack[stack_size] as usize;
+        }
+    }
+}
+
+#[cfg(test)]
+mod bvh_tests {
+    use super::{Aabb, Bvh, MAX_DEPTH};
+    use crate::camera::Ray;
+    use crate::shape::{Shape, Sphere};
+    use crate::vector::Vector3;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn aabb_test() {
+        let bounds =
+            Aabb::from_points(&[Vector3::new(-1.0, 0.0, 2.0), Vector3::new(1.0, 1.0, 3.0)]);
+        assert!(bounds.centroid() == Vector3::new(0.0, 0.5, 2.5));
+        assert_eq!(bounds.largest_axis(), 0);
+        assert!((bounds.surface_area() - 10.0).abs() < 1e-5);
+
+        let ray = Ray {
+            origin: Vector3::zero_vector(),
+            direction: Vector3::new(0.0, 0.0, 1.0),
+            time: 0.0,
+        };
+        let inv_direction = Vector3::new(f32::INFINITY, f32::INFINITY, 1.0);
+        assert!(bounds.intersect(&ray, &inv_direction, f32::MAX));
+        assert!(!bounds.intersect(&ray, &inv_direction, 1.0));
+
+        // flat box hit by a ray lying in its plane
+        let flat = Aabb::from_points(&[Vector3::new(-1.0, 0.0, 2.0), Vector3::new(1.0, 0.0, 3.0)]);
+        assert!(flat.intersect(&ray, &inv_direction, f32::MAX));
+    }
+
+    #[test]
+    fn traversal_matches_linear_test() {
+        let mut rgen = StdRng::seed_from_u64(7);
+        let spheres: Vec<Sphere> = (0..500)
+            .map(|_| Sphere {
+                position: Vector3::new(
+                    rgen.gen_range(-10.0..10.0),
+                    rgen.gen_range(-10.0..10.0),
+                    rgen.gen_range(-10.0..10.0),
+                ),
+                radius: rgen.gen_range(0.05..0.5),
+            })
+            .collect();
+        let bounds: Vec<Aabb> = spheres.iter().map(|sphere| sphere.bounds()).collect();
+        let bvh = Bvh::build(&bounds);
+
+        for _ in 0..1000 {
+            let ray = Ray {
+                origin: Vector3::new(
+                    rgen.gen_range(-12.0..12.0),
+                    rgen.gen_range(-12.0..12.0),
+                    rgen.gen_range(-12.0..12.0),
+                ),
+                direction: Vector3::new(
+                    rgen.gen_range(-1.0..1.0),
+                    rgen.gen_range(-1.0..1.0),
+                    rgen.gen_range(-1.0..1.0),
+                )
+                .unit(),
+                time: 0.0,
+            };
+
+            let mut linear_t = f32::MAX;
+            for sphere in spheres.iter() {
+                let t = sphere.intersect(&ray).t;
+                if t >= 0.0 && t < linear_t {
+                    linear_t = t;
+                }
+            }
+
+            let mut bvh_t = f32::MAX;
+            bvh.traverse(&ray, |index| {
+                let t = spheres[index].intersect(&ray).t;
+                if t >= 0.0 && t < bvh_t {
+                    bvh_t = t;
+                }
+                t
+            });
+            assert_eq!(linear_t, bvh_t);
+        }
+    }
+
+    #[test]
+    fn deep_tree_test() {
+        // more primitives than a leaf holds, piled at the origin under chains of ever closer ones
+        // along each axis, which the surface area heuristic peels off one level at a time
+        let mut bounds: Vec<Aabb> = (0..70000)
+            .map(|_| Aabb::from_points(&[Vector3::zero_vector()]))
+            .collect();
+        for i in 0..38 {
+            let x = f32::powi(13.0, -i);
+            for point in [
+                Vector3::new(x, 0.0, 0.0),
+                Vector3::new(0.0, x, 0.0),
+                Vector3::new(0.0, 0.0, x),
+            ] {
+                bounds.push(Aabb::from_points(&[point]));
+            }
+        }
+        let bvh = Bvh::build(&bounds);
+        fn depth(bvh: &Bvh, node_index: usize) -> usize {
+            let node = &bvh.nodes[node_index];
+            if node.num_of_primitives > 0 {
+                return 0;
+            }
+            1 + usize::max(depth(bvh, node_index + 1), depth(bvh, node.offset as usize))
+        }
+        assert!(depth(&bvh, 0) <= MAX_DEPTH);
+
+        let ray = Ray {
+            origin: Vector3::new(0.0, 0.0, -1.0),
+            direction: Vector3::new(0.0, 0.0, 1.0),
+            time: 0.0,
+        };
+        let mut hits = 0;
+        bvh.traverse(&ray, |_| {
+            hits += 1;
+            -1.0
+        });
+        assert!(hits >= 70000);
+    }
+}
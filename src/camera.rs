@@ -1,13 +1,72 @@
-use crate::tools;
+use crate::tools::{self, Distribution2D, Sample2D};
 use crate::vector::Vector3;
+use image::ImageError;
+use std::f32::consts::PI;
+use std::path::Path;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Ray {
     pub origin: Vector3,
     pub direction: Vector3,
+    // instant of the ray in the shutter interval of the camera. The moving shapes go from their
+    // declared placement at 0 to their moved one at 1
+    pub time: f32,
 }
 
-#[derive(Debug, Clone, Copy)]
+// Projection from the film to the rays leaving the camera
+pub trait CameraModel: Sync {
+    // film coordinates in [0, 1] from the bottom left corner, the lens sample picks the point of
+    // the aperture the ray goes through and the time sample in [0, 1] the instant of the ray in the
+    // shutter interval. The direction is zero for the parts of the film the camera does not see
+    fn generate_ray(
+        &self,
+        film_x: f32,
+        film_y: f32,
+        lens_sample: &Sample2D,
+        time_sample: f32,
+    ) -> Ray;
+}
+
+// Interval of time while the camera sees the scene, motion during it blurs the image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shutter {
+    pub open: f32,
+    pub close: f32,
+}
+
+// look direction towards the target and up direction made perpendicular to it. An up along the
+// look does not orient the camera, any perpendicular direction is used instead
+fn look_at_basis(eye: &Vector3, target: &Vector3, up: &Vector3) -> (Vector3, Vector3) {
+    let look = (target - eye).unit();
+    let mut right = up.cross(&look);
+    if right.length() <= 1e-6 * up.length() {
+        let mut b = Vector3::zero_vector();
+        look.create_basis(&mut b, &mut right);
+    }
+    (look, look.cross(&right.unit()))
+}
+
+// Shape of the opening of the lens, which is also the shape of the out of focus highlights
+#[derive(Debug, Clone)]
+pub enum Aperture {
+    Circle,
+    // regular polygon inscribed in the circle, turned by rotation radians
+    Polygon { blades: u32, rotation: f32 },
+    // mask over the square around the circle, the lens is sampled proportionally to its values so
+    // the shape should fit in the circle
+    Image(Distribution2D),
+}
+
+// Thin lens in front of the camera, only the points at the focus distance are sharp. A zero
+// aperture radius gives a pinhole where everything is in focus
+#[derive(Debug, Clone)]
+pub struct ThinLens {
+    pub aperture_radius: f32,
+    pub focus_distance: f32,
+    pub aperture: Aperture,
+}
+
+#[derive(Debug, Clone)]
 pub struct Camera {
     position: Vector3,
     up: Vector3,
@@ -15,6 +74,50 @@ pub struct Camera {
     right: Vector3,
     width: f32,
     aspect_ratio: f32,
+    lens: ThinLens,
+    shutter: Shutter,
+}
+
+// Whole sphere of directions around the position, longitude along the film x and latitude along
+// the film y with the look direction at the center
+pub struct EquirectangularCamera {
+    position: Vector3,
+    look: Vector3,
+    right: Vector3,
+    up: Vector3,
+    shutter: Shutter,
+}
+
+// How the angle from the look direction grows with the distance from the center of the film
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FisheyeMapping {
+    Equidistant, // proportionally
+    Equisolid,   // keeping the areas of solid angles
+}
+
+// Circular fisheye, the image circle touches the left and right sides of the film and holds the
+// field of view, up to 360 degrees
+pub struct FisheyeCamera {
+    position: Vector3,
+    look: Vector3,
+    right: Vector3,
+    up: Vector3,
+    fov: f32,
+    aspect_ratio: f32,
+    mapping: FisheyeMapping,
+    shutter: Shutter,
+}
+
+// Faces of a cubemap, each seen by a square 90 degree camera. The side faces are upright, the top
+// of the +y face is towards -z and the one of the -y face towards +z
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CubeFace {
+    PositiveX,
+    NegativeX,
+    PositiveY,
+    NegativeY,
+    PositiveZ,
+    NegativeZ,
 }
 
 pub struct OrthographicCamera {
@@ -22,6 +125,79 @@ pub struct OrthographicCamera {
     look_dir: Vector3,
     horizontal: Vector3,
     vertical: Vector3,
+    shutter: Shutter,
+}
+
+impl Aperture {
+    // mask given row by row from the top, brighter pixels let more light through
+    pub fn from_mask(width: usize, height: usize, mask: &[f32]) -> Aperture {
+        Aperture::Image(Distribution2D::new(mask, width, height))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Aperture, ImageError> {
+        let image = image::open(path)?.into_luma8();
+        let mask: Vec<f32> = image
+            .pixels()
+            .map(|pixel| pixel[0] as f32 / 255.0)
+            .collect();
+        Ok(Aperture::from_mask(
+            image.width() as usize,
+            image.height() as usize,
+            &mask,
+        ))
+    }
+
+    // point of the aperture, in the unit disk or in the square around it for a mask
+    pub fn sample(&self, sample: &Sample2D) -> (f32, f32) {
+        match self {
+            Aperture::Circle => tools::concentric_sample_disk(sample),
+            Aperture::Polygon { blades, rotation } => {
+                // the triangles between the center and each side all have the same area
+                let scaled = sample.s * *blades as f32;
+                let blade = f32::min(scaled.floor(), (blades - 1) as f32);
+                let u = f32::min(scaled - blade, 1.0).sqrt();
+                let angle = 2.0 * PI / *blades as f32;
+                let angle0 = rotation + blade * angle;
+                let angle1 = angle0 + angle;
+                (
+                    u * ((1.0 - sample.t) * angle0.cos() + sample.t * angle1.cos()),
+                    u * ((1.0 - sample.t) * angle0.sin() + sample.t * angle1.sin()),
+                )
+            }
+            Aperture::Image(distribution) => {
+                let (point, _) = distribution.sample(sample);
+                (2.0 * point.s - 1.0, 1.0 - 2.0 * point.t)
+            }
+        }
+    }
+}
+
+impl Default for Shutter {
+    // open during the whole motion of the shapes
+    fn default() -> Shutter {
+        Shutter {
+            open: 0.0,
+            close: 1.0,
+        }
+    }
+}
+
+impl Shutter {
+    // instant for a sample in [0, 1], uniformly over the interval
+    #[inline(always)]
+    pub fn time(&self, sample: f32) -> f32 {
+        self.open + sample * (self.close - self.open)
+    }
+}
+
+impl Default for ThinLens {
+    fn default() -> ThinLens {
+        ThinLens {
+            aperture_radius: 0.0,
+            focus_distance: 1.0,
+            aperture: Aperture::Circle,
+        }
+    }
 }
 
 impl Camera {
@@ -31,6 +207,24 @@ impl Camera {
         position: Vector3,
         look_dir: Vector3,
         up_dir: Vector3,
+    ) -> Camera {
+        Camera::with_lens(
+            fov,
+            aspect_ratio,
+            position,
+            look_dir,
+            up_dir,
+            ThinLens::default(),
+        )
+    }
+
+    pub fn with_lens(
+        fov: f32,
+        aspect_ratio: f32,
+        position: Vector3,
+        look_dir: Vector3,
+        up_dir: Vector3,
+        lens: ThinLens,
     ) -> Camera {
         let width = f32::tan(fov * 0.5) * 2.0;
         Camera {
@@ -40,22 +234,66 @@ impl Camera {
             right: up_dir.cross(&look_dir),
             width,
             aspect_ratio,
+            lens,
+            shutter: Shutter::default(),
         }
     }
 
+    pub fn with_shutter(self, shutter: Shutter) -> Camera {
+        Camera { shutter, ..self }
+    }
+
+    pub fn look_at(
+        fov: f32,
+        aspect_ratio: f32,
+        eye: Vector3,
+        target: Vector3,
+        up: Vector3,
+    ) -> Camera {
+        let (look, up) = look_at_basis(&eye, &target, &up);
+        Camera::new(fov, aspect_ratio, eye, look, up)
+    }
+}
+
+impl CameraModel for Camera {
     #[inline(always)]
-    pub fn generate_ray(&self, film_x: f32, film_y: f32) -> Ray {
+    fn generate_ray(
+        &self,
+        film_x: f32,
+        film_y: f32,
+        lens_sample: &Sample2D,
+        time_sample: f32,
+    ) -> Ray {
         debug_assert!((0.0..=1.0).contains(&film_x) && (0.0..=1.0).contains(&film_y));
         let film_position = Vector3 {
-            x: self.width * film_x - 1.0,
-            y: (self.width * film_y - 1.0) / self.aspect_ratio,
+            x: self.width * (film_x - 0.5),
+            y: self.width * (film_y - 0.5) / self.aspect_ratio,
             z: 1.0,
         };
-        let film_position_camera =
-            Vector3::to_basis(&film_position, &self.look, &self.right, &self.up);
+        if self.lens.aperture_radius <= 0.0 {
+            let film_position_camera =
+                Vector3::to_basis(&film_position, &self.look, &self.right, &self.up);
+            return Ray {
+                origin: self.position,
+                direction: film_position_camera.unit(),
+                time: self.shutter.time(time_sample),
+            };
+        }
+
+        // every ray through the lens for this film position meets on the plane of focus
+        let focus_point = &film_position * self.lens.focus_distance;
+        let (lens_x, lens_y) = self.lens.aperture.sample(lens_sample);
+        let lens_point = Vector3::new(
+            lens_x * self.lens.aperture_radius,
+            lens_y * self.lens.aperture_radius,
+            0.0,
+        );
+        let direction = &focus_point - &lens_point;
         Ray {
-            origin: self.position,
-            direction: film_position_camera.unit(),
+            origin: &self.position
+                + &Vector3::to_basis(&lens_point, &self.look, &self.right, &self.up),
+            direction: Vector3::to_basis(&direction, &self.look, &self.right, &self.up).unit(),
+            time: self.shutter.time(time_sample),
         }
     }
 }
@@ -76,22 +314,199 @@ impl OrthographicCamera {
             look_dir,
             horizontal,
             vertical,
+            shutter: Shutter::default(),
         }
     }
 
+    pub fn with_shutter(self, shutter: Shutter) -> OrthographicCamera {
+        OrthographicCamera { shutter, ..self }
+    }
+
+    pub fn look_at(
+        scale: f32,
+        aspect_ratio: f32,
+        eye: Vector3,
+        target: Vector3,
+        up: Vector3,
+    ) -> OrthographicCamera {
+        let (look, up) = look_at_basis(&eye, &target, &up);
+        OrthographicCamera::new(scale, aspect_ratio, eye, look, up)
+    }
+}
+
+// Parallel rays, there is no perspective nor depth of field
+impl CameraModel for OrthographicCamera {
     #[inline(always)]
-    pub fn generate_ray(&self, film_x: f32, film_y: f32) -> Ray {
+    fn generate_ray(
+        &self,
+        film_x: f32,
+        film_y: f32,
+        _lens_sample: &Sample2D,
+        time_sample: f32,
+    ) -> Ray {
         Ray {
             origin: &self.corner + &(&(&self.horizontal * film_x) + &(&self.vertical * film_y)),
             direction: self.look_dir,
+            time: self.shutter.time(time_sample),
         }
     }
 }
 
+impl EquirectangularCamera {
+    pub fn new(position: Vector3, look_dir: Vector3, up_dir: Vector3) -> EquirectangularCamera {
+        EquirectangularCamera {
+            position,
+            look: look_dir,
+            right: up_dir.cross(&look_dir),
+            up: up_dir,
+            shutter: Shutter::default(),
+        }
+    }
+
+    pub fn with_shutter(self, shutter: Shutter) -> EquirectangularCamera {
+        EquirectangularCamera { shutter, ..self }
+    }
+}
+
+impl CameraModel for EquirectangularCamera {
+    fn generate_ray(
+        &self,
+        film_x: f32,
+        film_y: f32,
+        _lens_sample: &Sample2D,
+        time_sample: f32,
+    ) -> Ray {
+        let phi = 2.0 * PI * (film_x - 0.5);
+        let latitude = PI * (film_y - 0.5);
+        let direction = Vector3::new(
+            latitude.cos() * phi.sin(),
+            latitude.sin(),
+            latitude.cos() * phi.cos(),
+        );
+        Ray {
+            origin: self.position,
+            direction: Vector3::to_basis(&direction, &self.look, &self.right, &self.up),
+            time: self.shutter.time(time_sample),
+        }
+    }
+}
+
+impl FisheyeCamera {
+    pub fn new(
+        fov: f32,
+        aspect_ratio: f32,
+        mapping: FisheyeMapping,
+        position: Vector3,
+        look_dir: Vector3,
+        up_dir: Vector3,
+    ) -> FisheyeCamera {
+        FisheyeCamera {
+            position,
+            look: look_dir,
+            right: up_dir.cross(&look_dir),
+            up: up_dir,
+            fov,
+            aspect_ratio,
+            mapping,
+            shutter: Shutter::default(),
+        }
+    }
+
+    pub fn with_shutter(self, shutter: Shutter) -> FisheyeCamera {
+        FisheyeCamera { shutter, ..self }
+    }
+}
+
+impl CameraModel for FisheyeCamera {
+    fn generate_ray(
+        &self,
+        film_x: f32,
+        film_y: f32,
+        _lens_sample: &Sample2D,
+        time_sample: f32,
+    ) -> Ray {
+        let u = 2.0 * film_x - 1.0;
+        let v = (2.0 * film_y - 1.0) / self.aspect_ratio;
+        let radius = f32::sqrt(u * u + v * v);
+        if radius > 1.0 {
+            return Ray {
+                origin: self.position,
+                direction: Vector3::zero_vector(),
+                time: self.shutter.time(time_sample),
+            };
+        }
+
+        let theta = match self.mapping {
+            FisheyeMapping::Equidistant => radius * self.fov * 0.5,
+            FisheyeMapping::Equisolid => 2.0 * f32::asin(radius * f32::sin(self.fov * 0.25)),
+        };
+        let phi = v.atan2(u);
+        let direction = Vector3::new(
+            theta.sin() * phi.cos(),
+            theta.sin() * phi.sin(),
+            theta.cos(),
+        );
+        Ray {
+            origin: self.position,
+            direction: Vector3::to_basis(&direction, &self.look, &self.right, &self.up),
+            time: self.shutter.time(time_sample),
+        }
+    }
+}
+
+impl CubeFace {
+    pub const ALL: [CubeFace; 6] = [
+        CubeFace::PositiveX,
+        CubeFace::NegativeX,
+        CubeFace::PositiveY,
+        CubeFace::NegativeY,
+        CubeFace::PositiveZ,
+        CubeFace::NegativeZ,
+    ];
+
+    // appended to the name of the image of the face
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            CubeFace::PositiveX => "px",
+            CubeFace::NegativeX => "nx",
+            CubeFace::PositiveY => "py",
+            CubeFace::NegativeY => "ny",
+            CubeFace::PositiveZ => "pz",
+            CubeFace::NegativeZ => "nz",
+        }
+    }
+
+    // look and up directions of the camera of the face
+    fn orientation(&self) -> (Vector3, Vector3) {
+        let (look, up) = match self {
+            CubeFace::PositiveX => ((1.0, 0.0, 0.0), (0.0, 1.0, 0.0)),
+            CubeFace::NegativeX => ((-1.0, 0.0, 0.0), (0.0, 1.0, 0.0)),
+            CubeFace::PositiveY => ((0.0, 1.0, 0.0), (0.0, 0.0, -1.0)),
+            CubeFace::NegativeY => ((0.0, -1.0, 0.0), (0.0, 0.0, 1.0)),
+            CubeFace::PositiveZ => ((0.0, 0.0, 1.0), (0.0, 1.0, 0.0)),
+            CubeFace::NegativeZ => ((0.0, 0.0, -1.0), (0.0, 1.0, 0.0)),
+        };
+        (
+            Vector3::new(look.0, look.1, look.2),
+            Vector3::new(up.0, up.1, up.2),
+        )
+    }
+
+    pub fn camera(&self, position: Vector3) -> Camera {
+        let (look, up) = self.orientation();
+        Camera::new(PI / 2.0, 1.0, position, look, up)
+    }
+}
+
 #[cfg(test)]
 mod camera_tests {
-    use super::Camera;
+    use super::{
+        Aperture, Camera, CameraModel, CubeFace, EquirectangularCamera, FisheyeCamera,
+        FisheyeMapping, OrthographicCamera, Shutter, ThinLens,
+    };
+    use crate::tools::{self, Sample2D};
     use crate::vector::Vector3;
+    use std::f32::consts::PI;
 
     #[test]
     fn main_test() {
@@ -116,7 +531,7 @@ mod camera_tests {
         );
         println!("Camera = {:?}", cam);
 
-        let center_ray = cam.generate_ray(0.5, 0.5);
+        let center_ray = cam.generate_ray(0.5, 0.5, &Sample2D { s: 0.5, t: 0.5 }, 0.0);
         println!("Ray = {:?}", center_ray);
         assert!(
             center_ray.direction
@@ -128,6 +543,216 @@ mod camera_tests {
         );
     }
 
+    #[test]
+    fn field_of_view_test() {
+        // the rays on the sides of the film are half the field of view away from the look
+        let cam = Camera::new(
+            60_f32.to_radians(),
+            2.0,
+            Vector3::zero_vector(),
+            Vector3::new(0.0, 0.0, 1.0),
+            Vector3::new(0.0, 1.0, 0.0),
+        );
+        let side_ray = cam.generate_ray(1.0, 0.5, &Sample2D { s: 0.5, t: 0.5 }, 0.0);
+        assert!(tools::equal_error(
+            side_ray.direction.z,
+            30_f32.to_radians().cos()
+        ));
+        assert!(side_ray.direction.x > 0.0);
+        let top_ray = cam.generate_ray(0.5, 1.0, &Sample2D { s: 0.5, t: 0.5 }, 0.0);
+        assert!(tools::equal_error(
+            top_ray.direction.y / top_ray.direction.z,
+            30_f32.to_radians().tan() / 2.0
+        ));
+    }
+
+    #[test]
+    fn look_at_test() {
+        // the up direction does not need to be perpendicular to the target direction
+        let eye = Vector3::new(0.0, 0.0, -5.0);
+        let up = Vector3::new(0.0, 1.0, 1.0);
+        let lens_sample = Sample2D { s: 0.5, t: 0.5 };
+        let camera = Camera::look_at(1.0, 1.0, eye, Vector3::zero_vector(), up);
+        let ray = camera.generate_ray(0.5, 0.5, &lens_sample, 0.0);
+        assert!((&ray.direction - &Vector3::new(0.0, 0.0, 1.0)).length() < 1e-6);
+        assert!(camera.generate_ray(0.5, 1.0, &lens_sample, 0.0).direction.y > 0.0);
+        assert!(camera.generate_ray(1.0, 0.5, &lens_sample, 0.0).direction.x > 0.0);
+
+        // parallel rays from a plane of the given width around the eye
+        let camera = OrthographicCamera::look_at(4.0, 2.0, eye, Vector3::zero_vector(), up);
+        let corner = camera.generate_ray(0.0, 0.0, &lens_sample, 0.0);
+        let center = camera.generate_ray(0.5, 0.5, &lens_sample, 0.0);
+        assert!(corner.direction == center.direction);
+        assert!((&center.origin - &eye).length() < 1e-6);
+        assert!((&corner.origin - &Vector3::new(-2.0, -1.0, -5.0)).length() < 1e-6);
+
+        // an up along the look still gives a valid camera
+        let camera = Camera::look_at(1.0, 1.0, eye, Vector3::zero_vector(), eye);
+        let ray = camera.generate_ray(0.5, 0.5, &lens_sample, 0.0);
+        assert!((&ray.direction - &Vector3::new(0.0, 0.0, 1.0)).length() < 1e-6);
+        let corner = camera.generate_ray(1.0, 1.0, &lens_sample, 0.0).direction;
+        assert!(corner.x.is_finite() && corner.y.is_finite() && corner.z.is_finite());
+        assert!(tools::equal_error(corner.length(), 1.0));
+        assert!(corner.z < 0.9);
+    }
+
+    #[test]
+    fn panoramic_test() {
+        let lens_sample = Sample2D { s: 0.5, t: 0.5 };
+        let close = |a: &Vector3, b: &Vector3| (a - b).length() < 1e-5;
+        let position = Vector3::new(1.0, 2.0, 3.0);
+        let look = Vector3::new(0.0, 0.0, 1.0);
+        let up = Vector3::new(0.0, 1.0, 0.0);
+
+        // longitude along x from behind to behind, latitude along y from below to above
+        let camera = EquirectangularCamera::new(position, look, up);
+        let direction = |x, y| camera.generate_ray(x, y, &lens_sample, 0.0).direction;
+        assert!(close(&direction(0.5, 0.5), &look));
+        assert!(close(&direction(0.75, 0.5), &Vector3::new(1.0, 0.0, 0.0)));
+        assert!(close(&direction(0.0, 0.5), &Vector3::new(0.0, 0.0, -1.0)));
+        assert!(close(&direction(0.3, 1.0), &up));
+        assert!(camera.generate_ray(0.2, 0.7, &lens_sample, 0.0).origin == position);
+
+        // the side of the image circle is at half the field of view, whatever the mapping
+        for mapping in [FisheyeMapping::Equidistant, FisheyeMapping::Equisolid] {
+            let camera = FisheyeCamera::new(PI, 1.0, mapping, position, look, up);
+            let direction = |x, y| camera.generate_ray(x, y, &lens_sample, 0.0).direction;
+            assert!(close(&direction(0.5, 0.5), &look));
+            assert!(close(&direction(1.0, 0.5), &Vector3::new(1.0, 0.0, 0.0)));
+            assert!(close(&direction(0.5, 0.0), &Vector3::new(0.0, -1.0, 0.0)));
+            assert!(direction(1.0, 1.0).is_zero());
+            let theta = f32::acos(direction(0.75, 0.5).z).to_degrees();
+            match mapping {
+                FisheyeMapping::Equidistant => assert!((theta - 45.0).abs() < 1e-3),
+                FisheyeMapping::Equisolid => assert!((theta - 41.41).abs() < 1e-2),
+            }
+        }
+
+        // the faces look along the axes and their corners meet
+        let axes: Vec<Vector3> = CubeFace::ALL
+            .iter()
+            .map(|face| {
+                face.camera(position)
+                    .generate_ray(0.5, 0.5, &lens_sample, 0.0)
+                    .direction
+            })
+            .collect();
+        assert!(close(&axes[0], &Vector3::new(1.0, 0.0, 0.0)));
+        assert!(close(&axes[3], &Vector3::new(0.0, -1.0, 0.0)));
+        assert!(close(&axes[5], &Vector3::new(0.0, 0.0, -1.0)));
+        let corner = Vector3::new(1.0, 1.0, 1.0).unit();
+        let face_corner = |face: CubeFace, x, y| {
+            face.camera(position)
+                .generate_ray(x, y, &lens_sample, 0.0)
+                .direction
+        };
+        assert!(close(&face_corner(CubeFace::PositiveX, 0.0, 1.0), &corner));
+        assert!(close(&face_corner(CubeFace::PositiveY, 1.0, 0.0), &corner));
+        assert!(close(&face_corner(CubeFace::PositiveZ, 1.0, 1.0), &corner));
+    }
+
+    #[test]
+    fn thin_lens_test() {
+        let apertures = [
+            Aperture::Circle,
+            Aperture::Polygon {
+                blades: 6,
+                rotation: 0.3,
+            },
+            // only the right half of the mask is open
+            Aperture::from_mask(2, 2, &[0.0, 1.0, 0.0, 1.0]),
+        ];
+        for aperture in apertures {
+            let polygon = match aperture {
+                Aperture::Polygon { blades, rotation } => Some((blades, rotation)),
+                _ => None,
+            };
+            let is_mask = matches!(aperture, Aperture::Image(_));
+            let camera = Camera::with_lens(
+                std::f32::consts::PI / 2.0,
+                1.0,
+                Vector3::new(1.0, 2.0, 3.0),
+                Vector3::new(0.0, 0.0, 1.0),
+                Vector3::new(0.0, 1.0, 0.0),
+                ThinLens {
+                    aperture_radius: 0.5,
+                    focus_distance: 4.0,
+                    aperture,
+                },
+            );
+            let pinhole = Camera::new(
+                std::f32::consts::PI / 2.0,
+                1.0,
+                Vector3::new(1.0, 2.0, 3.0),
+                Vector3::new(0.0, 0.0, 1.0),
+                Vector3::new(0.0, 1.0, 0.0),
+            );
+            let pinhole_ray = pinhole.generate_ray(0.3, 0.8, &Sample2D { s: 0.1, t: 0.9 }, 0.0);
+            let focus_point =
+                &pinhole_ray.origin + &(&pinhole_ray.direction * (4.0 / pinhole_ray.direction.z));
+            for i in 0..16 {
+                for j in 0..16 {
+                    let lens_sample = Sample2D {
+                        s: (i as f32 + 0.5) / 16.0,
+                        t: (j as f32 + 0.5) / 16.0,
+                    };
+                    let ray = camera.generate_ray(0.3, 0.8, &lens_sample, 0.0);
+                    // the rays start on the lens and all meet the pinhole ray on the plane of focus
+                    assert!(tools::equal_error(ray.origin.z, 3.0));
+                    let lens_x = (ray.origin.x - 1.0) / 0.5;
+                    let lens_y = (ray.origin.y - 2.0) / 0.5;
+                    let in_focus = &ray.origin + &(&ray.direction * (4.0 / ray.direction.z));
+                    assert!((&in_focus - &focus_point).length() < 1e-4);
+                    if is_mask {
+                        assert!(lens_x > 0.0 && lens_x <= 1.0 && lens_y.abs() <= 1.0);
+                        continue;
+                    }
+                    assert!(lens_x * lens_x + lens_y * lens_y <= 1.0 + 1e-5);
+                    // inside every side of the hexagon
+                    if let Some((blades, rotation)) = polygon {
+                        let apothem = (std::f32::consts::PI / blades as f32).cos();
+                        for blade in 0..blades {
+                            let angle = rotation
+                                + (blade as f32 + 0.5) * 2.0 * std::f32::consts::PI / blades as f32;
+                            let distance = lens_x * angle.cos() + lens_y * angle.sin();
+                            assert!(distance <= apothem + 1e-5);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn shutter_test() {
+        // the time samples are spread over the interval the shutter is open, for every camera
+        let shutter = Shutter {
+            open: 0.5,
+            close: 0.75,
+        };
+        let position = Vector3::zero_vector();
+        let look = Vector3::new(0.0, 0.0, 1.0);
+        let up = Vector3::new(0.0, 1.0, 0.0);
+        let cameras: [Box<dyn CameraModel>; 5] = [
+            Box::new(Camera::new(PI / 2.0, 1.0, position, look, up)),
+            Box::new(Camera::new(PI / 2.0, 1.0, position, look, up).with_shutter(shutter)),
+            Box::new(OrthographicCamera::new(1.0, 1.0, position, look, up).with_shutter(shutter)),
+            Box::new(EquirectangularCamera::new(position, look, up).with_shutter(shutter)),
+            Box::new(
+                FisheyeCamera::new(PI, 1.0, FisheyeMapping::Equidistant, position, look, up)
+                    .with_shutter(shutter),
+            ),
+        ];
+        let lens_sample = Sample2D { s: 0.5, t: 0.5 };
+        for (i, camera) in cameras.iter().enumerate() {
+            let (open, close) = if i == 0 { (0.0, 1.0) } else { (0.5, 0.75) };
+            for (sample, time) in [(0.0, open), (0.5, (open + close) * 0.5), (1.0, close)] {
+                let ray = camera.generate_ray(0.5, 0.5, &lens_sample, sample);
+                assert!(tools::equal_error(ray.time, time));
+            }
+        }
+    }
+
     #[test]
     fn direction_test() {
         let width = 800;
@@ -160,7 +785,7 @@ mod camera_tests {
         for (x, y, image_pixel) in image_buffer.enumerate_pixels_mut() {
             let film_x = (x as f32 + 0.5) / (width as f32);
             let film_y = (y as f32 + 0.5) / (height as f32);
-            let ray = camera.generate_ray(film_x, film_y);
+            let ray = camera.generate_ray(film_x, film_y, &Sample2D { s: 0.5, t: 0.5 }, 0.0);
             let x = (ray.direction.x + 1.0) * 0.5;
             let y = (ray.direction.y + 1.0) * 0.5;
             let z = (ray.direction.z + 1.0) * 0.5;
@@ -0,0 +1,632 @@
+use crate::light::{self, DirectionalLight, EnvironmentLight, Light, LightSample};
+use crate::tools::{Distribution2D, Sample2D};
+use crate::vector::Vector3;
+use image::ImageError;
+use std::f32::consts::PI;
+use std::path::Path;
+
+// Environment light from an equirectangular image: columns go around the up axis y and rows from
+// the zenith to the nadir. Directions are importance sampled proportionally to the luminance of
+// the pixels, so that a small bright sun gets most of the shadow rays
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vector3>,
+    intensity: f32,
+    rotation: f32, // around the up axis, in radians
+    distribution: Distribution2D,
+}
+
+// Daylight sky of Preetham et al., "A Practical Analytic Model for Daylight", for a sun above the
+// horizon. Radiances are in kcd/m^2 scaled by the intensity. The ground below the horizon is a
+// diffuse plane lit by the sky and the sun. The sun disk is part of the sky, seen by every ray and
+// sampled along with it. A sun without a disk cannot be hit and is a separate light, see sun
+pub struct PreethamSky {
+    sun_direction: Vector3, // towards the sun
+    turbidity: f32,         // haziness, from 2 for a very clear sky to 10 for haze
+    intensity: f32,
+    zenith: Vector3,      // luminance Y and chromaticity x, y at the zenith
+    perez: [[f32; 5]; 3], // distribution coefficients of Y, x and y
+    ground_radiance: Vector3,
+    sun_cos_half_angle: f32,
+    sun_radiance: Vector3, // zero for a sun without a disk
+    sun_probability: f32,  // of sampling the sun disk rather than the table
+    distribution: Distribution2D,
+}
+
+fn luminance(color: &Vector3) -> f32 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+// rotation around the up axis
+fn rotate_y(vec: &Vector3, angle: f32) -> Vector3 {
+    let (sin, cos) = angle.sin_cos();
+    Vector3::new(cos * vec.x + sin * vec.z, vec.y, cos * vec.z - sin * vec.x)
+}
+
+// equirectangular coordinates in [0, 1)^2 of a unit direction
+fn equirectangular_point(direction: &Vector3) -> Sample2D {
+    let theta = f32::acos(direction.y.clamp(-1.0, 1.0));
+    let mut phi = f32::atan2(direction.z, direction.x);
+    if phi < 0.0 {
+        phi += 2.0 * PI;
+    }
+    Sample2D {
+        s: f32::min(phi / (2.0 * PI), 1.0 - f32::EPSILON),
+        t: f32::min(theta / PI, 1.0 - f32::EPSILON),
+    }
+}
+
+fn equirectangular_direction(point: &Sample2D) -> Vector3 {
+    let theta = point.t * PI;
+    let phi = point.s * 2.0 * PI;
+    let sin_theta = theta.sin();
+    Vector3::new(sin_theta * phi.cos(), theta.cos(), sin_theta * phi.sin())
+}
+
+// from the pdf over the equirectangular image to the one over solid angle
+fn equirectangular_pdf(point: &Sample2D, image_pdf: f32) -> f32 {
+    let sin_theta = f32::sin(point.t * PI);
+    if sin_theta <= 0.0 {
+        return 0.0;
+    }
+    image_pdf / (2.0 * PI * PI * sin_theta)
+}
+
+// distribution over the equirectangular image following the luminance of the radiance at the
+// pixel centers. Pixels near the poles cover a smaller solid angle
+fn luminance_distribution<F: Fn(usize, usize) -> Vector3>(
+    width: usize,
+    height: usize,
+    radiance: F,
+) -> Distribution2D {
+    let mut weights = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let sin_theta = f32::sin(PI * (y as f32 + 0.5) / height as f32);
+        for x in 0..width {
+            weights.push(luminance(&radiance(x, y)).max(0.0) * sin_theta);
+        }
+    }
+    Distribution2D::new(&weights, width, height)
+}
+
+impl EnvironmentMap {
+    // pixels row by row from the top of the image
+    pub fn new(
+        width: usize,
+        height: usize,
+        pixels: Vec<Vector3>,
+        intensity: f32,
+        rotation: f32,
+    ) -> EnvironmentMap {
+        debug_assert!(pixels.len() == width * height);
+        let distribution = luminance_distribution(width, height, |x, y| pixels[y * width + x]);
+        EnvironmentMap {
+            width,
+            height,
+            pixels,
+            intensity,
+            rotation,
+            distribution,
+        }
+    }
+
+    // radiance files are decoded directly, the generic image reader would convert them to 8-bit
+    pub fn load<P: AsRef<Path>>(
+        path: P,
+        intensity: f32,
+        rotation: f32,
+    ) -> Result<EnvironmentMap, ImageError> {
+        let path = path.as_ref();
+        let is_radiance_hdr = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("hdr"));
+        let (width, height, pixels) = if is_radiance_hdr {
+            let file = std::io::BufReader::new(std::fs::File::open(path)?);
+            let decoder = image::codecs::hdr::HdrDecoder::new(file)?;
+            let metadata = decoder.metadata();
+            let pixels = decoder
+                .read_image_hdr()?
+                .iter()
+                .map(|pixel| Vector3::new(pixel[0], pixel[1], pixel[2]))
+                .collect();
+            (metadata.width, metadata.height, pixels)
+        } else {
+            let image = image::open(path)?.into_rgb32f();
+            let pixels = image
+                .pixels()
+                .map(|pixel| Vector3::new(pixel[0], pixel[1], pixel[2]))
+                .collect();
+            (image.width(), image.height(), pixels)
+        };
+        Ok(EnvironmentMap::new(
+            width as usize,
+            height as usize,
+            pixels,
+            intensity,
+            rotation,
+        ))
+    }
+
+    fn direction_to_image(&self, direction: &Vector3) -> Sample2D {
+        equirectangular_point(&rotate_y(direction, -self.rotation))
+    }
+
+    fn image_to_direction(&self, point: &Sample2D) -> Vector3 {
+        rotate_y(&equirectangular_direction(point), self.rotation)
+    }
+
+    fn lookup(&self, point: &Sample2D) -> Vector3 {
+        let x = usize::min((point.s * self.width as f32) as usize, self.width - 1);
+        let y = usize::min((point.t * self.height as f32) as usize, self.height - 1);
+        &self.pixels[y * self.width + x] * self.intensity
+    }
+}
+
+impl Light for EnvironmentMap {
+    fn sample(&self, _reference_point: &Vector3, sample: &Sample2D) -> LightSample {
+        let (point, image_pdf) = self.distribution.sample(sample);
+        let pdf = equirectangular_pdf(&point, image_pdf);
+        if pdf <= 0.0 {
+            return LightSample::invalid_sample();
+        }
+        LightSample {
+            direction: self.image_to_direction(&point),
+            distance: f32::INFINITY,
+            radiance: self.lookup(&point),
+            pdf,
+            is_hittable: true,
+        }
+    }
+}
+
+impl EnvironmentLight for EnvironmentMap {
+    fn radiance(&self, direction: &Vector3) -> Vector3 {
+        self.lookup(&self.direction_to_image(direction))
+    }
+
+    fn pdf(&self, direction: &Vector3) -> f32 {
+        let point = self.direction_to_image(direction);
+        equirectangular_pdf(&point, self.distribution.pdf(&point))
+    }
+}
+
+// resolution of the table importance sampling the sky
+const SKY_TABLE_WIDTH: usize = 128;
+const SKY_TABLE_HEIGHT: usize = 64;
+
+// illuminance of the sun outside of the atmosphere, in klux
+const SOLAR_ILLUMINANCE: f32 = 128.0;
+
+// Perez et al. all-weather luminance distribution, theta from the zenith and gamma from the sun
+fn perez(coefficients: &[f32; 5], cos_theta: f32, gamma: f32) -> f32 {
+    let [a, b, c, d, e] = *coefficients;
+    let cos_gamma = gamma.cos();
+    (1.0 + a * f32::exp(b / cos_theta))
+        * (1.0 + c * f32::exp(d * gamma) + e * cos_gamma * cos_gamma)
+}
+
+// CIE xyY to linear sRGB
+fn xyy_to_rgb(luminance: f32, x: f32, y: f32) -> Vector3 {
+    if y <= 0.0 {
+        return Vector3::zero_vector();
+    }
+    let cie_x = x * luminance / y;
+    let cie_z = (1.0 - x - y) * luminance / y;
+    Vector3 {
+        x: f32::max(0.0, 3.2406 * cie_x - 1.5372 * luminance - 0.4986 * cie_z),
+        y: f32::max(0.0, -0.9689 * cie_x + 1.8758 * luminance + 0.0415 * cie_z),
+        z: f32::max(0.0, 0.0557 * cie_x - 0.2040 * luminance + 1.0570 * cie_z),
+    }
+}
+
+impl PreethamSky {
+    // the angular diameter of the sun is in radians
+    pub fn new(
+        sun_direction: Vector3,
+        turbidity: f32,
+        ground_albedo: &Vector3,
+        intensity: f32,
+        sun_diameter: f32,
+    ) -> PreethamSky {
+        let mut sun_direction = sun_direction.unit();
+        // the model does not hold for a sun below the horizon
+        if sun_direction.y < 0.0 {
+            sun_direction = Vector3::new(sun_direction.x, 0.0, sun_direction.z).unit();
+        }
+        let t = turbidity;
+        let theta_s = f32::acos(sun_direction.y.clamp(-1.0, 1.0));
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let theta = [theta_s * theta_s * theta_s, theta_s * theta_s, theta_s, 1.0];
+        let chromaticity = |m: [[f32; 4]; 3]| {
+            let turbidity_terms = [t * t, t, 1.0];
+            let mut value = 0.0;
+            for (row, turbidity_term) in m.iter().zip(turbidity_terms) {
+                for (coefficient, theta_term) in row.iter().zip(theta) {
+                    value += turbidity_term * coefficient * theta_term;
+                }
+            }
+            value
+        };
+        let zenith_x = chromaticity([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let zenith_y = chromaticity([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        let mut sky = PreethamSky {
+            sun_direction,
+            turbidity,
+            intensity,
+            zenith: Vector3::new(zenith_luminance, zenith_x, zenith_y),
+            perez,
+            ground_radiance: Vector3::zero_vector(),
+            sun_cos_half_angle: f32::cos(sun_diameter * 0.5),
+            sun_radiance: Vector3::zero_vector(),
+            sun_probability: 0.0,
+            distribution: Distribution2D::new(&[1.0], 1, 1),
+        };
+
+        // the ground reflects the irradiance of the sky and the sun on a horizontal plane
+        let mut sky_irradiance = Vector3::zero_vector();
+        let mut sky_power = 0.0;
+        for y in 0..SKY_TABLE_HEIGHT / 2 {
+            for x in 0..SKY_TABLE_WIDTH {
+                let direction = equirectangular_direction(&Sample2D {
+                    s: (x as f32 + 0.5) / SKY_TABLE_WIDTH as f32,
+                    t: (y as f32 + 0.5) / SKY_TABLE_HEIGHT as f32,
+                });
+                let solid_angle = 2.0 * PI * PI / (SKY_TABLE_WIDTH * SKY_TABLE_HEIGHT) as f32
+                    * f32::sqrt(1.0 - direction.y * direction.y);
+                let radiance = sky.sky_radiance(&direction);
+                sky_irradiance += &(&radiance * (direction.y * solid_angle));
+                sky_power += luminance(&radiance) * solid_angle;
+            }
+        }
+        let sun_irradiance = sky.sun_irradiance();
+        let ground_irradiance = &sky_irradiance + &(&sun_irradiance * sun_direction.y);
+        sky.ground_radiance = &(ground_albedo * &ground_irradiance) * (1.0 / PI);
+        sky_power += luminance(&sky.ground_radiance) * 2.0 * PI;
+
+        // the disk is sampled in proportion to its share of the light of the whole sky
+        if sky.sun_cos_half_angle < 1.0 {
+            sky.sun_radiance = &sun_irradiance * light::disk_radiance_scale(sky.sun_cos_half_angle);
+            let sun_power =
+                luminance(&sky.sun_radiance) * light::cone_solid_angle(sky.sun_cos_half_angle);
+            if sun_power > 0.0 {
+                sky.sun_probability = sun_power / (sun_power + sky_power);
+            }
+        }
+
+        sky.distribution = luminance_distribution(SKY_TABLE_WIDTH, SKY_TABLE_HEIGHT, |x, y| {
+            sky.radiance_without_sun(&equirectangular_direction(&Sample2D {
+                s: (x as f32 + 0.5) / SKY_TABLE_WIDTH as f32,
+                t: (y as f32 + 0.5) / SKY_TABLE_HEIGHT as f32,
+            }))
+        });
+        sky
+    }
+
+    fn radiance_without_sun(&self, direction: &Vector3) -> Vector3 {
+        if direction.y < 0.0 {
+            return self.ground_radiance;
+        }
+        self.sky_radiance(direction)
+    }
+
+    fn is_in_sun(&self, direction: &Vector3) -> bool {
+        direction.dot(&self.sun_direction) >= self.sun_cos_half_angle
+    }
+
+    // radiance of the sky above the horizon
+    fn sky_radiance(&self, direction: &Vector3) -> Vector3 {
+        // the formula diverges at the horizon
+        let cos_theta = f32::max(direction.y, 0.01);
+        let cos_theta_s = self.sun_direction.y;
+        let gamma = f32::acos(direction.dot(&self.sun_direction).clamp(-1.0, 1.0));
+        let theta_s = f32::acos(cos_theta_s.clamp(-1.0, 1.0));
+        let value = |index: usize, zenith: f32| {
+            let coefficients = &self.perez[index];
+            zenith * perez(coefficients, cos_theta, gamma) / perez(coefficients, 1.0, theta_s)
+        };
+        let radiance = xyy_to_rgb(
+            value(0, self.zenith.x),
+            value(1, self.zenith.y),
+            value(2, self.zenith.z),
+        );
+        &radiance * self.intensity
+    }
+
+    // irradiance of the sun on a surface facing it, dimmed and reddened by the atmosphere with
+    // the rayleigh and aerosol terms of Preetham's appendix
+    pub fn sun_irradiance(&self) -> Vector3 {
+        let theta_s = f32::acos(self.sun_direction.y.clamp(0.0, 1.0));
+        let relative_optical_mass =
+            1.0 / (theta_s.cos() + 0.15 * f32::powf(93.885 - theta_s.to_degrees(), -1.253));
+        let beta = 0.04608366 * self.turbidity - 0.04586026;
+        // wavelengths of the red, green and blue primaries in micrometers
+        let transmittance = |wavelength: f32| {
+            let rayleigh = -0.008735 * wavelength.powf(-4.08);
+            let aerosol = -beta * wavelength.powf(-1.3);
+            f32::exp((rayleigh + aerosol) * relative_optical_mass)
+        };
+        let transmittance = Vector3 {
+            x: transmittance(0.65),
+            y: transmittance(0.57),
+            z: transmittance(0.475),
+        };
+        &transmittance * (SOLAR_ILLUMINANCE * self.intensity)
+    }
+
+    // the sun matching a sky without a sun disk, as a directional light
+    pub fn sun(&self) -> Option<DirectionalLight> {
+        if self.sun_cos_half_angle < 1.0 {
+            return None;
+        }
+        Some(DirectionalLight::new(
+            -&self.sun_direction,
+            &self.sun_irradiance(),
+            1.0,
+            0.0,
+        ))
+    }
+}
+
+impl Light for PreethamSky {
+    fn sample(&self, _reference_point: &Vector3, sample: &Sample2D) -> LightSample {
+        let direction = if sample.s < self.sun_probability {
+            let sample = Sample2D {
+                s: sample.s / self.sun_probability,
+                t: sample.t,
+            };
+            light::sample_cone(&self.sun_direction, self.sun_cos_half_angle, &sample)
+        } else {
+            let sample = Sample2D {
+                s: (sample.s - self.sun_probability) / (1.0 - self.sun_probability),
+                t: sample.t,
+            };
+            equirectangular_direction(&self.distribution.sample(&sample).0)
+        };
+        let pdf = self.pdf(&direction);
+        if pdf <= 0.0 {
+            return LightSample::invalid_sample();
+        }
+        LightSample {
+            direction,
+            distance: f32::INFINITY,
+            radiance: self.radiance(&direction),
+            pdf,
+            is_hittable: true,
+        }
+    }
+}
+
+impl EnvironmentLight for PreethamSky {
+    fn radiance(&self, direction: &Vector3) -> Vector3 {
+        let radiance = self.radiance_without_sun(direction);
+        if self.is_in_sun(direction) {
+            return &radiance + &self.sun_radiance;
+        }
+        radiance
+    }
+
+    fn pdf(&self, direction: &Vector3) -> f32 {
+        let point = equirectangular_point(direction);
+        let table_pdf = equirectangular_pdf(&point, self.distribution.pdf(&point));
+        let mut pdf = (1.0 - self.sun_probability) * table_pdf;
+        if self.sun_probability > 0.0 && self.is_in_sun(direction) {
+            pdf += self.sun_probability / light::cone_solid_angle(self.sun_cos_half_angle);
+        }
+        pdf
+    }
+}
+
+#[cfg(test)]
+mod environment_tests {
+    use super::{EnvironmentMap, PreethamSky};
+    use crate::film::Film;
+    use crate::light::{EnvironmentLight, Light};
+    use crate::tools::{self, Sample2D};
+    use crate::vector::Vector3;
+    use std::f32::consts::PI;
+
+    #[test]
+    fn environment_map_test() {
+        // a dark sky with a single bright pixel above the horizon
+        let (width, height) = (32, 16);
+        let mut pixels = vec![Vector3::new(0.1, 0.1, 0.1); width * height];
+        pixels[4 * width + 8] = Vector3::new(1000.0, 900.0, 800.0);
+        let environment = EnvironmentMap::new(width, height, pixels, 2.0, 0.5);
+
+        // directions map back to the pixel they come from
+        for (s, t) in [(0.3, 0.2), (0.9, 0.7), (0.01, 0.5)] {
+            let point = Sample2D { s, t };
+            let direction = environment.image_to_direction(&point);
+            assert!(tools::equal_error(direction.length(), 1.0));
+            let back = environment.direction_to_image(&direction);
+            assert!((back.s - s).abs() < 1e-4 && (back.t - t).abs() < 1e-4);
+        }
+
+        // the pdf integrates to one over the sphere, the steps follow the pixels of the image
+        const STEPS: usize = 10;
+        let (theta_steps, phi_steps) = (height * STEPS, width * STEPS);
+        let mut pdf_integral = 0.0;
+        for i in 0..theta_steps {
+            let theta = (i as f32 + 0.5) / theta_steps as f32 * PI;
+            let mut row_integral = 0.0;
+            for j in 0..phi_steps {
+                let phi = (j as f32 + 0.5) / phi_steps as f32 * 2.0 * PI - 0.5;
+                let direction = Vector3::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                row_integral += environment.pdf(&direction);
+            }
+            pdf_integral += row_integral * theta.sin();
+        }
+        pdf_integral *= 2.0 * PI * PI / (theta_steps * phi_steps) as f32;
+        assert!((pdf_integral - 1.0).abs() < 0.01, "{}", pdf_integral);
+
+        // most samples go to the bright pixel, and agree with the evaluation
+        let mut bright_samples = 0;
+        for i in 0..1000 {
+            let sample = Sample2D {
+                s: (i as f32 * 0.618_034) % 1.0,
+                t: (i as f32 + 0.5) / 1000.0,
+            };
+            let light_sample = environment.sample(&Vector3::zero_vector(), &sample);
+            assert!(light_sample.is_hittable && light_sample.pdf > 0.0);
+            let radiance = environment.radiance(&light_sample.direction);
+            assert!(radiance == light_sample.radiance);
+            let pdf = environment.pdf(&light_sample.direction);
+            assert!((pdf - light_sample.pdf).abs() < 1e-3 * pdf);
+            if radiance.x > 1000.0 {
+                bright_samples += 1;
+            }
+        }
+        assert!(bright_samples > 900);
+    }
+
+    #[test]
+    fn load_test() {
+        // a left half twice as bright as the right one
+        let mut film = Film::new(4, 2);
+        for x in 0..4 {
+            for y in 0..2 {
+                let radiance = if x < 2 { 2.0 } else { 1.0 };
+                let color = Vector3::new(radiance, radiance, radiance);
+                film.add_sample(x as f32 + 0.5, y as f32 + 0.5, &color);
+            }
+        }
+        for location in ["environment_load_test.hdr", "environment_load_test.exr"] {
+            film.save_image(location);
+            let environment = EnvironmentMap::load(location, 0.5, 0.0).unwrap();
+            assert!(environment.width == 4 && environment.height == 2);
+            let left = environment.radiance(&Vector3::new(1.0, 0.0, 0.1).unit());
+            let right = environment.radiance(&Vector3::new(-1.0, 0.0, -0.1).unit());
+            assert!((left.x - 1.0).abs() < 0.01 && (right.x - 0.5).abs() < 0.01);
+        }
+        assert!(EnvironmentMap::load("environment_missing.exr", 1.0, 0.0).is_err());
+    }
+
+    #[test]
+    fn preetham_sky_test() {
+        let albedo = Vector3::new(0.2, 0.2, 0.2);
+        let sun_direction = Vector3::new(0.5, 0.6, 0.3).unit();
+        let sky = PreethamSky::new(sun_direction, 3.0, &albedo, 1.0, 0.0);
+
+        // the zenith gets the luminance of the model, the sky is brighter around the sun and blue
+        // away from it
+        let zenith = sky.radiance(&Vector3::new(0.0, 1.0, 0.0));
+        let zenith_luminance = 0.2126 * zenith.x + 0.7152 * zenith.y + 0.0722 * zenith.z;
+        assert!((zenith_luminance - sky.zenith.x).abs() < 0.01 * sky.zenith.x);
+        let near_sun = sky.radiance(&Vector3::new(0.5, 0.65, 0.3).unit());
+        let away = sky.radiance(&Vector3::new(-0.5, 0.6, -0.3).unit());
+        assert!(near_sun.y > away.y);
+        assert!(away.z > away.x);
+        let ground = sky.radiance(&Vector3::new(0.3, -0.5, 0.1).unit());
+        assert!(ground == sky.radiance(&Vector3::new(0.0, -1.0, 0.0)) && ground.x > 0.0);
+
+        // a wide sun disk is seen in the sky, and gives the irradiance of the sun to a surface
+        // facing it
+        let sun_sky = PreethamSky::new(sun_direction, 3.0, &albedo, 1.0, 10_f32.to_radians());
+        assert!(sky.sun().is_some() && sun_sky.sun().is_none());
+        let sun_radiance = sun_sky.radiance(&sun_direction);
+        assert!(sun_radiance.y > 100.0 * near_sun.y);
+        let outside_disk = Vector3::new(0.5, 0.8, 0.3).unit();
+        assert!(sun_sky.radiance(&outside_disk) == sky.radiance(&outside_disk));
+        let mut sun_irradiance = 0.0;
+        for i in 0..100 {
+            for j in 0..100 {
+                let sample = Sample2D {
+                    s: (i as f32 + 0.5) / 100.0 * sun_sky.sun_probability,
+                    t: (j as f32 + 0.5) / 100.0,
+                };
+                let light_sample = sun_sky.sample(&Vector3::zero_vector(), &sample);
+                let cos_theta = light_sample.direction.dot(&sun_direction);
+                let radiance = &light_sample.radiance - &sky.radiance(&light_sample.direction);
+                sun_irradiance +=
+                    radiance.y * cos_theta * sun_sky.sun_probability / light_sample.pdf / 10000.0;
+            }
+        }
+        let expected = sun_sky.sun_irradiance().y;
+        assert!(
+            (sun_irradiance - expected).abs() < 0.02 * expected,
+            "{} {}",
+            sun_irradiance,
+            expected
+        );
+
+        // sampling agrees with the pdf, which integrates to one
+        for sky in [&sky, &sun_sky] {
+            for i in 0..100 {
+                let sample = Sample2D {
+                    s: (i as f32 * 0.618_034) % 1.0,
+                    t: (i as f32 + 0.5) / 100.0,
+                };
+                let light_sample = sky.sample(&Vector3::zero_vector(), &sample);
+                let pdf = sky.pdf(&light_sample.direction);
+                assert!((pdf - light_sample.pdf).abs() < 1e-3 * pdf);
+                assert!(light_sample.radiance == sky.radiance(&light_sample.direction));
+            }
+            const STEPS: usize = 1024;
+            let mut pdf_integral = 0.0;
+            for i in 0..STEPS / 2 {
+                let theta = (i as f32 + 0.5) / (STEPS / 2) as f32 * PI;
+                let mut row_integral = 0.0;
+                for j in 0..STEPS {
+                    let phi = (j as f32 + 0.5) / STEPS as f32 * 2.0 * PI;
+                    let direction = Vector3::new(
+                        theta.sin() * phi.cos(),
+                        theta.cos(),
+                        theta.sin() * phi.sin(),
+                    );
+                    row_integral += sky.pdf(&direction);
+                }
+                pdf_integral += row_integral * theta.sin();
+            }
+            pdf_integral *= 4.0 * PI * PI / (STEPS * STEPS) as f32;
+            assert!((pdf_integral - 1.0).abs() < 0.01, "{}", pdf_integral);
+        }
+
+        // the low sun is dimmer and redder
+        let sun = sky.sun_irradiance();
+        let low_sun =
+            PreethamSky::new(Vector3::new(1.0, 0.1, 0.0), 3.0, &albedo, 1.0, 0.0).sun_irradiance();
+        assert!(low_sun.y < sun.y);
+        assert!(low_sun.x / low_sun.z > sun.x / sun.z);
+        assert!(sun.x < 128.0 && sun.x > 50.0);
+    }
+}
@@ -1,12 +1,63 @@
+use crate::filter::Filter;
+use crate::tonemap::ToneMapper;
 use crate::vector::Vector3;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
 
 #[derive(Debug, Clone, Copy)]
 pub struct FilmSample {
-    pub num_of_samples: u32,
-    pub accumulated_radiance: Vector3,
+    pub accumulated_weight: f32,
+    pub accumulated_radiance: Vector3, // sum of the samples weighted by the filter
+}
+
+// File format of a saved film, the float formats keep the linear radiance unclamped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Ldr, // 8-bit sRGB, the container (png, jpg, ...) is deduced from the extension
+    OpenExr,
+    RadianceHdr,
+    Pfm,
+}
+
+impl ImageFormat {
+    // unknown extensions are left to the image crate as 8-bit images
+    pub fn from_path(location: &str) -> ImageFormat {
+        let extension = Path::new(location)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("exr") => ImageFormat::OpenExr,
+            Some("hdr") => ImageFormat::RadianceHdr,
+            Some("pfm") => ImageFormat::Pfm,
+            _ => ImageFormat::Ldr,
+        }
+    }
 }
 
 pub struct Film {
+    width: u32,
+    height: u32,
+    filter: Filter,
+    buffer: PixelBuffer,
+}
+
+// A rectangular part of the film rendered independently and merged back with Film::merge_tile.
+// Its buffer has a border as wide as the filter radius catching the samples splatted outside
+pub struct FilmTile {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+    filter: Filter,
+    buffer: PixelBuffer,
+}
+
+// Pixels [x0, x0 + width) x [y0, y0 + height) of the film
+struct PixelBuffer {
+    x0: u32,
+    y0: u32,
     width: u32,
     height: u32,
     pixels: Vec<FilmSample>,
@@ -32,14 +83,16 @@ pub fn to_srgb(linear_color: &Vector3) -> Vector3 {
     }
 }
 
-impl Film {
-    pub fn new(width: u32, height: u32) -> Film {
-        Film {
+impl PixelBuffer {
+    fn new(x0: u32, y0: u32, width: u32, height: u32) -> PixelBuffer {
+        PixelBuffer {
+            x0,
+            y0,
             width,
             height,
             pixels: vec![
                 FilmSample {
-                    num_of_samples: 0,
+                    accumulated_weight: 0.0,
                     accumulated_radiance: Vector3 {
                         x: 0.0,
                         y: 0.0,
@@ -53,28 +106,146 @@ impl Film {
 
     #[inline(always)]
     fn index(&self, x: u32, y: u32) -> usize {
-        debug_assert!(x < self.width && y < self.height);
-        (x * self.height + y) as usize
+        debug_assert!(x >= self.x0 && x < self.x0 + self.width);
+        debug_assert!(y >= self.y0 && y < self.y0 + self.height);
+        ((x - self.x0) * self.height + (y - self.y0)) as usize
+    }
+
+    fn pixel(&self, x: u32, y: u32) -> &FilmSample {
+        &self.pixels[self.index(x, y)]
     }
 
+    // adds the sample to every pixel of the buffer whose center is within the filter radius
+    fn splat(&mut self, filter: &Filter, film_x: f32, film_y: f32, radiance: &Vector3) {
+        let radius = filter.radius();
+        // pixel centers are at half integers
+        let x_min = i64::max((film_x - 0.5 - radius).ceil() as i64, self.x0 as i64);
+        let x_max = i64::min(
+            (film_x - 0.5 + radius).floor() as i64,
+            (self.x0 + self.width) as i64 - 1,
+        );
+        let y_min = i64::max((film_y - 0.5 - radius).ceil() as i64, self.y0 as i64);
+        let y_max = i64::min(
+            (film_y - 0.5 + radius).floor() as i64,
+            (self.y0 + self.height) as i64 - 1,
+        );
+        for x in x_min..=x_max {
+            let weight_x = filter.evaluate_1d(film_x - (x as f32 + 0.5));
+            if weight_x == 0.0 {
+                continue;
+            }
+            for y in y_min..=y_max {
+                let weight = weight_x * filter.evaluate_1d(film_y - (y as f32 + 0.5));
+                if weight == 0.0 {
+                    continue;
+                }
+                let pixel_index = self.index(x as u32, y as u32);
+                let pixel = &mut self.pixels[pixel_index];
+                pixel.accumulated_radiance += &(radiance * weight);
+                pixel.accumulated_weight += weight;
+            }
+        }
+    }
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32) -> Film {
+        Film::with_filter(width, height, Filter::default())
+    }
+
+    pub fn with_filter(width: u32, height: u32, filter: Filter) -> Film {
+        Film {
+            width,
+            height,
+            filter,
+            buffer: PixelBuffer::new(0, 0, width, height),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    // tile sampling the pixels [x0, x1) x [y0, y1)
+    pub fn create_tile(&self, x0: u32, y0: u32, x1: u32, y1: u32) -> FilmTile {
+        debug_assert!(x0 < x1 && x1 <= self.width && y0 < y1 && y1 <= self.height);
+        let border = f32::max(0.0, (self.filter.radius() - 0.5).ceil()) as u32;
+        let buffer_x0 = x0.saturating_sub(border);
+        let buffer_y0 = y0.saturating_sub(border);
+        let buffer_x1 = u32::min(x1 + border, self.width);
+        let buffer_y1 = u32::min(y1 + border, self.height);
+        FilmTile {
+            x0,
+            y0,
+            x1,
+            y1,
+            filter: self.filter,
+            buffer: PixelBuffer::new(
+                buffer_x0,
+                buffer_y0,
+                buffer_x1 - buffer_x0,
+                buffer_y1 - buffer_y0,
+            ),
+        }
+    }
+
+    // tiles overlap through their borders, merging them in a fixed order gives reproducible films
+    pub fn merge_tile(&mut self, tile: &FilmTile) {
+        let tile_buffer = &tile.buffer;
+        for x in tile_buffer.x0..tile_buffer.x0 + tile_buffer.width {
+            for y in tile_buffer.y0..tile_buffer.y0 + tile_buffer.height {
+                let tile_pixel = tile_buffer.pixel(x, y);
+                let pixel_index = self.buffer.index(x, y);
+                let pixel = &mut self.buffer.pixels[pixel_index];
+                pixel.accumulated_radiance += &tile_pixel.accumulated_radiance;
+                pixel.accumulated_weight += tile_pixel.accumulated_weight;
+            }
+        }
+    }
+
+    pub fn pixel_radiance(&self, x: u32, y: u32) -> Vector3 {
+        let film_pixel = self.buffer.pixel(x, y);
+        if film_pixel.accumulated_weight == 0.0 {
+            return Vector3::zero_vector();
+        }
+        &film_pixel.accumulated_radiance / film_pixel.accumulated_weight
+    }
+
+    // (film_x, film_y) is the sample position in pixels, pixel (x, y) covers [x, x + 1) x [y, y + 1)
     #[inline(always)]
-    pub fn add_sample(&mut self, x: u32, y: u32, radiance: &Vector3) {
-        let pixel_index = self.index(x, y);
-        self.pixels[pixel_index].accumulated_radiance += radiance;
-        self.pixels[pixel_index].num_of_samples += 1;
+    pub fn add_sample(&mut self, film_x: f32, film_y: f32, radiance: &Vector3) {
+        self.buffer.splat(&self.filter, film_x, film_y, radiance);
     }
 
-    pub fn save_image(&self, location: &'static str) {
+    // the format is chosen from the extension of location, 8-bit images are only clamped
+    pub fn save_image(&self, location: &str) {
+        self.save_image_as(
+            location,
+            ImageFormat::from_path(location),
+            &ToneMapper::default(),
+        );
+    }
+
+    // the tone mapper only applies to 8-bit images, float formats store the linear radiance
+    pub fn save_image_as(&self, location: &str, format: ImageFormat, tone_mapper: &ToneMapper) {
+        match format {
+            ImageFormat::Ldr => self.save_ldr(location, tone_mapper),
+            ImageFormat::OpenExr => self.save_exr(location),
+            ImageFormat::RadianceHdr => self.save_hdr(location),
+            ImageFormat::Pfm => self.save_pfm(location).unwrap(),
+        }
+    }
+
+    fn save_ldr(&self, location: &str, tone_mapper: &ToneMapper) {
         let mut image_buffer = image::ImageBuffer::new(self.width, self.height);
 
         for (x, y, image_pixel) in image_buffer.enumerate_pixels_mut() {
-            let film_pixel = &self.pixels[self.index(x, self.height - y - 1)]; // the film is flipped in the camera so we need to revert it
-            let linear_color =
-                &film_pixel.accumulated_radiance / (film_pixel.num_of_samples as f32);
-            let mut srgb = to_srgb(&linear_color);
-            srgb.x = srgb.x.clamp(0.0, 1.0);
-            srgb.y = srgb.y.clamp(0.0, 1.0);
-            srgb.z = srgb.z.clamp(0.0, 1.0);
+            let linear_color = self.pixel_radiance(x, self.height - y - 1); // the film is flipped in the camera so we need to revert it
+            let srgb = to_srgb(&tone_mapper.apply(&linear_color));
             *image_pixel = image::Rgb([
                 (srgb.x * 255.0) as u8,
                 (srgb.y * 255.0) as u8,
@@ -84,11 +255,79 @@ impl Film {
 
         image_buffer.save(location).unwrap();
     }
+
+    // linear radiance in image order, top row first
+    fn linear_pixels(&self) -> Vec<image::Rgb<f32>> {
+        let mut pixels = Vec::with_capacity((self.width * self.height) as usize);
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let radiance = self.pixel_radiance(x, y);
+                pixels.push(image::Rgb([radiance.x, radiance.y, radiance.z]));
+            }
+        }
+        pixels
+    }
+
+    fn save_exr(&self, location: &str) {
+        let mut image_buffer: image::Rgb32FImage = image::ImageBuffer::new(self.width, self.height);
+        for (image_pixel, radiance) in image_buffer.pixels_mut().zip(self.linear_pixels()) {
+            *image_pixel = radiance;
+        }
+        image_buffer
+            .save_with_format(location, image::ImageFormat::OpenExr)
+            .unwrap();
+    }
+
+    fn save_hdr(&self, location: &str) {
+        let writer = BufWriter::new(File::create(location).unwrap());
+        image::codecs::hdr::HdrEncoder::new(writer)
+            .encode(
+                &self.linear_pixels(),
+                self.width as usize,
+                self.height as usize,
+            )
+            .unwrap();
+    }
+
+    // portable float map, rows are stored bottom to top which is the film order
+    fn save_pfm(&self, location: &str) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(location)?);
+        // a negative scale means little endian
+        write!(writer, "PF\n{} {}\n-1.0\n", self.width, self.height)?;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let radiance = self.pixel_radiance(x, y);
+                for component in [radiance.x, radiance.y, radiance.z] {
+                    writer.write_all(&component.to_le_bytes())?;
+                }
+            }
+        }
+        writer.flush()
+    }
+}
+
+impl FilmTile {
+    // sampled pixels [x0, x1) x [y0, y1) as (x0, y0, x1, y1)
+    pub fn bounds(&self) -> (u32, u32, u32, u32) {
+        (self.x0, self.y0, self.x1, self.y1)
+    }
+
+    pub fn num_of_pixels(&self) -> u32 {
+        (self.x1 - self.x0) * (self.y1 - self.y0)
+    }
+
+    #[inline(always)]
+    pub fn add_sample(&mut self, film_x: f32, film_y: f32, radiance: &Vector3) {
+        debug_assert!(film_x >= self.x0 as f32 && film_x <= self.x1 as f32);
+        debug_assert!(film_y >= self.y0 as f32 && film_y <= self.y1 as f32);
+        self.buffer.splat(&self.filter, film_x, film_y, radiance);
+    }
 }
 
 #[cfg(test)]
 mod film_tests {
-    use super::Film;
+    use super::{Film, ImageFormat};
+    use crate::filter::Filter;
     use crate::vector::Vector3;
     use rand::Rng;
 
@@ -105,11 +344,135 @@ mod film_tests {
                         y: rgen.gen(),
                         z: rgen.gen(),
                     };
-                    film.add_sample(x, y, &radiance);
+                    film.add_sample(x as f32 + 0.5, y as f32 + 0.5, &radiance);
                 }
             }
         }
 
         film.save_image("film_tests.png");
     }
+
+    #[test]
+    fn tile_test() {
+        let mut film = Film::new(20, 10);
+        let mut tile = film.create_tile(16, 0, 20, 8);
+        assert_eq!(tile.bounds(), (16, 0, 20, 8));
+        assert_eq!(tile.num_of_pixels(), 32);
+
+        tile.add_sample(19.5, 7.5, &Vector3::new(1.0, 2.0, 3.0));
+        tile.add_sample(19.25, 7.75, &Vector3::new(3.0, 2.0, 1.0));
+        film.merge_tile(&tile);
+        assert!(film.pixel_radiance(19, 7) == Vector3::new(2.0, 2.0, 2.0));
+        assert!(film.pixel_radiance(16, 0).is_zero());
+    }
+
+    #[test]
+    fn filter_test() {
+        let mut rgen = rand::thread_rng();
+        let samples: Vec<(f32, f32, Vector3)> = (0..2000)
+            .map(|_| {
+                let radiance = Vector3::new(rgen.gen(), rgen.gen(), rgen.gen());
+                (rgen.gen::<f32>() * 20.0, rgen.gen::<f32>() * 10.0, radiance)
+            })
+            .collect();
+
+        for name in ["box", "tent", "gaussian", "mitchell", "lanczos"] {
+            let filter = Filter::from_name(name).unwrap();
+
+            // splatting through tiles gives the same film as splatting on the whole film
+            let mut film = Film::with_filter(20, 10, filter);
+            let mut tiled_film = Film::with_filter(20, 10, filter);
+            let mut tiles = vec![
+                tiled_film.create_tile(0, 0, 8, 10),
+                tiled_film.create_tile(8, 0, 20, 4),
+                tiled_film.create_tile(8, 4, 20, 10),
+            ];
+            for (film_x, film_y, radiance) in &samples {
+                film.add_sample(*film_x, *film_y, radiance);
+                let tile = tiles
+                    .iter_mut()
+                    .find(|tile| {
+                        let (x0, y0, x1, y1) = tile.bounds();
+                        *film_x >= x0 as f32
+                            && *film_x < x1 as f32
+                            && *film_y >= y0 as f32
+                            && *film_y < y1 as f32
+                    })
+                    .unwrap();
+                tile.add_sample(*film_x, *film_y, radiance);
+            }
+            for tile in &tiles {
+                tiled_film.merge_tile(tile);
+            }
+
+            for x in 0..20 {
+                for y in 0..10 {
+                    let difference = &film.pixel_radiance(x, y) - &tiled_film.pixel_radiance(x, y);
+                    assert!(difference.length() < 1e-3, "{} ({}, {})", name, x, y);
+                }
+            }
+        }
+
+        // a constant image stays constant whatever the filter
+        let mut film = Film::with_filter(4, 4, Filter::from_name("mitchell").unwrap());
+        for i in 0..64 {
+            let film_x = (i % 8) as f32 * 0.5 + 0.25;
+            let film_y = (i / 8) as f32 * 0.5 + 0.25;
+            film.add_sample(film_x, film_y, &Vector3::new(0.5, 0.5, 0.5));
+        }
+        assert!((film.pixel_radiance(1, 2).x - 0.5).abs() < 1e-5);
+
+        // wider filters blur a single sample over the neighbouring pixels
+        let mut film = Film::with_filter(3, 3, Filter::from_name("gaussian").unwrap());
+        film.add_sample(1.5, 1.5, &Vector3::new(1.0, 1.0, 1.0));
+        film.add_sample(0.5, 0.5, &Vector3::zero_vector());
+        let center = film.pixel_radiance(1, 1).x;
+        let side = film.pixel_radiance(0, 1).x;
+        let corner = film.pixel_radiance(0, 0).x;
+        assert!(center < 1.0 && center > side && side > corner && corner > 0.0);
+    }
+
+    #[test]
+    fn hdr_output_test() {
+        assert_eq!(ImageFormat::from_path("render.EXR"), ImageFormat::OpenExr);
+        assert_eq!(
+            ImageFormat::from_path("render.hdr"),
+            ImageFormat::RadianceHdr
+        );
+        assert_eq!(ImageFormat::from_path("render.pfm"), ImageFormat::Pfm);
+        assert_eq!(ImageFormat::from_path("render.png"), ImageFormat::Ldr);
+
+        let mut film = Film::new(3, 2);
+        film.add_sample(0.5, 0.5, &Vector3::new(12.5, 0.25, 3.0));
+        film.add_sample(2.5, 1.5, &Vector3::new(0.5, 40.0, 0.125));
+
+        // values above one survive unclamped, the top row of the image is the last film row
+        film.save_image("film_hdr_output_test.exr");
+        let exr = image::open("film_hdr_output_test.exr")
+            .unwrap()
+            .into_rgb32f();
+        assert_eq!(exr.get_pixel(0, 1).0, [12.5, 0.25, 3.0]);
+        assert_eq!(exr.get_pixel(2, 0).0, [0.5, 40.0, 0.125]);
+
+        film.save_image("film_hdr_output_test.hdr");
+        // the generic image reader converts radiance files to 8-bit, decode them directly
+        let hdr_file =
+            std::io::BufReader::new(std::fs::File::open("film_hdr_output_test.hdr").unwrap());
+        let hdr = image::codecs::hdr::HdrDecoder::new(hdr_file)
+            .unwrap()
+            .read_image_hdr()
+            .unwrap();
+        // rgbe shares the exponent between the channels so the smallest ones lose precision
+        assert!((hdr[3][0] - 12.5).abs() < 0.1 && (hdr[3][2] - 3.0).abs() < 0.1);
+        assert!((hdr[2][1] - 40.0).abs() < 0.2);
+
+        film.save_image("film_hdr_output_test.pfm");
+        let pfm = std::fs::read("film_hdr_output_test.pfm").unwrap();
+        let header = b"PF\n3 2\n-1.0\n";
+        assert_eq!(&pfm[..header.len()], header);
+        assert_eq!(pfm.len(), header.len() + 3 * 2 * 3 * 4);
+        let first_component =
+            f32::from_le_bytes(pfm[header.len()..header.len() + 4].try_into().unwrap());
+        assert_eq!(first_component, 12.5);
+    }
 }
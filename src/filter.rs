@@ -0,0 +1,144 @@
+use std::f32::consts::PI;
+
+// Pixel reconstruction filter, separable in x and y. Offsets are in pixels from the pixel center
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    Box { radius: f32 },
+    Tent { radius: f32 },
+    Gaussian { radius: f32, alpha: f32 }, // shifted down to reach zero at the radius
+    Mitchell { radius: f32, b: f32, c: f32 },
+    Lanczos { radius: f32 }, // sinc windowed by a sinc stretched over the radius
+}
+
+impl Default for Filter {
+    // a sample only counts for the pixel it was taken in
+    fn default() -> Filter {
+        Filter::Box { radius: 0.5 }
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-5 {
+        return 1.0;
+    }
+    f32::sin(PI * x) / (PI * x)
+}
+
+fn mitchell_1d(x: f32, b: f32, c: f32) -> f32 {
+    // x in [0, 2]
+    if x > 1.0 {
+        ((-b - 6.0 * c) * x * x * x
+            + (6.0 * b + 30.0 * c) * x * x
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c))
+            / 6.0
+    } else {
+        ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+            + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+            + (6.0 - 2.0 * b))
+            / 6.0
+    }
+}
+
+impl Filter {
+    // filter with the usual parameters for the given type name
+    pub fn from_name(name: &str) -> Option<Filter> {
+        match name {
+            "box" => Some(Filter::Box { radius: 0.5 }),
+            "tent" => Some(Filter::Tent { radius: 1.0 }),
+            "gaussian" => Some(Filter::Gaussian {
+                radius: 1.5,
+                alpha: 2.0,
+            }),
+            "mitchell" => Some(Filter::Mitchell {
+                radius: 2.0,
+                b: 1.0 / 3.0,
+                c: 1.0 / 3.0,
+            }),
+            "lanczos" => Some(Filter::Lanczos { radius: 3.0 }),
+            _ => None,
+        }
+    }
+
+    pub fn radius(&self) -> f32 {
+        match *self {
+            Filter::Box { radius }
+            | Filter::Tent { radius }
+            | Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius, .. }
+            | Filter::Lanczos { radius } => radius,
+        }
+    }
+
+    pub fn set_radius(&mut self, new_radius: f32) {
+        match self {
+            Filter::Box { radius }
+            | Filter::Tent { radius }
+            | Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius, .. }
+            | Filter::Lanczos { radius } => *radius = new_radius,
+        }
+    }
+
+    // the support is [-radius, radius), so that box filters never count a sample twice
+    pub fn evaluate_1d(&self, x: f32) -> f32 {
+        let radius = self.radius();
+        if x < -radius || x >= radius {
+            return 0.0;
+        }
+        match *self {
+            Filter::Box { .. } => 1.0,
+            Filter::Tent { .. } => radius - x.abs(),
+            Filter::Gaussian { alpha, .. } => f32::max(
+                0.0,
+                (-alpha * x * x).exp() - (-alpha * radius * radius).exp(),
+            ),
+            Filter::Mitchell { b, c, .. } => mitchell_1d(f32::abs(2.0 * x / radius), b, c),
+            Filter::Lanczos { .. } => sinc(x) * sinc(x / radius),
+        }
+    }
+
+    pub fn evaluate(&self, x: f32, y: f32) -> f32 {
+        self.evaluate_1d(x) * self.evaluate_1d(y)
+    }
+}
+
+#[cfg(test)]
+mod filter_tests {
+    use super::Filter;
+
+    #[test]
+    fn main_test() {
+        for name in ["box", "tent", "gaussian", "mitchell", "lanczos"] {
+            let filter = Filter::from_name(name).unwrap();
+            let radius = filter.radius();
+            assert!(filter.evaluate(0.0, 0.0) > 0.0);
+            assert_eq!(filter.evaluate_1d(radius), 0.0);
+            assert_eq!(filter.evaluate_1d(radius + 0.1), 0.0);
+            assert_eq!(filter.evaluate(-radius - 0.1, 0.0), 0.0);
+
+            // symmetric, with a positive integral
+            let mut integral = 0.0;
+            for i in 0..1000 {
+                let x = (i as f32 + 0.5) / 1000.0 * radius;
+                assert!((filter.evaluate_1d(x) - filter.evaluate_1d(-x)).abs() < 1e-5);
+                integral += filter.evaluate_1d(x) * radius / 1000.0;
+            }
+            assert!(integral > 0.0, "{}", name);
+        }
+
+        // the Mitchell filter has negative lobes but still sums to one over the pixel grid
+        let mitchell = Filter::from_name("mitchell").unwrap();
+        assert!(mitchell.evaluate_1d(1.5) < 0.0);
+        let mut sum = 0.0;
+        for i in -2..=2 {
+            sum += mitchell.evaluate_1d(i as f32 + 0.25);
+        }
+        assert!((sum - 1.0).abs() < 1e-4);
+
+        let mut tent = Filter::from_name("tent").unwrap();
+        tent.set_radius(2.0);
+        assert_eq!(tent, Filter::Tent { radius: 2.0 });
+        assert!(Filter::from_name("sinc").is_none());
+    }
+}
@@ -0,0 +1,227 @@
+use crate::bvh::Aabb;
+use crate::camera::Ray;
+use crate::shape::{self, Shape, ShapeIntersection, ShapeSample};
+use crate::tools::Sample2D;
+use crate::vector::{Transform, Vector3};
+use std::sync::Arc;
+
+// Shape placed in the scene by a transform from its own object space. The shape is shared by all
+// its instances, so a mesh can be placed many times while being stored once
+pub struct Instance {
+    shape: Arc<dyn Shape>,
+    to_scene: Transform,
+    to_object: Transform,
+}
+
+impl Instance {
+    pub fn new(shape: Arc<dyn Shape>, transform: Transform) -> Instance {
+        Instance {
+            shape,
+            to_scene: transform,
+            to_object: transform.inverse(),
+        }
+    }
+
+    // ray in object space with a unit direction, and the length of the transformed direction
+    // which scales the distances along the ray
+    fn object_ray(&self, ray: &Ray) -> (Ray, f32) {
+        let direction = self.to_object.vector(&ray.direction);
+        let scale = direction.length();
+        let object_ray = Ray {
+            origin: self.to_object.point(&ray.origin),
+            direction: &direction / scale,
+            time: ray.time,
+        };
+        (object_ray, scale)
+    }
+
+    // solid angle pdf in the scene of a point the shape sampled with object_pdf in object space,
+    // through the area pdfs which only differ by the change of area of the surface
+    fn scene_pdf(
+        &self,
+        object_pdf: f32,
+        object_reference: &Vector3,
+        object_point: &Vector3,
+        object_normal: &Vector3,
+        reference_point: &Vector3,
+    ) -> f32 {
+        let to_solid_angle =
+            shape::area_to_solid_angle_pdf(1.0, object_reference, object_point, object_normal);
+        if to_solid_angle <= 0.0 {
+            return 0.0;
+        }
+        let normal = self.to_scene.normal(object_normal);
+        let area_scale = self.to_scene.matrix().determinant3().abs() * normal.length();
+        shape::area_to_solid_angle_pdf(
+            object_pdf / (to_solid_angle * area_scale),
+            reference_point,
+            &self.to_scene.point(object_point),
+            &normal.unit(),
+        )
+    }
+}
+
+impl Shape for Instance {
+    fn intersect(&self, ray: &Ray) -> ShapeIntersection {
+        let (object_ray, scale) = self.object_ray(ray);
+        let mut intersection = self.shape.intersect(&object_ray);
+        if intersection.t >= 0.0 {
+            intersection.t /= scale;
+            intersection.surface_normal = self.to_scene.normal(&intersection.surface_normal).unit();
+        }
+        intersection
+    }
+
+    fn bounds(&self) -> Aabb {
+        let bounds = self.shape.bounds();
+        let extremes = [bounds.min, bounds.max];
+        let corners: Vec<Vector3> = (0..8)
+            .map(|i| {
+                self.to_scene.point(&Vector3::new(
+                    extremes[i & 1].x,
+                    extremes[(i >> 1) & 1].y,
+                    extremes[(i >> 2) & 1].z,
+                ))
+            })
+            .collect();
+        Aabb::from_points(&corners)
+    }
+
+    fn sample(&self, reference_point: &Vector3, sample: &Sample2D) -> ShapeSample {
+        let object_reference = self.to_object.point(reference_point);
+        let object_sample = self.shape.sample(&object_reference, sample);
+        if object_sample.pdf <= 0.0 {
+            return ShapeSample::invalid_sample();
+        }
+        ShapeSample {
+            point: self.to_scene.point(&object_sample.point),
+            normal: self.to_scene.normal(&object_sample.normal).unit(),
+            pdf: self.scene_pdf(
+                object_sample.pdf,
+                &object_reference,
+                &object_sample.point,
+                &object_sample.normal,
+                reference_point,
+            ),
+        }
+    }
+
+    fn pdf(&self, reference_point: &Vector3, direction: &Vector3) -> f32 {
+        let (object_ray, _) = self.object_ray(&Ray {
+            origin: *reference_point,
+            direction: *direction,
+            time: 0.0,
+        });
+        let intersection = self.shape.intersect(&object_ray);
+        if intersection.t < 0.0 {
+            return 0.0;
+        }
+        let object_pdf = self.shape.pdf(&object_ray.origin, &object_ray.direction);
+        if object_pdf <= 0.0 {
+            return 0.0;
+        }
+        let object_point = &object_ray.origin + &(&object_ray.direction * intersection.t);
+        self.scene_pdf(
+            object_pdf,
+            &object_ray.origin,
+            &object_point,
+            &intersection.surface_normal,
+            reference_point,
+        )
+    }
+}
+
+#[cfg(test)]
+mod instance_tests {
+    use super::Instance;
+    use crate::camera::Ray;
+    use crate::shape::{Shape, Sphere};
+    use crate::tools::{self, Sample2D};
+    use crate::vector::{Transform, Vector3};
+    use std::sync::Arc;
+
+    #[test]
+    fn main_test() {
+        // a unit sphere stretched along x, turned to lie along z and moved to z = 10
+        let sphere: Arc<dyn Shape> = Arc::new(Sphere {
+            position: Vector3::zero_vector(),
+            radius: 1.0,
+        });
+        let transform = Transform::scaling(&Vector3::new(3.0, 1.0, 1.0))
+            .then(&Transform::rotation(
+                &Vector3::new(0.0, 1.0, 0.0),
+                std::f32::consts::FRAC_PI_2,
+            ))
+            .then(&Transform::translation(&Vector3::new(0.0, 0.0, 10.0)));
+        let instance = Instance::new(sphere.clone(), transform);
+        let other = Instance::new(sphere, Transform::identity());
+
+        // the nearest end of the ellipsoid along z is 3 away from its center
+        let ray = Ray {
+            origin: Vector3::zero_vector(),
+            direction: Vector3::new(0.0, 0.0, 1.0),
+            time: 0.0,
+        };
+        let intersection = instance.intersect(&ray);
+        assert!(tools::equal_error(intersection.t, 7.0));
+        assert!(intersection.surface_normal == Vector3::new(0.0, 0.0, -1.0));
+        assert!(tools::equal_error(other.intersect(&ray).t, 1.0));
+
+        // its side is only 1 away from its axis, where the normal is not the stretched one
+        let ray = Ray {
+            origin: Vector3::new(-5.0, 0.0, 11.5),
+            direction: Vector3::new(1.0, 0.0, 0.0),
+            time: 0.0,
+        };
+        let intersection = instance.intersect(&ray);
+        let point = &ray.origin + &(&ray.direction * intersection.t);
+        assert!(tools::equal_error(
+            point.x * point.x + (point.z - 10.0).powi(2) / 9.0,
+            1.0
+        ));
+        let expected_normal = Vector3::new(point.x, 0.0, (point.z - 10.0) / 9.0).unit();
+        assert!(intersection.surface_normal == expected_normal);
+
+        let bounds = instance.bounds();
+        assert!(bounds.min == Vector3::new(-1.0, -1.0, 7.0));
+        assert!(bounds.max == Vector3::new(1.0, 1.0, 13.0));
+
+        // the pdf of the samples agrees with pdf(), and the mean of its inverse is the solid angle
+        // of the ellipsoid, measured by the directions hitting it
+        let reference_point = Vector3::new(-4.0, 0.5, 9.0);
+        let mut sampled_solid_angle = 0.0;
+        let mut solid_angle = 0.0;
+        for i in 0..128 {
+            for j in 0..128 {
+                let sample = Sample2D {
+                    s: (i as f32 + 0.5) / 128.0,
+                    t: (j as f32 + 0.5) / 128.0,
+                };
+                let shape_sample = instance.sample(&reference_point, &sample);
+                assert!(shape_sample.pdf > 0.0);
+                let direction = (&shape_sample.point - &reference_point).unit();
+                let pdf = instance.pdf(&reference_point, &direction);
+                assert!((pdf - shape_sample.pdf).abs() < 1e-2 * pdf);
+                sampled_solid_angle += 1.0 / shape_sample.pdf / 16384.0;
+
+                let z = 1.0 - 2.0 * sample.s;
+                let r = f32::sqrt(1.0 - z * z);
+                let phi = 2.0 * std::f32::consts::PI * sample.t;
+                let ray = Ray {
+                    origin: reference_point,
+                    direction: Vector3::new(r * phi.cos(), r * phi.sin(), z),
+                    time: 0.0,
+                };
+                if instance.intersect(&ray).t >= 0.0 {
+                    solid_angle += 4.0 * std::f32::consts::PI / 16384.0;
+                }
+            }
+        }
+        assert!(
+            (sampled_solid_angle - solid_angle).abs() < 0.02 * solid_angle,
+            "{} {}",
+            sampled_solid_angle,
+            solid_angle
+        );
+    }
+}
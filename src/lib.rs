@@ -0,0 +1,19 @@
+pub mod bvh;
+pub mod camera;
+pub mod environment;
+pub mod film;
+pub mod filter;
+pub mod instance;
+pub mod light;
+pub mod material;
+pub mod microfacet;
+pub mod motion;
+pub mod obj;
+pub mod renderer;
+pub mod sampler;
+pub mod scene;
+pub mod scene_file;
+pub mod shape;
+pub mod tonemap;
+pub mod tools;
+pub mod vector;
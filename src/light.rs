@@ -0,0 +1,268 @@
+use crate::tools::Sample2D;
+use crate::vector::Vector3;
+use std::f32::consts::PI;
+
+pub struct LightSample {
+    pub direction: Vector3,
+    pub distance: f32,
+    pub radiance: Vector3,
+    pub pdf: f32, // solid angle pdf including the choice of the light, 0 for an invalid sample
+    // emissive geometry can also be found by the material samples, and is weighted against them
+    pub is_hittable: bool,
+}
+
+impl LightSample {
+    pub fn invalid_sample() -> LightSample {
+        LightSample {
+            direction: Vector3::zero_vector(),
+            distance: 0.0,
+            radiance: Vector3::zero_vector(),
+            pdf: 0.0,
+            is_hittable: false,
+        }
+    }
+}
+
+// Light without geometry, rays never hit it so it is only found through shadow rays
+pub trait Light: Sync {
+    // incident radiance at the reference point, for a direction sampled towards the light
+    fn sample(&self, reference_point: &Vector3, sample: &Sample2D) -> LightSample;
+}
+
+// Light at infinity surrounding the scene, it is also seen by the rays leaving the scene
+pub trait EnvironmentLight: Light {
+    // radiance arriving from the direction
+    fn radiance(&self, direction: &Vector3) -> Vector3;
+    // solid angle pdf of sample returning the direction
+    fn pdf(&self, direction: &Vector3) -> f32;
+}
+
+// Emits the same intensity in every direction from a single point
+pub struct PointLight {
+    pub position: Vector3,
+    pub intensity: Vector3, // power per unit of solid angle
+}
+
+// Point light restricted to a cone, fading out between the falloff and the cone angles
+pub struct SpotLight {
+    pub position: Vector3,
+    pub direction: Vector3,
+    pub intensity: Vector3,
+    cos_cone_angle: f32,
+    cos_falloff_angle: f32,
+}
+
+// Light coming from infinitely far away, like the sun. A positive angular diameter gives it a disk
+// and soft shadows
+pub struct DirectionalLight {
+    pub direction: Vector3,  // towards which the light travels
+    pub irradiance: Vector3, // received by a surface facing the light
+    cos_half_angle: f32,
+}
+
+impl PointLight {
+    pub fn new(position: Vector3, color: &Vector3, intensity: f32) -> PointLight {
+        PointLight {
+            position,
+            intensity: color * intensity,
+        }
+    }
+}
+
+// the light reaches the reference point from a single point
+fn sample_point(position: &Vector3, reference_point: &Vector3, intensity: &Vector3) -> LightSample {
+    let to_light = position - reference_point;
+    let distance = to_light.length();
+    if distance <= 0.0 {
+        return LightSample::invalid_sample();
+    }
+    LightSample {
+        direction: &to_light / distance,
+        distance,
+        radiance: intensity * (1.0 / (distance * distance)),
+        pdf: 1.0,
+        is_hittable: false,
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, reference_point: &Vector3, _sample: &Sample2D) -> LightSample {
+        sample_point(&self.position, reference_point, &self.intensity)
+    }
+}
+
+impl SpotLight {
+    // angles in radians from the axis of the cone, full intensity up to the falloff angle
+    pub fn new(
+        position: Vector3,
+        direction: Vector3,
+        color: &Vector3,
+        intensity: f32,
+        cone_angle: f32,
+        falloff_angle: f32,
+    ) -> SpotLight {
+        SpotLight {
+            position,
+            direction: direction.unit(),
+            intensity: color * intensity,
+            cos_cone_angle: cone_angle.cos(),
+            cos_falloff_angle: f32::min(falloff_angle, cone_angle).cos(),
+        }
+    }
+
+    // smooth transition from 1 at the falloff angle to 0 at the cone angle
+    fn falloff(&self, cos_theta: f32) -> f32 {
+        if cos_theta >= self.cos_falloff_angle {
+            return 1.0;
+        }
+        if cos_theta <= self.cos_cone_angle {
+            return 0.0;
+        }
+        let t = (cos_theta - self.cos_cone_angle) / (self.cos_falloff_angle - self.cos_cone_angle);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, reference_point: &Vector3, _sample: &Sample2D) -> LightSample {
+        let mut light_sample = sample_point(&self.position, reference_point, &self.intensity);
+        let falloff = self.falloff(-light_sample.direction.dot(&self.direction));
+        if falloff <= 0.0 {
+            return LightSample::invalid_sample();
+        }
+        light_sample.radiance *= falloff;
+        light_sample
+    }
+}
+
+impl DirectionalLight {
+    // angular diameter in radians, 0 for perfectly sharp shadows
+    pub fn new(
+        direction: Vector3,
+        color: &Vector3,
+        intensity: f32,
+        angular_diameter: f32,
+    ) -> DirectionalLight {
+        DirectionalLight {
+            direction: direction.unit(),
+            irradiance: color * intensity,
+            cos_half_angle: f32::cos(angular_diameter * 0.5),
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    // uniform direction in the cone of the disk, whose radiance gives the same irradiance to a
+    // surface facing the light
+    fn sample(&self, _reference_point: &Vector3, sample: &Sample2D) -> LightSample {
+        let to_light = -&self.direction;
+        if self.cos_half_angle >= 1.0 {
+            return LightSample {
+                direction: to_light,
+                distance: f32::INFINITY,
+                radiance: self.irradiance,
+                pdf: 1.0,
+                is_hittable: false,
+            };
+        }
+
+        LightSample {
+            direction: sample_cone(&to_light, self.cos_half_angle, sample),
+            distance: f32::INFINITY,
+            radiance: &self.irradiance * disk_radiance_scale(self.cos_half_angle),
+            pdf: 1.0 / cone_solid_angle(self.cos_half_angle),
+            is_hittable: false,
+        }
+    }
+}
+
+// uniform direction in the cone around the unit axis
+pub fn sample_cone(axis: &Vector3, cos_half_angle: f32, sample: &Sample2D) -> Vector3 {
+    let cos_theta = 1.0 - sample.s * (1.0 - cos_half_angle);
+    let sin_theta = f32::sqrt(f32::max(0.0, 1.0 - cos_theta * cos_theta));
+    let phi = 2.0 * PI * sample.t;
+    let local = Vector3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+    let mut t = Vector3::zero_vector();
+    let mut b = Vector3::zero_vector();
+    axis.create_basis(&mut b, &mut t);
+    Vector3::to_basis(&local, axis, &t, &b).unit()
+}
+
+pub fn cone_solid_angle(cos_half_angle: f32) -> f32 {
+    2.0 * PI * (1.0 - cos_half_angle)
+}
+
+// radiance of a disk per irradiance it gives a surface facing it, a disk of half angle theta
+// delivers pi sin^2(theta) its radiance
+pub fn disk_radiance_scale(cos_half_angle: f32) -> f32 {
+    1.0 / (PI * (1.0 - cos_half_angle * cos_half_angle))
+}
+
+#[cfg(test)]
+mod light_tests {
+    use super::{DirectionalLight, Light, PointLight, SpotLight};
+    use crate::tools::{self, Sample2D};
+    use crate::vector::Vector3;
+
+    #[test]
+    fn main_test() {
+        let sample = Sample2D { s: 0.5, t: 0.5 };
+        let white = Vector3::new(1.0, 1.0, 1.0);
+
+        // inverse square law
+        let point = PointLight::new(Vector3::new(0.0, 2.0, 0.0), &white, 8.0);
+        let light_sample = point.sample(&Vector3::zero_vector(), &sample);
+        assert!(light_sample.direction == Vector3::new(0.0, 1.0, 0.0));
+        assert!(tools::equal_error(light_sample.distance, 2.0));
+        assert!(tools::equal_error(light_sample.radiance.x, 2.0));
+
+        let spot = SpotLight::new(
+            Vector3::new(0.0, 1.0, 0.0),
+            Vector3::new(0.0, -1.0, 0.0),
+            &white,
+            1.0,
+            30_f32.to_radians(),
+            20_f32.to_radians(),
+        );
+        let radiance_at = |angle: f32| {
+            let point = Vector3::new(angle.to_radians().tan(), 0.0, 0.0);
+            let light_sample = spot.sample(&point, &sample);
+            light_sample.radiance.x * light_sample.distance * light_sample.distance
+        };
+        assert!(tools::equal_error(radiance_at(0.0), 1.0));
+        assert!(tools::equal_error(radiance_at(19.0), 1.0));
+        let fading = radiance_at(25.0);
+        assert!(fading > 0.0 && fading < 1.0);
+        assert_eq!(radiance_at(31.0), 0.0);
+        assert_eq!(spot.sample(&Vector3::new(0.0, 2.0, 0.0), &sample).pdf, 0.0);
+
+        // the sun disk gives the irradiance of the sharp light, over a cone of directions
+        let sharp = DirectionalLight::new(Vector3::new(0.0, -1.0, 0.0), &white, 3.0, 0.0);
+        let light_sample = sharp.sample(&Vector3::zero_vector(), &sample);
+        assert!(light_sample.direction == Vector3::new(0.0, 1.0, 0.0));
+        assert!(light_sample.radiance == Vector3::new(3.0, 3.0, 3.0));
+        // for small disks as for wide ones, up to the whole sky
+        for angular_diameter in [10.0, 120.0, 180.0] {
+            let soft = DirectionalLight::new(
+                Vector3::new(0.0, -1.0, 0.0),
+                &white,
+                3.0,
+                f32::to_radians(angular_diameter),
+            );
+            let mut irradiance = 0.0;
+            for i in 0..100 {
+                for j in 0..100 {
+                    let sample = Sample2D {
+                        s: (i as f32 + 0.5) / 100.0,
+                        t: (j as f32 + 0.5) / 100.0,
+                    };
+                    let light_sample = soft.sample(&Vector3::zero_vector(), &sample);
+                    let cos_theta = light_sample.direction.y;
+                    assert!(cos_theta >= f32::to_radians(angular_diameter * 0.5).cos() - 1e-5);
+                    irradiance += light_sample.radiance.x * cos_theta / light_sample.pdf / 10000.0;
+                }
+            }
+            assert!((irradiance - 3.0).abs() < 0.01, "{}", irradiance);
+        }
+    }
+}
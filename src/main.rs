@@ -1,272 +1,108 @@
-use camera::Camera;
-use material::{DiffuseMaterial, EmissiveMaterial, ReflectiveMaterial, TransparentMaterial};
-use renderer::RenderSettings;
-use scene::{Entity, Scene};
-use shape::{Plane, Sphere};
-use vector::Vector3;
-
-pub mod camera;
-pub mod film;
-pub mod material;
-pub mod renderer;
-pub mod scene;
-pub mod shape;
-pub mod tools;
-pub mod vector;
-
-fn main() {
-    let back_wall = Plane::new(
-        Vector3 {
-            x: 0.0,
-            y: 0.0,
-            z: 3.0,
-        },
-        Vector3 {
-            x: 0.0,
-            y: 0.0,
-            z: -1.0,
-        },
-        Vector3 {
-            x: 0.0,
-            y: 1.0,
-            z: 0.0,
-        },
-        8.0,
-        10.0,
-    );
-
-    let light_plane = Plane::new(
-        Vector3 {
-            x: 0.0,
-            y: 4.95,
-            z: 0.0,
-        },
-        Vector3 {
-            x: 0.0,
-            y: -1.0,
-            z: 0.0,
-        },
-        Vector3 {
-            x: 0.0,
-            y: 0.0,
-            z: 1.0,
-        },
-        1.3,
-        1.3,
-    );
-
-    let ceiling = Plane::new(
-        Vector3 {
-            x: 0.0,
-            y: 5.0,
-            z: 0.0,
-        },
-        Vector3 {
-            x: 0.0,
-            y: -1.0,
-            z: 0.0,
-        },
-        Vector3 {
-            x: 0.0,
-            y: 0.0,
-            z: 1.0,
-        },
-        8.0,
-        6.0,
-    );
-
-    let floor = Plane::new(
-        Vector3 {
-            x: 0.0,
-            y: -5.0,
-            z: 0.0,
-        },
-        Vector3 {
-            x: 0.0,
-            y: 1.0,
-            z: 0.0,
-        },
-        Vector3 {
-            x: 0.0,
-            y: 0.0,
-            z: 1.0,
-        },
-        8.0,
-        6.0,
-    );
-
-    let right_wall = Plane::new(
-        Vector3 {
-            x: 4.0,
-            y: 0.0,
-            z: 0.0,
-        },
-        Vector3 {
-            x: -1.0,
-            y: 0.0,
-            z: 0.0,
-        },
-        Vector3 {
-            x: 0.0,
-            y: 1.0,
-            z: 0.0,
-        },
-        8.0,
-        10.0,
-    );
-
-    let left_wall = Plane::new(
-        Vector3 {
-            x: -4.0,
-            y: 0.0,
-            z: 0.0,
-        },
-        Vector3 {
-            x: 1.0,
-            y: 0.0,
-            z: 0.0,
-        },
-        Vector3 {
-            x: 0.0,
-            y: 1.0,
-            z: 0.0,
-        },
-        8.0,
-        10.0,
+use pathtracer_rs::film::ImageFormat;
+use pathtracer_rs::renderer;
+use pathtracer_rs::scene_file::SceneDescription;
+use pathtracer_rs::tonemap::{ToneMapper, ToneMappingOperator};
+use std::path::Path;
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {} <scene file> [output image] [--tonemap <operator>] [--exposure <stops>] [--white <white point>]",
+        program
     );
+    eprintln!("Tone mapping operators: clamp, reinhard, extended_reinhard, hable, aces");
+    std::process::exit(1);
+}
 
-    let mirror_sphere = Sphere {
-        position: Vector3 {
-            x: -1.75,
-            y: -2.5,
-            z: 2.0,
-        },
-        radius: 1.35,
-    };
+// overrides the tone mapping of the scene file with the command line options
+fn parse_tone_mapper(
+    program: &str,
+    options: &[String],
+    tone_mapper: &mut ToneMapper,
+) -> Result<(), String> {
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        let value = match options.next() {
+            Some(value) => value,
+            None => usage(program),
+        };
+        match option.as_str() {
+            "--tonemap" => {
+                tone_mapper.operator = ToneMappingOperator::from_name(value)
+                    .ok_or(format!("unknown tone mapping operator '{}'", value))?
+            }
+            "--exposure" => {
+                tone_mapper.exposure = value
+                    .parse()
+                    .map_err(|_| format!("invalid exposure '{}'", value))?
+            }
+            "--white" => {
+                tone_mapper.white_point = value
+                    .parse()
+                    .ok()
+                    .filter(|white_point: &f32| *white_point > 0.0)
+                    .ok_or(format!("invalid white point '{}'", value))?
+            }
+            _ => usage(program),
+        }
+    }
+    Ok(())
+}
 
-    let glass_sphere = Sphere {
-        position: Vector3 {
-            x: 2.0,
-            y: -2.25,
-            z: 0.5,
-        },
-        radius: 1.5,
+// image name with the suffix of the camera before the extension, "sky.png" becomes "sky_px.png"
+fn with_suffix(output: &str, suffix: &str) -> String {
+    if suffix.is_empty() {
+        return output.to_string();
+    }
+    let path = Path::new(output);
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("");
+    let name = match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => format!("{}_{}.{}", stem, suffix, extension),
+        None => format!("{}_{}", stem, suffix),
     };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
 
-    let light = EmissiveMaterial::new(
-        &Vector3 {
-            x: 1.0,
-            y: 1.0,
-            z: 1.0,
-        },
-        27.777,
-    );
-
-    let white_diffuse = DiffuseMaterial {
-        color: Vector3 {
-            x: 0.8,
-            y: 0.8,
-            z: 0.8,
-        },
-    };
-    let green_diffuse = DiffuseMaterial {
-        color: Vector3 {
-            x: 0.1,
-            y: 0.8,
-            z: 0.1,
-        },
-    };
-    let red_diffuse = DiffuseMaterial {
-        color: Vector3 {
-            x: 0.8,
-            y: 0.1,
-            z: 0.1,
-        },
-    };
-    let mirror = ReflectiveMaterial {
-        color: Vector3 {
-            x: 1.0,
-            y: 1.0,
-            z: 1.0,
-        },
-    };
-    let glass = TransparentMaterial {
-        color: Vector3 {
-            x: 1.0,
-            y: 1.0,
-            z: 1.0,
-        },
-        ior: 1.75,
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() < 2 || args[1].starts_with("--") {
+        usage(&args[0]);
+    }
+    let (output_arg, options) = match args.get(2) {
+        Some(output) if !output.starts_with("--") => (Some(output), &args[3..]),
+        _ => (None, &args[2..]),
     };
 
-    let mut scene = Scene::new(Vector3 {
-        x: 0.05,
-        y: 0.05,
-        z: 0.1,
-    });
-
-    scene.add_entity(Entity {
-        material: &white_diffuse,
-        shape: &back_wall,
-    });
-    scene.add_entity(Entity {
-        material: &white_diffuse,
-        shape: &floor,
-    });
-    scene.add_entity(Entity {
-        material: &red_diffuse,
-        shape: &left_wall,
-    });
-    scene.add_entity(Entity {
-        material: &green_diffuse,
-        shape: &right_wall,
-    });
-    scene.add_entity(Entity {
-        material: &light,
-        shape: &light_plane,
-    });
-    scene.add_entity(Entity {
-        material: &white_diffuse,
-        shape: &ceiling,
-    });
-    scene.add_entity(Entity {
-        material: &mirror,
-        shape: &mirror_sphere,
-    });
-    scene.add_entity(Entity {
-        material: &glass,
-        shape: &glass_sphere,
-    });
-
-    let width = 800;
-    let height = 600;
-    let aspect_ratio = width as f32 / height as f32;
-
-    let camera = Camera::new(
-        std::f32::consts::PI / 2.0,
-        aspect_ratio,
-        Vector3 {
-            x: 0.0,
-            y: 0.0,
-            z: -7.0,
-        },
-        Vector3 {
-            x: 0.0,
-            y: 0.0,
-            z: 1.0,
-        },
-        Vector3 {
-            x: 0.0,
-            y: 1.0,
-            z: 0.0,
-        },
-    );
-
-    let render_settings = RenderSettings {
-        image_width: width,
-        image_height: height,
-        num_of_samples: 2048,
+    let mut description = match SceneDescription::load(&args[1]) {
+        Ok(description) => description,
+        Err(error) => {
+            eprintln!("Failed to load scene {}: {}", args[1], error);
+            std::process::exit(1);
+        }
     };
-    let film = renderer::render_scene(&scene, &camera, &render_settings);
-    film.save_image("example.png");
+    if let Err(error) = parse_tone_mapper(
+        &args[0],
+        options,
+        &mut description.render_settings.tone_mapper,
+    ) {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+    let output = output_arg
+        .or(description.output.as_ref())
+        .map_or("example.png", |output| output.as_str())
+        .to_string();
+
+    let scene = description.build_scene();
+    for (suffix, camera) in &description.cameras {
+        let film = renderer::render_scene(&scene, camera.as_ref(), &description.render_settings);
+        let output = with_suffix(&output, suffix);
+        film.save_image_as(
+            &output,
+            ImageFormat::from_path(&output),
+            &description.render_settings.tone_mapper,
+        );
+    }
 }
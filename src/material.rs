@@ -1,3 +1,4 @@
+use crate::microfacet::TrowbridgeReitz;
 use crate::shape::ShapeIntersection;
 use crate::tools;
 use crate::tools::Sampler;
@@ -10,16 +11,32 @@ pub struct MaterialSample {
     pub pdf: f32,
 }
 
-pub trait Material {
+pub trait Material: Sync {
     fn sample_material(
         &self,
         wo: &Vector3,
         intersection: &ShapeIntersection,
-        sampler: &mut Sampler,
+        sampler: &mut dyn Sampler,
     ) -> MaterialSample;
+    // brdf for the pair of directions, always zero for delta materials
+    fn eval(&self, _wo: &Vector3, _wi: &Vector3, _intersection: &ShapeIntersection) -> Vector3 {
+        Vector3::zero_vector()
+    }
+    // solid angle pdf of sample_material returning wi, always zero for delta materials
+    fn pdf(&self, _wo: &Vector3, _wi: &Vector3, _intersection: &ShapeIntersection) -> f32 {
+        0.0
+    }
+    // delta materials scatter in discrete directions and cannot be evaluated or light sampled
+    fn is_delta(&self) -> bool {
+        false
+    }
     fn get_emission(&self) -> Vector3 {
         Vector3::zero_vector()
     }
+    // absorption coefficient per unit of distance of the medium enclosed by the surface
+    fn get_absorption(&self) -> Vector3 {
+        Vector3::zero_vector()
+    }
 }
 
 pub struct NoMaterial;
@@ -28,7 +45,7 @@ impl Material for NoMaterial {
         &self,
         _wo: &Vector3,
         _intersection: &ShapeIntersection,
-        _sampler: &mut Sampler,
+        _sampler: &mut dyn Sampler,
     ) -> MaterialSample {
         MaterialSample::invalid_sample()
     }
@@ -46,9 +63,26 @@ pub struct ReflectiveMaterial {
     pub color: Vector3,
 }
 
+// Rough metal, its color comes from the complex index of refraction eta + i k of each channel
+pub struct ConductorMaterial {
+    pub eta: Vector3,
+    pub k: Vector3,
+    pub distribution: TrowbridgeReitz,
+}
+
 pub struct TransparentMaterial {
+    pub color: Vector3,      // tint of every interface crossing
+    pub ior: f32,            // index of refraction
+    pub absorption: Vector3, // Beer-Lambert attenuation inside, grows with the distance travelled
+}
+
+// Frosted glass, the interface is made of Trowbridge-Reitz microfacets as in Walter et al.,
+// "Microfacet Models for Refraction through Rough Surfaces"
+pub struct RoughTransparentMaterial {
     pub color: Vector3,
     pub ior: f32, // index of refraction
+    pub absorption: Vector3,
+    pub distribution: TrowbridgeReitz,
 }
 
 impl MaterialSample {
@@ -74,7 +108,7 @@ impl Material for EmissiveMaterial {
         &self,
         _wo: &Vector3,
         _intersection: &ShapeIntersection,
-        _sampler: &mut Sampler,
+        _sampler: &mut dyn Sampler,
     ) -> MaterialSample {
         MaterialSample::invalid_sample()
     }
@@ -84,6 +118,35 @@ impl Material for EmissiveMaterial {
     }
 }
 
+// Orthonormal basis around the surface normal, the roughness axes of anisotropic materials follow
+// its tangents
+struct ShadingFrame {
+    t: Vector3,
+    b: Vector3,
+    n: Vector3,
+}
+
+impl ShadingFrame {
+    fn new(normal: &Vector3) -> ShadingFrame {
+        let mut t = Vector3::zero_vector();
+        let mut b = Vector3::zero_vector();
+        normal.create_basis(&mut b, &mut t);
+        ShadingFrame { t, b, n: *normal }
+    }
+
+    fn world_to_local(&self, vec: &Vector3) -> Vector3 {
+        Vector3 {
+            x: vec.dot(&self.t),
+            y: vec.dot(&self.b),
+            z: vec.dot(&self.n),
+        }
+    }
+
+    fn local_to_world(&self, vec: &Vector3) -> Vector3 {
+        Vector3::to_basis(vec, &self.n, &self.t, &self.b)
+    }
+}
+
 #[inline(always)]
 fn transform_to_base_unit(vec: &Vector3, normal: &Vector3) -> Vector3 {
     let mut t = Vector3::zero_vector();
@@ -93,33 +156,44 @@ fn transform_to_base_unit(vec: &Vector3, normal: &Vector3) -> Vector3 {
 }
 
 impl Material for DiffuseMaterial {
-    // sample hemisphere uniformly
+    // sample the hemisphere proportionally to the cosine, by projecting a point of the unit disk
+    // on it (Malley's method)
     fn sample_material(
         &self,
         wo: &Vector3,
         intersection: &ShapeIntersection,
-        sampler: &mut Sampler,
+        sampler: &mut dyn Sampler,
     ) -> MaterialSample {
         if !tools::is_positive_error(wo.dot(&intersection.surface_normal)) {
             return MaterialSample::invalid_sample();
         }
 
         const ONE_OVER_PI: f32 = 1.0 / std::f32::consts::PI;
-        let sample_2d = sampler.get_sample_2d();
-        let cos_theta = 1.0 - 2.0 * sample_2d.t;
-        let phi = sample_2d.s * (2.0 * std::f32::consts::PI);
-        let sin_theta = f32::sqrt(1.0 - cos_theta * cos_theta);
-        let sample_dir = Vector3 {
-            x: sin_theta * f32::cos(phi),
-            y: sin_theta * f32::sin(phi),
-            z: f32::abs(cos_theta),
-        };
+        let (x, y) = tools::concentric_sample_disk(&sampler.get_sample_2d());
+        let cos_theta = f32::sqrt(f32::max(0.0, 1.0 - x * x - y * y));
+        let sample_dir = Vector3 { x, y, z: cos_theta };
 
         MaterialSample {
             brdf: &self.color * ONE_OVER_PI,
             sample_direction: transform_to_base_unit(&sample_dir, &intersection.surface_normal),
-            pdf: 0.5 * ONE_OVER_PI,
+            pdf: cos_theta * ONE_OVER_PI,
+        }
+    }
+
+    fn eval(&self, wo: &Vector3, wi: &Vector3, intersection: &ShapeIntersection) -> Vector3 {
+        let n = &intersection.surface_normal;
+        if !tools::is_positive_error(wo.dot(n)) || !tools::is_positive_error(wi.dot(n)) {
+            return Vector3::zero_vector();
+        }
+        &self.color * (1.0 / std::f32::consts::PI)
+    }
+
+    fn pdf(&self, wo: &Vector3, wi: &Vector3, intersection: &ShapeIntersection) -> f32 {
+        let n = &intersection.surface_normal;
+        if !tools::is_positive_error(wo.dot(n)) || !tools::is_positive_error(wi.dot(n)) {
+            return 0.0;
         }
+        wi.dot(n) / std::f32::consts::PI
     }
 }
 
@@ -165,7 +239,7 @@ impl Material for ReflectiveMaterial {
         &self,
         wo: &Vector3,
         intersection: &ShapeIntersection,
-        _sampler: &mut Sampler,
+        _sampler: &mut dyn Sampler,
     ) -> MaterialSample {
         const ETA: f32 = 0.04;
         let wo_dot_n = wo.dot(&intersection.surface_normal);
@@ -179,65 +253,397 @@ impl Material for ReflectiveMaterial {
             pdf: 1.0,
         }
     }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
 }
 
-impl Material for TransparentMaterial {
+// Fresnel reflectance of a conductor for unpolarized light
+fn conductor_fresnel(cos_theta: f32, eta: f32, k: f32) -> f32 {
+    let cos2_theta = cos_theta * cos_theta;
+    let sin2_theta = 1.0 - cos2_theta;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - sin2_theta;
+    let a2_plus_b2 = f32::sqrt(f32::max(0.0, t0 * t0 + 4.0 * eta2 * k2));
+    let t1 = a2_plus_b2 + cos2_theta;
+    let a = f32::sqrt(f32::max(0.0, 0.5 * (a2_plus_b2 + t0)));
+    let t2 = 2.0 * cos_theta * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2_theta * a2_plus_b2 + sin2_theta * sin2_theta;
+    let t4 = t2 * sin2_theta;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+    0.5 * (rp + rs)
+}
+
+impl ConductorMaterial {
+    // roughness 0 is a perfect mirror, roughness_x and roughness_y differ for brushed metals
+    pub fn new(eta: Vector3, k: Vector3, roughness_x: f32, roughness_y: f32) -> ConductorMaterial {
+        ConductorMaterial {
+            eta,
+            k,
+            distribution: TrowbridgeReitz::new(roughness_x, roughness_y),
+        }
+    }
+
+    // measured metals, fitted to the red, green and blue channels
+    pub fn from_preset(
+        name: &str,
+        roughness_x: f32,
+        roughness_y: f32,
+    ) -> Option<ConductorMaterial> {
+        let (eta, k) = match name {
+            "gold" => (
+                Vector3::new(0.143, 0.374, 1.442),
+                Vector3::new(3.983, 2.385, 1.603),
+            ),
+            "copper" => (
+                Vector3::new(0.200, 0.924, 1.102),
+                Vector3::new(3.912, 2.452, 2.142),
+            ),
+            "aluminium" => (
+                Vector3::new(1.657, 0.880, 0.521),
+                Vector3::new(9.224, 6.270, 4.837),
+            ),
+            "silver" => (
+                Vector3::new(0.155, 0.117, 0.138),
+                Vector3::new(4.828, 3.122, 2.147),
+            ),
+            _ => return None,
+        };
+        Some(ConductorMaterial::new(eta, k, roughness_x, roughness_y))
+    }
+
+    fn fresnel(&self, cos_theta: f32) -> Vector3 {
+        Vector3 {
+            x: conductor_fresnel(cos_theta, self.eta.x, self.k.x),
+            y: conductor_fresnel(cos_theta, self.eta.y, self.k.y),
+            z: conductor_fresnel(cos_theta, self.eta.z, self.k.z),
+        }
+    }
+
+    // torrance-sparrow brdf in the shading frame
+    fn eval_local(&self, wo: &Vector3, wi: &Vector3) -> Vector3 {
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Vector3::zero_vector();
+        }
+        let wm = wo + wi;
+        if wm.is_zero() {
+            return Vector3::zero_vector();
+        }
+        let wm = wm.unit();
+        let fresnel = self.fresnel(wo.dot(&wm).abs());
+        &fresnel * (self.distribution.d(&wm) * self.distribution.g(wo, wi) / (4.0 * wo.z * wi.z))
+    }
+}
+
+impl Material for ConductorMaterial {
+    // samples the microfacet normals visible from wo, the direction is their mirror reflection
     fn sample_material(
         &self,
         wo: &Vector3,
         intersection: &ShapeIntersection,
-        sampler: &mut Sampler,
+        sampler: &mut dyn Sampler,
     ) -> MaterialSample {
-        let mut wo_dot_n = wo.dot(&intersection.surface_normal);
-        if tools::equal_error(wo_dot_n, 0.0) {
+        let wo_dot_n = wo.dot(&intersection.surface_normal);
+        if !tools::is_positive_error(wo_dot_n) {
+            return MaterialSample::invalid_sample();
+        }
+
+        if self.distribution.is_smooth() {
+            return MaterialSample {
+                brdf: &self.fresnel(wo_dot_n) * (1.0 / wo_dot_n),
+                sample_direction: reflect(wo, &intersection.surface_normal, wo_dot_n),
+                pdf: 1.0,
+            };
+        }
+
+        let frame = ShadingFrame::new(&intersection.surface_normal);
+        let wo_local = frame.world_to_local(wo);
+        let wm = self
+            .distribution
+            .sample_visible_normal(&wo_local, &sampler.get_sample_2d());
+        let wo_dot_wm = wo_local.dot(&wm);
+        let wi_local = reflect(&wo_local, &wm, wo_dot_wm);
+        if !tools::is_positive_error(wi_local.z) {
             return MaterialSample::invalid_sample();
         }
 
-        let eta = if wo_dot_n > 0.0 {
+        MaterialSample {
+            brdf: self.eval_local(&wo_local, &wi_local),
+            sample_direction: frame.local_to_world(&wi_local).unit(),
+            pdf: self.distribution.visible_d(&wo_local, &wm) / (4.0 * wo_dot_wm),
+        }
+    }
+
+    fn eval(&self, wo: &Vector3, wi: &Vector3, intersection: &ShapeIntersection) -> Vector3 {
+        if self.distribution.is_smooth() {
+            return Vector3::zero_vector();
+        }
+        let frame = ShadingFrame::new(&intersection.surface_normal);
+        self.eval_local(&frame.world_to_local(wo), &frame.world_to_local(wi))
+    }
+
+    fn pdf(&self, wo: &Vector3, wi: &Vector3, intersection: &ShapeIntersection) -> f32 {
+        if self.distribution.is_smooth() {
+            return 0.0;
+        }
+        let frame = ShadingFrame::new(&intersection.surface_normal);
+        let wo = frame.world_to_local(wo);
+        let wi = frame.world_to_local(wi);
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let wm = &wo + &wi;
+        if wm.is_zero() {
+            return 0.0;
+        }
+        let wm = wm.unit();
+        self.distribution.visible_d(&wo, &wm) / (4.0 * wo.dot(&wm))
+    }
+
+    fn is_delta(&self) -> bool {
+        self.distribution.is_smooth()
+    }
+}
+
+// reflects or refracts through a smooth interface, proportionally to the fresnel reflectance
+fn sample_smooth_dielectric(
+    color: &Vector3,
+    ior: f32,
+    wo: &Vector3,
+    intersection: &ShapeIntersection,
+    sampler: &mut dyn Sampler,
+) -> MaterialSample {
+    let mut wo_dot_n = wo.dot(&intersection.surface_normal);
+    if tools::equal_error(wo_dot_n, 0.0) {
+        return MaterialSample::invalid_sample();
+    }
+
+    let eta = if wo_dot_n > 0.0 { ior } else { 1.0 / ior };
+    let mut n = intersection.surface_normal;
+    if wo_dot_n < 0.0 {
+        wo_dot_n = -wo_dot_n;
+        n = -&n;
+    }
+
+    let fresnel = calculate_fresnel(eta, wo_dot_n);
+    if sampler.get_sample() < fresnel {
+        return MaterialSample {
+            brdf: color * (fresnel / wo_dot_n),
+            sample_direction: reflect(wo, &n, wo_dot_n),
+            pdf: fresnel,
+        };
+    }
+
+    let wi = refract(wo, &n, 1.0 / eta, wo_dot_n).unit();
+    let wi_dot_n = f32::abs(wi.dot(&intersection.surface_normal));
+    if tools::equal_error(wi_dot_n, 0.0) {
+        return MaterialSample::invalid_sample();
+    }
+
+    let refraction_fresnel = 1.0 - fresnel;
+    let brdf = color * (refraction_fresnel * eta * eta / wi_dot_n);
+    MaterialSample {
+        brdf,
+        sample_direction: wi,
+        pdf: refraction_fresnel,
+    }
+}
+
+impl Material for TransparentMaterial {
+    fn sample_material(
+        &self,
+        wo: &Vector3,
+        intersection: &ShapeIntersection,
+        sampler: &mut dyn Sampler,
+    ) -> MaterialSample {
+        sample_smooth_dielectric(&self.color, self.ior, wo, intersection, sampler)
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    fn get_absorption(&self) -> Vector3 {
+        self.absorption
+    }
+}
+
+impl RoughTransparentMaterial {
+    // roughness 0 is the smooth interface of TransparentMaterial
+    pub fn new(color: Vector3, ior: f32, roughness: f32) -> RoughTransparentMaterial {
+        RoughTransparentMaterial {
+            color,
+            ior,
+            absorption: Vector3::zero_vector(),
+            distribution: TrowbridgeReitz::new(roughness, roughness),
+        }
+    }
+
+    // index of refraction of the side opposite to wo relative to the one of wo, the shading
+    // normal points to the outside
+    fn relative_eta(&self, wo: &Vector3) -> f32 {
+        if wo.z > 0.0 {
             self.ior
         } else {
             1.0 / self.ior
+        }
+    }
+
+    // microfacet normal turning wo into wi, facing the outside. None when the microfacet would
+    // be seen from behind by one of the directions
+    fn half_vector(&self, wo: &Vector3, wi: &Vector3) -> Option<Vector3> {
+        if wo.z == 0.0 || wi.z == 0.0 {
+            return None;
+        }
+        let eta = if wo.z * wi.z > 0.0 {
+            1.0
+        } else {
+            self.relative_eta(wo)
         };
-        let mut n = intersection.surface_normal;
-        if wo_dot_n < 0.0 {
-            wo_dot_n = -wo_dot_n;
-            n = -&n;
+        let wm = &(wi * eta) + wo;
+        if wm.is_zero() {
+            return None;
+        }
+        let wm = if wm.z < 0.0 { -&wm.unit() } else { wm.unit() };
+        if wm.dot(wi) * wi.z < 0.0 || wm.dot(wo) * wo.z < 0.0 {
+            return None;
         }
+        Some(wm)
+    }
 
-        let fresnel = calculate_fresnel(eta, wo_dot_n);
-        if sampler.get_sample() < fresnel {
-            return MaterialSample {
-                brdf: &self.color * (fresnel / wo_dot_n),
-                sample_direction: reflect(wo, &n, wo_dot_n),
-                pdf: fresnel,
-            };
+    // reflection and transmission through the microfacets in the shading frame
+    fn eval_local(&self, wo: &Vector3, wi: &Vector3) -> Vector3 {
+        let wm = match self.half_vector(wo, wi) {
+            Some(wm) => wm,
+            None => return Vector3::zero_vector(),
+        };
+        let eta = self.relative_eta(wo);
+        let wo_dot_wm = wo.dot(&wm);
+        let fresnel = calculate_fresnel(eta, wo_dot_wm.abs());
+        let d_g = self.distribution.d(&wm) * self.distribution.g(wo, wi);
+        if wo.z * wi.z > 0.0 {
+            return &self.color * (fresnel * d_g / (4.0 * f32::abs(wo.z * wi.z)));
+        }
+
+        let wi_dot_wm = wi.dot(&wm);
+        let denom = wi_dot_wm + wo_dot_wm / eta;
+        // radiance is squeezed into the smaller solid angle of the denser side
+        let transmission = (1.0 - fresnel) * d_g / (eta * eta)
+            * f32::abs(wi_dot_wm * wo_dot_wm / (wi.z * wo.z * denom * denom));
+        &self.color * transmission
+    }
+
+    fn pdf_local(&self, wo: &Vector3, wi: &Vector3) -> f32 {
+        let wm = match self.half_vector(wo, wi) {
+            Some(wm) => wm,
+            None => return 0.0,
+        };
+        let eta = self.relative_eta(wo);
+        let wo_dot_wm = wo.dot(&wm);
+        let fresnel = calculate_fresnel(eta, wo_dot_wm.abs());
+        let visible_d = self.distribution.visible_d(wo, &wm);
+        if wo.z * wi.z > 0.0 {
+            return fresnel * visible_d / (4.0 * wo_dot_wm.abs());
+        }
+
+        // jacobian of the refraction, from the microfacet normal to wi
+        let wi_dot_wm = wi.dot(&wm);
+        let denom = wi_dot_wm + wo_dot_wm / eta;
+        (1.0 - fresnel) * visible_d * wi_dot_wm.abs() / (denom * denom)
+    }
+}
+
+impl Material for RoughTransparentMaterial {
+    // samples a microfacet normal visible from wo, then reflects or refracts through it
+    // proportionally to its fresnel reflectance
+    fn sample_material(
+        &self,
+        wo: &Vector3,
+        intersection: &ShapeIntersection,
+        sampler: &mut dyn Sampler,
+    ) -> MaterialSample {
+        if self.distribution.is_smooth() {
+            return sample_smooth_dielectric(&self.color, self.ior, wo, intersection, sampler);
         }
 
-        let wi = refract(wo, &n, 1.0 / eta, wo_dot_n).unit();
-        let wi_dot_n = f32::abs(wi.dot(&intersection.surface_normal));
-        if tools::equal_error(wi_dot_n, 0.0) {
+        let frame = ShadingFrame::new(&intersection.surface_normal);
+        let wo_local = frame.world_to_local(wo);
+        if tools::equal_error(wo_local.z, 0.0) {
+            return MaterialSample::invalid_sample();
+        }
+        let wm = self
+            .distribution
+            .sample_visible_normal(&wo_local, &sampler.get_sample_2d());
+        // the microfacet normal on the side of wo
+        let wo_dot_wm = wo_local.dot(&wm);
+        let wm = if wo_dot_wm < 0.0 { -&wm } else { wm };
+        let wo_dot_wm = wo_dot_wm.abs();
+
+        let eta = self.relative_eta(&wo_local);
+        let fresnel = calculate_fresnel(eta, wo_dot_wm);
+        let reflection = sampler.get_sample() < fresnel;
+        let wi_local = if reflection {
+            reflect(&wo_local, &wm, wo_dot_wm)
+        } else {
+            refract(&wo_local, &wm, 1.0 / eta, wo_dot_wm)
+        };
+        // directions leaving through the wrong side of the macro surface are absorbed
+        if wi_local.is_zero() || (wi_local.z * wo_local.z > 0.0) != reflection {
             return MaterialSample::invalid_sample();
         }
+        let wi_local = wi_local.unit();
 
-        let refraction_fresnel = 1.0 - fresnel;
-        let brdf = &self.color * (refraction_fresnel * eta * eta / wi_dot_n);
         MaterialSample {
-            brdf,
-            sample_direction: wi,
-            pdf: refraction_fresnel,
+            brdf: self.eval_local(&wo_local, &wi_local),
+            sample_direction: frame.local_to_world(&wi_local).unit(),
+            pdf: self.pdf_local(&wo_local, &wi_local),
+        }
+    }
+
+    fn eval(&self, wo: &Vector3, wi: &Vector3, intersection: &ShapeIntersection) -> Vector3 {
+        if self.distribution.is_smooth() {
+            return Vector3::zero_vector();
+        }
+        let frame = ShadingFrame::new(&intersection.surface_normal);
+        self.eval_local(&frame.world_to_local(wo), &frame.world_to_local(wi))
+    }
+
+    fn pdf(&self, wo: &Vector3, wi: &Vector3, intersection: &ShapeIntersection) -> f32 {
+        if self.distribution.is_smooth() {
+            return 0.0;
         }
+        let frame = ShadingFrame::new(&intersection.surface_normal);
+        self.pdf_local(&frame.world_to_local(wo), &frame.world_to_local(wi))
+    }
+
+    fn is_delta(&self) -> bool {
+        self.distribution.is_smooth()
+    }
+
+    fn get_absorption(&self) -> Vector3 {
+        self.absorption
     }
 }
 
 #[cfg(test)]
 mod material_tests {
-    use super::EmissiveMaterial;
-    use crate::camera::Camera;
+    use super::{
+        conductor_fresnel, ConductorMaterial, DiffuseMaterial, EmissiveMaterial,
+        RoughTransparentMaterial,
+    };
+    use crate::camera::{Camera, CameraModel};
     use crate::film::Film;
     use crate::material::Material;
-    use crate::shape::{Shape, Sphere};
+    use crate::sampler::IndependentSampler;
+    use crate::shape::{Shape, ShapeIntersection, Sphere};
     use crate::tools;
     use crate::vector::Vector3;
+    use std::f32::consts::PI;
 
     #[test]
     fn emission_material_test() {
@@ -285,7 +691,8 @@ mod material_tests {
             let film_x = (x as f32 + 0.5) / (width as f32);
             for y in 0..height {
                 let film_y = (y as f32 + 0.5) / (height as f32);
-                let ray = camera.generate_ray(film_x, film_y);
+                let ray =
+                    camera.generate_ray(film_x, film_y, &tools::Sample2D { s: 0.5, t: 0.5 }, 0.0);
                 let intersection = sphere.intersect(&ray);
                 if intersection.t >= 0.0 {
                     assert!(tools::equal_error(
@@ -294,11 +701,260 @@ mod material_tests {
                     ));
                     assert!(intersection.surface_normal.z <= 0.0);
                     let sample_radiance = material.get_emission();
-                    film.add_sample(x, y, &sample_radiance);
+                    film.add_sample(x as f32 + 0.5, y as f32 + 0.5, &sample_radiance);
                 }
             }
         }
 
         film.save_image("emission_material_test.png");
     }
+
+    #[test]
+    fn diffuse_eval_pdf_test() {
+        let material = DiffuseMaterial {
+            color: Vector3::new(0.5, 0.25, 1.0),
+        };
+        let intersection = ShapeIntersection {
+            t: 1.0,
+            surface_normal: Vector3::new(0.0, 1.0, 0.0),
+        };
+        let wo = Vector3::new(1.0, 1.0, 0.0).unit();
+        let mut sampler = IndependentSampler::new(11);
+        for _ in 0..100 {
+            let sample = material.sample_material(&wo, &intersection, &mut sampler);
+            let wi = sample.sample_direction;
+            assert!(sample.brdf == material.eval(&wo, &wi, &intersection));
+            assert!(tools::equal_error(
+                sample.pdf,
+                material.pdf(&wo, &wi, &intersection)
+            ));
+        }
+
+        let below = Vector3::new(0.0, -1.0, 0.0);
+        assert!(material.eval(&wo, &below, &intersection).is_zero());
+        assert_eq!(material.pdf(&wo, &below, &intersection), 0.0);
+    }
+
+    #[test]
+    fn diffuse_chi_square_test() {
+        // compares the histogram of the sampled directions with the one expected from the pdf,
+        // on a grid of cos(theta) and phi around a tilted normal
+        const COS_THETA_BINS: usize = 10;
+        const PHI_BINS: usize = 20;
+        const NUM_OF_SAMPLES: usize = 200000;
+        let material = DiffuseMaterial {
+            color: Vector3::new(0.5, 0.5, 0.5),
+        };
+        let normal = Vector3::new(1.0, 2.0, 3.0).unit();
+        let intersection = ShapeIntersection {
+            t: 1.0,
+            surface_normal: normal,
+        };
+        let wo = normal;
+        let mut tangent = Vector3::zero_vector();
+        let mut bitangent = Vector3::zero_vector();
+        normal.create_basis(&mut bitangent, &mut tangent);
+        let tangent = tangent.unit();
+        let bitangent = normal.cross(&tangent);
+        let bin_index = |cos_theta: f32, phi: f32| {
+            let cos_theta_bin = usize::min((cos_theta * COS_THETA_BINS as f32) as usize, 9);
+            let phi_bin = ((phi / (2.0 * PI) + 0.5) * PHI_BINS as f32) as usize;
+            cos_theta_bin * PHI_BINS + usize::min(phi_bin, PHI_BINS - 1)
+        };
+
+        let mut observed = vec![0.0; COS_THETA_BINS * PHI_BINS];
+        let mut sampler = IndependentSampler::new(5);
+        for _ in 0..NUM_OF_SAMPLES {
+            let wi = material
+                .sample_material(&wo, &intersection, &mut sampler)
+                .sample_direction;
+            let phi = f32::atan2(wi.dot(&bitangent), wi.dot(&tangent));
+            observed[bin_index(wi.dot(&normal), phi)] += 1.0;
+        }
+
+        // integrates the pdf over each bin, the solid angle is d(cos(theta)) d(phi)
+        let mut expected = vec![0.0; COS_THETA_BINS * PHI_BINS];
+        const STEPS: usize = 400;
+        let cell_area = (1.0 / STEPS as f32) * (2.0 * PI / STEPS as f32);
+        for i in 0..STEPS {
+            let cos_theta = (i as f32 + 0.5) / STEPS as f32;
+            let sin_theta = f32::sqrt(1.0 - cos_theta * cos_theta);
+            for j in 0..STEPS {
+                let phi = (j as f32 + 0.5) / STEPS as f32 * 2.0 * PI - PI;
+                let wi = &(&(&tangent * (sin_theta * phi.cos()))
+                    + &(&bitangent * (sin_theta * phi.sin())))
+                    + &(&normal * cos_theta);
+                expected[bin_index(cos_theta, phi)] +=
+                    material.pdf(&wo, &wi, &intersection) * cell_area * NUM_OF_SAMPLES as f32;
+            }
+        }
+
+        let total_expected: f32 = expected.iter().sum();
+        assert!((total_expected / NUM_OF_SAMPLES as f32 - 1.0).abs() < 0.01);
+        let chi_square: f32 = observed
+            .iter()
+            .zip(&expected)
+            .map(|(observed, expected)| (observed - expected) * (observed - expected) / expected)
+            .sum();
+        // 99.9% quantile of the chi-square distribution with 199 degrees of freedom
+        assert!(chi_square < 264.0, "chi-square {}", chi_square);
+
+        // the old uniform hemisphere distribution is rejected
+        let uniform_chi_square: f32 = observed
+            .iter()
+            .map(|observed| {
+                let expected = NUM_OF_SAMPLES as f32 / (COS_THETA_BINS * PHI_BINS) as f32;
+                (observed - expected) * (observed - expected) / expected
+            })
+            .sum();
+        assert!(uniform_chi_square > 1000.0);
+    }
+
+    #[test]
+    fn conductor_test() {
+        // at normal incidence the reflectance is ((eta - 1)^2 + k^2) / ((eta + 1)^2 + k^2)
+        let (eta, k) = (0.2_f32, 3.9_f32);
+        let expected = ((eta - 1.0).powi(2) + k * k) / ((eta + 1.0).powi(2) + k * k);
+        assert!(tools::equal_error(conductor_fresnel(1.0, eta, k), expected));
+        assert!(tools::equal_error(conductor_fresnel(0.0, eta, k), 1.0));
+
+        assert!(ConductorMaterial::from_preset("gold", 0.0, 0.0)
+            .unwrap()
+            .is_delta());
+        assert!(ConductorMaterial::from_preset("brass", 0.0, 0.0).is_none());
+
+        let material = ConductorMaterial::from_preset("gold", 0.6, 0.3).unwrap();
+        assert!(!material.is_delta());
+        let normal = Vector3::new(1.0, 2.0, 3.0).unit();
+        let intersection = ShapeIntersection {
+            t: 1.0,
+            surface_normal: normal,
+        };
+        let wo = Vector3::new(0.0, 1.0, 0.2).unit();
+        assert!(wo.dot(&normal) > 0.0);
+
+        const NUM_OF_SAMPLES: usize = 20000;
+        let mut num_of_valid_samples = 0;
+        let mut sampler = IndependentSampler::new(17);
+        for _ in 0..NUM_OF_SAMPLES {
+            let sample = material.sample_material(&wo, &intersection, &mut sampler);
+            if sample.pdf == 0.0 {
+                continue;
+            }
+            num_of_valid_samples += 1;
+            let wi = sample.sample_direction;
+            let eval = material.eval(&wo, &wi, &intersection);
+            assert!((&sample.brdf - &eval).length() < 1e-3 * eval.length().max(1.0));
+            let pdf = material.pdf(&wo, &wi, &intersection);
+            assert!((sample.pdf - pdf).abs() < 1e-3 * pdf.max(1.0));
+            // fresnel and masking can only lose energy
+            let weight = &sample.brdf * (wi.dot(&normal) / sample.pdf);
+            assert!(weight.x <= 1.001 && weight.y <= 1.001 && weight.z <= 1.001);
+        }
+
+        // the pdf integrates to the fraction of samples that are not below the surface
+        let mut tangent = Vector3::zero_vector();
+        let mut bitangent = Vector3::zero_vector();
+        normal.create_basis(&mut bitangent, &mut tangent);
+        const STEPS: usize = 500;
+        let mut pdf_integral = 0.0;
+        for i in 0..STEPS {
+            let cos_theta = (i as f32 + 0.5) / STEPS as f32;
+            let sin_theta = f32::sqrt(1.0 - cos_theta * cos_theta);
+            for j in 0..STEPS {
+                let phi = (j as f32 + 0.5) / STEPS as f32 * 2.0 * PI;
+                let wi = &(&(&tangent * (sin_theta * phi.cos()))
+                    + &(&bitangent * (sin_theta * phi.sin())))
+                    + &(&normal * cos_theta);
+                pdf_integral += material.pdf(&wo, &wi, &intersection);
+            }
+        }
+        pdf_integral *= 2.0 * PI / (STEPS * STEPS) as f32;
+        let valid_fraction = num_of_valid_samples as f32 / NUM_OF_SAMPLES as f32;
+        assert!(valid_fraction < 0.99);
+        assert!(
+            (pdf_integral - valid_fraction).abs() < 0.01,
+            "{} {}",
+            pdf_integral,
+            valid_fraction
+        );
+    }
+
+    #[test]
+    fn rough_transparent_test() {
+        assert!(RoughTransparentMaterial::new(Vector3::new(1.0, 1.0, 1.0), 1.5, 0.0).is_delta());
+
+        let ior = 1.5;
+        let material = RoughTransparentMaterial::new(Vector3::new(1.0, 1.0, 1.0), ior, 0.5);
+        assert!(!material.is_delta());
+        let normal = Vector3::new(1.0, 2.0, 3.0).unit();
+        let intersection = ShapeIntersection {
+            t: 1.0,
+            surface_normal: normal,
+        };
+        let mut tangent = Vector3::zero_vector();
+        let mut bitangent = Vector3::zero_vector();
+        normal.create_basis(&mut bitangent, &mut tangent);
+
+        // from the outside and from the inside of the medium
+        for wo in [
+            Vector3::new(0.0, 1.0, 0.2).unit(),
+            -&Vector3::new(0.5, 1.0, 0.8).unit(),
+        ] {
+            let entering = wo.dot(&normal) > 0.0;
+            const NUM_OF_SAMPLES: usize = 20000;
+            let mut num_of_valid_samples = 0;
+            let mut num_of_transmissions = 0;
+            let mut energy = 0.0;
+            let mut sampler = IndependentSampler::new(23);
+            for _ in 0..NUM_OF_SAMPLES {
+                let sample = material.sample_material(&wo, &intersection, &mut sampler);
+                if sample.pdf == 0.0 {
+                    continue;
+                }
+                num_of_valid_samples += 1;
+                let wi = sample.sample_direction;
+                let eval = material.eval(&wo, &wi, &intersection);
+                assert!((&sample.brdf - &eval).length() < 1e-3 * eval.length().max(1.0));
+                let pdf = material.pdf(&wo, &wi, &intersection);
+                assert!((sample.pdf - pdf).abs() < 1e-3 * pdf.max(1.0));
+
+                // undo the radiance scaling to measure the energy of the transmitted light
+                let mut weight = sample.brdf.x * wi.dot(&normal).abs() / sample.pdf;
+                if wi.dot(&normal) * wo.dot(&normal) < 0.0 {
+                    num_of_transmissions += 1;
+                    let eta = if entering { ior } else { 1.0 / ior };
+                    weight *= eta * eta;
+                }
+                energy += weight / NUM_OF_SAMPLES as f32;
+            }
+            assert!(num_of_transmissions > NUM_OF_SAMPLES / 2);
+            assert!(energy <= 1.0 && energy > 0.8, "{}", energy);
+
+            // the pdf integrates over the sphere to the fraction of valid samples
+            const STEPS: usize = 600;
+            let mut pdf_integral = 0.0;
+            for i in 0..STEPS {
+                let cos_theta = (i as f32 + 0.5) / STEPS as f32 * 2.0 - 1.0;
+                let sin_theta = f32::sqrt(1.0 - cos_theta * cos_theta);
+                let mut row_integral = 0.0;
+                for j in 0..STEPS {
+                    let phi = (j as f32 + 0.5) / STEPS as f32 * 2.0 * PI;
+                    let wi = &(&(&tangent * (sin_theta * phi.cos()))
+                        + &(&bitangent * (sin_theta * phi.sin())))
+                        + &(&normal * cos_theta);
+                    row_integral += material.pdf(&wo, &wi, &intersection);
+                }
+                pdf_integral += row_integral;
+            }
+            pdf_integral *= 4.0 * PI / (STEPS * STEPS) as f32;
+            let valid_fraction = num_of_valid_samples as f32 / NUM_OF_SAMPLES as f32;
+            assert!(
+                (pdf_integral - valid_fraction).abs() < 0.01,
+                "{} {}",
+                pdf_integral,
+                valid_fraction
+            );
+        }
+    }
 }
@@ -0,0 +1,197 @@
+use crate::tools::Sample2D;
+use crate::vector::Vector3;
+use std::f32::consts::PI;
+
+const MIN_ALPHA: f32 = 1e-4;
+
+// Trowbridge-Reitz (GGX) distribution of microfacet normals. Directions are in the local shading
+// frame, where the macro surface normal is +z and the roughness axes are x and y
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrowbridgeReitz {
+    alpha_x: f32,
+    alpha_y: f32,
+}
+
+impl TrowbridgeReitz {
+    // roughness in [0, 1] is mapped to alpha = roughness^2, which looks perceptually linear. Each
+    // alpha is kept away from 0, where d() divides by it when the other axis is rough
+    pub fn new(roughness_x: f32, roughness_y: f32) -> TrowbridgeReitz {
+        TrowbridgeReitz {
+            alpha_x: f32::max(roughness_x * roughness_x, MIN_ALPHA),
+            alpha_y: f32::max(roughness_y * roughness_y, MIN_ALPHA),
+        }
+    }
+
+    // surfaces this smooth are rendered as perfect specular ones
+    pub fn is_smooth(&self) -> bool {
+        f32::max(self.alpha_x, self.alpha_y) < 1e-3
+    }
+
+    // density of microfacet normals, normalized so that its projection on the surface is 1
+    pub fn d(&self, wm: &Vector3) -> f32 {
+        let cos2_theta = wm.z * wm.z;
+        if cos2_theta <= 0.0 {
+            return 0.0;
+        }
+        let e = (wm.x * wm.x / (self.alpha_x * self.alpha_x)
+            + wm.y * wm.y / (self.alpha_y * self.alpha_y))
+            / cos2_theta;
+        1.0 / (PI * self.alpha_x * self.alpha_y * cos2_theta * cos2_theta * (1.0 + e) * (1.0 + e))
+    }
+
+    // Smith's auxiliary function, the masked microfacet area per visible one
+    pub fn lambda(&self, w: &Vector3) -> f32 {
+        let cos2_theta = w.z * w.z;
+        if cos2_theta <= 0.0 {
+            return f32::INFINITY;
+        }
+        let alpha2_tan2_theta = (w.x * w.x * self.alpha_x * self.alpha_x
+            + w.y * w.y * self.alpha_y * self.alpha_y)
+            / cos2_theta;
+        (f32::sqrt(1.0 + alpha2_tan2_theta) - 1.0) * 0.5
+    }
+
+    // fraction of the microfacets visible from w
+    pub fn g1(&self, w: &Vector3) -> f32 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // fraction of the microfacets visible from both directions, height correlated
+    pub fn g(&self, wo: &Vector3, wi: &Vector3) -> f32 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // density of the normals visible from w, back facing ones are hidden
+    pub fn visible_d(&self, w: &Vector3, wm: &Vector3) -> f32 {
+        if w.z == 0.0 {
+            return 0.0;
+        }
+        self.g1(w) / w.z.abs() * self.d(wm) * f32::max(0.0, w.dot(wm) * w.z.signum())
+    }
+
+    // samples a normal visible from w with the density visible_d, Heitz's "Sampling the GGX
+    // Distribution of Visible Normals"
+    pub fn sample_visible_normal(&self, w: &Vector3, sample: &Sample2D) -> Vector3 {
+        // stretch the view direction to the hemisphere configuration
+        let w = if w.z < 0.0 { -w } else { *w };
+        let wh = Vector3 {
+            x: self.alpha_x * w.x,
+            y: self.alpha_y * w.y,
+            z: w.z,
+        }
+        .unit();
+
+        let length2 = wh.x * wh.x + wh.y * wh.y;
+        let t1 = if length2 > 0.0 {
+            &Vector3::new(-wh.y, wh.x, 0.0) * (1.0 / length2.sqrt())
+        } else {
+            Vector3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = wh.cross(&t1);
+
+        // uniform point on the disk, warped to the visible half of the projected hemisphere
+        let radius = sample.s.sqrt();
+        let phi = 2.0 * PI * sample.t;
+        let p1 = radius * phi.cos();
+        let mut p2 = radius * phi.sin();
+        let s = 0.5 * (1.0 + wh.z);
+        p2 = (1.0 - s) * f32::sqrt(1.0 - p1 * p1) + s * p2;
+        let pz = f32::sqrt(f32::max(0.0, 1.0 - p1 * p1 - p2 * p2));
+        let nh = &(&(&t1 * p1) + &(&t2 * p2)) + &(&wh * pz);
+
+        // and back to the ellipsoid configuration
+        Vector3 {
+            x: self.alpha_x * nh.x,
+            y: self.alpha_y * nh.y,
+            z: f32::max(1e-6, nh.z),
+        }
+        .unit()
+    }
+}
+
+#[cfg(test)]
+mod microfacet_tests {
+    use super::TrowbridgeReitz;
+    use crate::tools::Sample2D;
+    use crate::vector::Vector3;
+    use std::f32::consts::PI;
+
+    // integrates f over the hemisphere with a midpoint rule in (cos(theta), phi)
+    fn integrate_hemisphere<F: Fn(&Vector3) -> f32>(f: F) -> f32 {
+        const STEPS: usize = 500;
+        let mut sum = 0.0;
+        for i in 0..STEPS {
+            let cos_theta = (i as f32 + 0.5) / STEPS as f32;
+            let sin_theta = f32::sqrt(1.0 - cos_theta * cos_theta);
+            for j in 0..STEPS {
+                let phi = (j as f32 + 0.5) / STEPS as f32 * 2.0 * PI;
+                let w = Vector3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+                sum += f(&w);
+            }
+        }
+        sum * 2.0 * PI / (STEPS * STEPS) as f32
+    }
+
+    #[test]
+    fn main_test() {
+        let wo = Vector3::new(0.4, -0.3, 0.5).unit();
+        for distribution in [
+            TrowbridgeReitz::new(0.5, 0.5),
+            TrowbridgeReitz::new(0.7, 0.3),
+        ] {
+            // the projected area of the microfacets is the one of the surface
+            let projected_area = integrate_hemisphere(|wm| distribution.d(wm) * wm.z);
+            assert!((projected_area - 1.0).abs() < 0.01, "{}", projected_area);
+            // and the visible normals are a distribution
+            let visible = integrate_hemisphere(|wm| distribution.visible_d(&wo, wm));
+            assert!((visible - 1.0).abs() < 0.01, "{}", visible);
+
+            // sampled visible normals follow visible_d, compared on a few cones around +z
+            const NUM_OF_SAMPLES: usize = 100000;
+            let mut sampled = [0.0; 4];
+            for i in 0..NUM_OF_SAMPLES {
+                let sample = Sample2D {
+                    s: (i as f32 + 0.5) / NUM_OF_SAMPLES as f32,
+                    t: ((i as f32 * 0.618_034) % 1.0),
+                };
+                let wm = distribution.sample_visible_normal(&wo, &sample);
+                assert!(wm.z > 0.0 && wm.dot(&wo) > 0.0);
+                sampled[usize::min((wm.z * 4.0) as usize, 3)] += 1.0 / NUM_OF_SAMPLES as f32;
+            }
+            for (bin, sampled) in sampled.iter().enumerate() {
+                let expected = integrate_hemisphere(|wm| {
+                    if (wm.z * 4.0) as usize == bin {
+                        distribution.visible_d(&wo, wm)
+                    } else {
+                        0.0
+                    }
+                });
+                assert!(
+                    (sampled - expected).abs() < 0.01,
+                    "{} {}",
+                    sampled,
+                    expected
+                );
+            }
+        }
+
+        assert!(TrowbridgeReitz::new(0.01, 0.02).is_smooth());
+
+        // a single smooth axis stays finite instead of dividing by zero
+        let distribution = TrowbridgeReitz::new(0.3, 0.0);
+        assert!(!distribution.is_smooth());
+        for wm in [
+            Vector3::new(0.0, 0.0, 1.0),
+            Vector3::new(0.0, 0.6, 0.8),
+            Vector3::new(0.6, 0.0, 0.8),
+            Vector3::new(0.48, 0.36, 0.8),
+        ] {
+            assert!(distribution.d(&wm).is_finite());
+            assert!(distribution.visible_d(&wo, &wm).is_finite());
+        }
+        let distribution = TrowbridgeReitz::new(0.5, 0.5);
+        let normal = Vector3::new(0.0, 0.0, 1.0);
+        assert_eq!(distribution.g1(&normal), 1.0);
+        assert!(distribution.g(&wo, &wo) < distribution.g1(&wo));
+    }
+}
@@ -0,0 +1,190 @@
+use crate::bvh::Aabb;
+use crate::camera::Ray;
+use crate::shape::{Shape, ShapeIntersection};
+use crate::vector::Vector3;
+use std::sync::Arc;
+
+// Rigid placement of a moving shape relative to how it was declared: a rotation of angle radians
+// around the axis of the motion, then a translation
+#[derive(Debug, Clone, Copy)]
+pub struct Placement {
+    pub translation: Vector3,
+    pub angle: f32,
+}
+
+// Shape moving linearly from its placement at time 0 to the one at time 1, rotating around an
+// axis through the center of its bounds and staying still out of that interval. Rays hit it where it is at their time.
+// It is not light sampled, moving emitters are only found by the material samples
+pub struct MovingShape {
+    shape: Arc<dyn Shape>,
+    pivot: Vector3,
+    axis: Vector3,
+    open: Placement,
+    close: Placement,
+}
+
+impl Placement {
+    pub fn identity() -> Placement {
+        Placement {
+            translation: Vector3::zero_vector(),
+            angle: 0.0,
+        }
+    }
+
+    fn lerp(&self, other: &Placement, time: f32) -> Placement {
+        Placement {
+            translation: &(&self.translation * (1.0 - time)) + &(&other.translation * time),
+            angle: self.angle * (1.0 - time) + other.angle * time,
+        }
+    }
+}
+
+impl MovingShape {
+    pub fn new(
+        shape: Arc<dyn Shape>,
+        axis: Vector3,
+        open: Placement,
+        close: Placement,
+    ) -> MovingShape {
+        MovingShape {
+            pivot: shape.bounds().centroid(),
+            shape,
+            axis: axis.unit(),
+            open,
+            close,
+        }
+    }
+
+    fn placement(&self, time: f32) -> Placement {
+        self.open.lerp(&self.close, time.clamp(0.0, 1.0))
+    }
+
+    fn place(&self, point: &Vector3, placement: &Placement) -> Vector3 {
+        let rotated = (point - &self.pivot).rotate(&self.axis, placement.angle);
+        &(&rotated + &self.pivot) + &placement.translation
+    }
+}
+
+impl Shape for MovingShape {
+    // the ray is moved into the frame of the declared shape, rigid motions keep its distances
+    fn intersect(&self, ray: &Ray) -> ShapeIntersection {
+        let placement = self.placement(ray.time);
+        let origin = &(&(&ray.origin - &placement.translation) - &self.pivot)
+            .rotate(&self.axis, -placement.angle)
+            + &self.pivot;
+        let local_ray = Ray {
+            origin,
+            direction: ray.direction.rotate(&self.axis, -placement.angle),
+            time: ray.time,
+        };
+        let mut intersection = self.shape.intersect(&local_ray);
+        if intersection.t >= 0.0 {
+            intersection.surface_normal = intersection
+                .surface_normal
+                .rotate(&self.axis, placement.angle);
+        }
+        intersection
+    }
+
+    fn bounds(&self) -> Aabb {
+        let bounds = self.shape.bounds();
+        if self.open.angle == self.close.angle {
+            // the corners of the box are enough without a rotation during the motion
+            let extremes = [bounds.min, bounds.max];
+            let mut corners = Vec::with_capacity(16);
+            for placement in [&self.open, &self.close] {
+                for i in 0..8 {
+                    let corner = Vector3::new(
+                        extremes[i & 1].x,
+                        extremes[(i >> 1) & 1].y,
+                        extremes[(i >> 2) & 1].z,
+                    );
+                    corners.push(self.place(&corner, placement));
+                }
+            }
+            return Aabb::from_points(&corners);
+        }
+
+        // any rotation of the shape stays in the sphere around the pivot
+        let radius = (&(&bounds.max - &bounds.min) * 0.5).length();
+        let extent = Vector3::new(radius, radius, radius);
+        let mut points = Vec::with_capacity(4);
+        for placement in [&self.open, &self.close] {
+            let center = &self.pivot + &placement.translation;
+            points.push(&center - &extent);
+            points.push(&center + &extent);
+        }
+        Aabb::from_points(&points)
+    }
+}
+
+#[cfg(test)]
+mod motion_tests {
+    use super::{MovingShape, Placement};
+    use crate::camera::Ray;
+    use crate::shape::{Plane, Shape, Sphere};
+    use crate::vector::Vector3;
+    use std::f32::consts::PI;
+    use std::sync::Arc;
+
+    #[test]
+    fn main_test() {
+        // a sphere moving from x = 0 to x = 4 along the ray going up from x = 2
+        let sphere = Sphere {
+            position: Vector3::zero_vector(),
+            radius: 1.0,
+        };
+        let moving = MovingShape::new(
+            Arc::new(sphere),
+            Vector3::new(0.0, 1.0, 0.0),
+            Placement::identity(),
+            Placement {
+                translation: Vector3::new(4.0, 0.0, 0.0),
+                angle: 0.0,
+            },
+        );
+        let ray_at = |time| Ray {
+            origin: Vector3::new(2.0, -5.0, 0.0),
+            direction: Vector3::new(0.0, 1.0, 0.0),
+            time,
+        };
+        assert!(moving.intersect(&ray_at(0.0)).t < 0.0);
+        assert!(moving.intersect(&ray_at(1.0)).t < 0.0);
+        let intersection = moving.intersect(&ray_at(0.5));
+        assert!((intersection.t - 4.0).abs() < 1e-5);
+        assert!(intersection.surface_normal == Vector3::new(0.0, -1.0, 0.0));
+        let bounds = moving.bounds();
+        assert!(bounds.min == Vector3::new(-1.0, -1.0, -1.0));
+        assert!(bounds.max == Vector3::new(5.0, 1.0, 1.0));
+
+        // a square standing along x turning a quarter around y, to stand along z
+        let plane = Plane::new(
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(0.0, 0.0, -1.0),
+            Vector3::new(0.0, 1.0, 0.0),
+            2.0,
+            2.0,
+        );
+        let turning = MovingShape::new(
+            Arc::new(plane),
+            Vector3::new(0.0, 1.0, 0.0),
+            Placement::identity(),
+            Placement {
+                translation: Vector3::zero_vector(),
+                angle: PI / 2.0,
+            },
+        );
+        let ray_at = |time| Ray {
+            origin: Vector3::new(-5.0, 0.0, 0.5),
+            direction: Vector3::new(1.0, 0.0, 0.0),
+            time,
+        };
+        assert!(turning.intersect(&ray_at(0.0)).t < 0.0);
+        let intersection = turning.intersect(&ray_at(1.0));
+        assert!((intersection.t - 5.0).abs() < 1e-5);
+        assert!(intersection.surface_normal == Vector3::new(-1.0, 0.0, 0.0));
+        let bounds = turning.bounds();
+        assert!(bounds.min.z <= -1.0 && bounds.max.z >= 1.0);
+        assert!(bounds.min.x <= -1.0 && bounds.max.x >= 1.0);
+    }
+}
@@ -0,0 +1,640 @@
+//! Wavefront OBJ and MTL import.
+//!
+//! Supported OBJ statements are `v`, `vn`, `vt`, `f`, `g`, `o`, `usemtl` and `mtllib`. Faces may
+//! use negative (relative) indices and polygons with more than three vertices are triangulated.
+//! Texture coordinates are validated but not used since materials are not textured. Every group
+//! and material combination becomes one mesh and one scene entity.
+//!
+//! MTL materials are mapped as follows: a non-zero `Ke` gives an `EmissiveMaterial`, `d < 1` (or
+//! `Tr > 0`) a `TransparentMaterial` using `Ni` and `Tf`, a `Ks` brighter than `Kd` a
+//! `ReflectiveMaterial` and anything else a `DiffuseMaterial` using `Kd`. Other statements are
+//! ignored.
+
+use crate::material::{
+    DiffuseMaterial, EmissiveMaterial, Material, ReflectiveMaterial, TransparentMaterial,
+};
+use crate::scene::{Entity, Scene};
+use crate::shape::TriangleMesh;
+use crate::vector::Vector3;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
+
+pub struct ObjGroup {
+    pub name: String,
+    mesh: usize,
+    material: usize,
+}
+
+pub struct ObjModel {
+    pub groups: Vec<ObjGroup>,
+    meshes: Vec<TriangleMesh>,
+    materials: Vec<Box<dyn Material>>,
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            ObjError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+struct LineParser<'a> {
+    path: &'a Path,
+    line: usize,
+}
+
+impl LineParser<'_> {
+    fn error<T>(&self, message: String) -> Result<T, ObjError> {
+        Err(ObjError::Parse {
+            path: self.path.to_path_buf(),
+            line: self.line,
+            message,
+        })
+    }
+
+    fn floats(&self, arguments: &[&str], min: usize, max: usize) -> Result<Vec<f32>, ObjError> {
+        if arguments.len() < min || arguments.len() > max {
+            return self.error(format!("expected {} to {} numbers", min, max));
+        }
+        arguments
+            .iter()
+            .map(|argument| match argument.parse::<f32>() {
+                Ok(number) if number.is_finite() => Ok(number),
+                _ => self.error(format!("invalid number '{}'", argument)),
+            })
+            .collect()
+    }
+
+    fn vector(&self, arguments: &[&str]) -> Result<Vector3, ObjError> {
+        let numbers = self.floats(arguments, 3, 3)?;
+        Ok(Vector3::new(numbers[0], numbers[1], numbers[2]))
+    }
+
+    // OBJ indices start at 1, negative indices count back from the last element
+    fn index(&self, token: &str, count: usize) -> Result<usize, ObjError> {
+        let index = match token.parse::<i64>() {
+            Ok(index) => index,
+            Err(_) => return self.error(format!("invalid index '{}'", token)),
+        };
+        let resolved = if index < 0 {
+            count as i64 + index
+        } else {
+            index - 1
+        };
+        if index == 0 || resolved < 0 || resolved >= count as i64 {
+            return self.error(format!("index {} out of range", index));
+        }
+        Ok(resolved as usize)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct FaceVertex {
+    position: usize,
+    normal: Option<usize>,
+}
+
+#[derive(Default)]
+struct MeshBuilder {
+    positions: Vec<Vector3>,
+    normals: Vec<Vector3>,
+    missing_normals: bool, // some vertex has no normal, use geometric normals for the mesh
+    indices: Vec<u32>,
+    vertices: HashMap<FaceVertex, u32>,
+}
+
+impl MeshBuilder {
+    fn add_vertex(
+        &mut self,
+        vertex: FaceVertex,
+        positions: &[Vector3],
+        normals: &[Vector3],
+    ) -> u32 {
+        if let Some(index) = self.vertices.get(&vertex) {
+            return *index;
+        }
+        let index = self.positions.len() as u32;
+        self.missing_normals |= vertex.normal.is_none();
+        self.positions.push(positions[vertex.position]);
+        self.normals.push(match vertex.normal {
+            Some(normal) if !normals[normal].is_zero() => normals[normal].unit(),
+            _ => Vector3::zero_vector(),
+        });
+        self.vertices.insert(vertex, index);
+        index
+    }
+
+    fn build(self) -> TriangleMesh {
+        let normals = if !self.missing_normals {
+            Some(self.normals)
+        } else {
+            None
+        };
+        TriangleMesh::new(self.positions, normals, self.indices)
+    }
+}
+
+// Newell's method, robust for non planar and concave polygons
+fn polygon_normal(points: &[Vector3]) -> Vector3 {
+    let mut normal = Vector3::zero_vector();
+    for (i, current) in points.iter().enumerate() {
+        let next = &points[(i + 1) % points.len()];
+        normal.x += (current.y - next.y) * (current.z + next.z);
+        normal.y += (current.z - next.z) * (current.x + next.x);
+        normal.z += (current.x - next.x) * (current.y + next.y);
+    }
+    normal
+}
+
+fn point_in_triangle(p: (f32, f32), a: (f32, f32), b: (f32, f32), c: (f32, f32)) -> bool {
+    let edge = |a: (f32, f32), b: (f32, f32)| (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0);
+    edge(a, b) >= 0.0 && edge(b, c) >= 0.0 && edge(c, a) >= 0.0
+}
+
+// Ear clipping triangulation, keeps the winding of the polygon
+fn triangulate(points: &[Vector3]) -> Vec<[usize; 3]> {
+    let fan = |remaining: &[usize]| {
+        (1..remaining.len() - 1)
+            .map(|i| [remaining[0], remaining[i], remaining[i + 1]])
+            .collect::<Vec<[usize; 3]>>()
+    };
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let normal = polygon_normal(points);
+    if points.len() == 3 || normal.is_zero() {
+        return fan(&remaining);
+    }
+
+    // project on the plane of the dominant normal axis, oriented counter-clockwise
+    let (u, v) = if normal.x.abs() > normal.y.abs() && normal.x.abs() > normal.z.abs() {
+        if normal.x > 0.0 {
+            (1, 2)
+        } else {
+            (2, 1)
+        }
+    } else if normal.y.abs() > normal.z.abs() {
+        if normal.y > 0.0 {
+            (2, 0)
+        } else {
+            (0, 2)
+        }
+    } else if normal.z > 0.0 {
+        (0, 1)
+    } else {
+        (1, 0)
+    };
+    let projected: Vec<(f32, f32)> = points.iter().map(|p| (p[u], p[v])).collect();
+
+    let mut triangles = Vec::with_capacity(points.len() - 2);
+    while remaining.len() > 3 {
+        let count = remaining.len();
+        let ear = (0..count).find(|&i| {
+            let prev = projected[remaining[(i + count - 1) % count]];
+            let current = projected[remaining[i]];
+            let next = projected[remaining[(i + 1) % count]];
+            let convex = (current.0 - prev.0) * (next.1 - prev.1)
+                - (current.1 - prev.1) * (next.0 - prev.0)
+                > 0.0;
+            convex
+                && remaining.iter().enumerate().all(|(j, point)| {
+                    j == i
+                        || j == (i + count - 1) % count
+                        || j == (i + 1) % count
+                        || !point_in_triangle(projected[*point], prev, current, next)
+                })
+        });
+
+        match ear {
+            Some(i) => {
+                triangles.push([
+                    remaining[(i + count - 1) % count],
+                    remaining[i],
+                    remaining[(i + 1) % count],
+                ]);
+                remaining.remove(i);
+            }
+            None => {
+                // self intersecting polygon, fall back to a fan
+                triangles.extend(fan(&remaining));
+                return triangles;
+            }
+        }
+    }
+
+    triangles.extend(fan(&remaining));
+    triangles
+}
+
+struct MtlMaterial {
+    diffuse: Vector3,
+    specular: Vector3,
+    emission: Vector3,
+    transmission: Vector3,
+    ior: f32,
+    dissolve: f32,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> MtlMaterial {
+        MtlMaterial {
+            diffuse: Vector3::new(0.8, 0.8, 0.8),
+            specular: Vector3::zero_vector(),
+            emission: Vector3::zero_vector(),
+            transmission: Vector3::new(1.0, 1.0, 1.0),
+            ior: 1.5,
+            dissolve: 1.0,
+        }
+    }
+}
+
+fn max_component(vector: &Vector3) -> f32 {
+    vector.x.max(vector.y).max(vector.z)
+}
+
+impl MtlMaterial {
+    fn to_material(&self) -> Box<dyn Material> {
+        if max_component(&self.emission) > 0.0 {
+            Box::new(EmissiveMaterial::new(&self.emission, 1.0))
+        } else if self.dissolve < 1.0 {
+            Box::new(TransparentMaterial {
+                color: self.transmission,
+                ior: self.ior,
+                absorption: Vector3::zero_vector(),
+            })
+        } else if max_component(&self.specular) > max_component(&self.diffuse) {
+            Box::new(ReflectiveMaterial {
+                color: self.specular,
+            })
+        } else {
+            Box::new(DiffuseMaterial {
+                color: self.diffuse,
+            })
+        }
+    }
+}
+
+fn parse_mtl(
+    source: &str,
+    path: &Path,
+    materials: &mut HashMap<String, MtlMaterial>,
+) -> Result<(), ObjError> {
+    let mut current: Option<String> = None;
+    for (index, line) in source.lines().enumerate() {
+        let parser = LineParser {
+            path,
+            line: index + 1,
+        };
+        let line = line.split('#').next().unwrap_or("");
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let (statement, arguments) = match tokens.split_first() {
+            Some((statement, arguments)) => (*statement, arguments),
+            None => continue,
+        };
+
+        if statement == "newmtl" {
+            if arguments.len() != 1 {
+                return parser.error("'newmtl' expects a name".to_string());
+            }
+            materials.insert(arguments[0].to_string(), MtlMaterial::default());
+            current = Some(arguments[0].to_string());
+            continue;
+        }
+
+        let material = match statement {
+            "Kd" | "Ks" | "Ke" | "Tf" | "Ni" | "d" | "Tr" => match &current {
+                Some(name) => materials.get_mut(name).unwrap(),
+                None => return parser.error(format!("'{}' before 'newmtl'", statement)),
+            },
+            _ => continue,
+        };
+        match statement {
+            "Kd" => material.diffuse = parser.vector(arguments)?,
+            "Ks" => material.specular = parser.vector(arguments)?,
+            "Ke" => material.emission = parser.vector(arguments)?,
+            "Tf" => material.transmission = parser.vector(arguments)?,
+            "Ni" => {
+                let ior = parser.floats(arguments, 1, 1)?[0];
+                if ior <= 0.0 {
+                    return parser.error("'Ni' must be positive".to_string());
+                }
+                material.ior = ior;
+            }
+            "d" => material.dissolve = parser.floats(arguments, 1, 1)?[0].clamp(0.0, 1.0),
+            "Tr" => material.dissolve = 1.0 - parser.floats(arguments, 1, 1)?[0].clamp(0.0, 1.0),
+            _ => unreachable!(),
+        }
+    }
+
+    Ok(())
+}
+
+fn read_file(path: &Path) -> Result<String, ObjError> {
+    std::fs::read_to_string(path).map_err(|error| ObjError::Io {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+impl ObjModel {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ObjModel, ObjError> {
+        let path = path.as_ref();
+        let source = read_file(path)?;
+        ObjModel::parse(&source, path, &mut |mtl_path| read_file(mtl_path))
+    }
+
+    fn parse(
+        source: &str,
+        path: &Path,
+        read_mtl: &mut dyn FnMut(&Path) -> Result<String, ObjError>,
+    ) -> Result<ObjModel, ObjError> {
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut positions: Vec<Vector3> = Vec::new();
+        let mut normals: Vec<Vector3> = Vec::new();
+        let mut num_of_uvs = 0;
+        let mut mtl_materials: HashMap<String, MtlMaterial> = HashMap::new();
+
+        let mut group_name = String::from("default");
+        let mut material_name: Option<String> = None;
+        // meshes are keyed by group and material, in the order they first appear
+        let mut builders: Vec<((String, Option<String>), MeshBuilder)> = Vec::new();
+        let mut current_builder: Option<usize> = None;
+
+        for (index, line) in source.lines().enumerate() {
+            let parser = LineParser {
+                path,
+                line: index + 1,
+            };
+            let line = line.split('#').next().unwrap_or("");
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            let (statement, arguments) = match tokens.split_first() {
+                Some((statement, arguments)) => (*statement, arguments),
+                None => continue,
+            };
+
+            match statement {
+                "v" => {
+                    // an optional w or vertex color may follow the position
+                    let numbers = parser.floats(arguments, 3, 7)?;
+                    positions.push(Vector3::new(numbers[0], numbers[1], numbers[2]));
+                }
+                "vn" => normals.push(parser.vector(arguments)?),
+                "vt" => {
+                    parser.floats(arguments, 1, 3)?;
+                    num_of_uvs += 1;
+                }
+                "g" | "o" => {
+                    group_name = if arguments.is_empty() {
+                        String::from("default")
+                    } else {
+                        arguments.join(" ")
+                    };
+                    current_builder = None;
+                }
+                "usemtl" => {
+                    if arguments.len() != 1 {
+                        return parser.error("'usemtl' expects a name".to_string());
+                    }
+                    if !mtl_materials.contains_key(arguments[0]) {
+                        return parser.error(format!("unknown material '{}'", arguments[0]));
+                    }
+                    material_name = Some(arguments[0].to_string());
+                    current_builder = None;
+                }
+                "mtllib" => {
+                    if arguments.is_empty() {
+                        return parser.error("'mtllib' expects a file name".to_string());
+                    }
+                    for file in arguments {
+                        let mtl_path = directory.join(file);
+                        let mtl_source = read_mtl(&mtl_path)?;
+                        parse_mtl(&mtl_source, &mtl_path, &mut mtl_materials)?;
+                    }
+                }
+                "f" => {
+                    if arguments.len() < 3 {
+                        return parser.error("a face needs at least three vertices".to_string());
+                    }
+                    let mut face = Vec::with_capacity(arguments.len());
+                    for argument in arguments {
+                        let mut parts = argument.split('/');
+                        let position = parser.index(parts.next().unwrap(), positions.len())?;
+                        match parts.next() {
+                            Some("") | None => (),
+                            Some(uv) => {
+                                parser.index(uv, num_of_uvs)?;
+                            }
+                        }
+                        let normal = match parts.next() {
+                            Some("") | None => None,
+                            Some(normal) => Some(parser.index(normal, normals.len())?),
+                        };
+                        if parts.next().is_some() {
+                            return parser.error(format!("invalid face vertex '{}'", argument));
+                        }
+                        face.push(FaceVertex { position, normal });
+                    }
+
+                    let builder_index = match current_builder {
+                        Some(builder_index) => builder_index,
+                        None => {
+                            let key = (group_name.clone(), material_name.clone());
+                            let builder_index =
+                                match builders.iter().position(|(other, _)| *other == key) {
+                                    Some(builder_index) => builder_index,
+                                    None => {
+                                        builders.push((key, MeshBuilder::default()));
+                                        builders.len() - 1
+                                    }
+                                };
+                            current_builder = Some(builder_index);
+                            builder_index
+                        }
+                    };
+                    let builder = &mut builders[builder_index].1;
+                    let points: Vec<Vector3> = face
+                        .iter()
+                        .map(|vertex| positions[vertex.position])
+                        .collect();
+                    for triangle in triangulate(&points) {
+                        for corner in triangle {
+                            let index = builder.add_vertex(face[corner], &positions, &normals);
+                            builder.indices.push(index);
+                        }
+                    }
+                }
+                _ => (), // smoothing groups, lines, points and other statements are ignored
+            }
+        }
+
+        let mut model = ObjModel {
+            groups: Vec::new(),
+            meshes: Vec::new(),
+            materials: Vec::new(),
+        };
+        let mut material_indices: HashMap<Option<String>, usize> = HashMap::new();
+        for ((name, material_name), builder) in builders {
+            let material = *material_indices
+                .entry(material_name.clone())
+                .or_insert_with(|| {
+                    let material = match &material_name {
+                        Some(material_name) => mtl_materials[material_name].to_material(),
+                        None => MtlMaterial::default().to_material(),
+                    };
+                    model.materials.push(material);
+                    model.materials.len() - 1
+                });
+            model.groups.push(ObjGroup {
+                name,
+                mesh: model.meshes.len(),
+                material,
+            });
+            model.meshes.push(builder.build());
+        }
+
+        Ok(model)
+    }
+
+    pub fn add_to_scene<'a>(&'a self, scene: &mut Scene<'a>) {
+        for group in self.groups.iter() {
+            scene.add_entity(Entity {
+                material: self.materials[group.material].as_ref(),
+                shape: &self.meshes[group.mesh],
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod obj_tests {
+    use super::{ObjError, ObjModel};
+    use crate::camera::Ray;
+    use crate::shape::Shape;
+    use crate::tools;
+    use crate::vector::Vector3;
+    use std::path::Path;
+
+    const MTL: &str = "
+newmtl white
+Kd 0.8 0.8 0.8
+newmtl lamp
+Ke 10 10 10
+newmtl glass
+Ni 1.5
+d 0.1
+";
+
+    fn parse(source: &str) -> Result<ObjModel, ObjError> {
+        ObjModel::parse(source, Path::new("models/test.obj"), &mut |path| {
+            assert_eq!(path, Path::new("models/test.mtl"));
+            Ok(MTL.to_string())
+        })
+    }
+
+    fn error_line(source: &str) -> usize {
+        match parse(source) {
+            Err(ObjError::Parse { line, .. }) => line,
+            Err(error) => panic!("unexpected error {}", error),
+            Ok(_) => panic!("parsing should fail"),
+        }
+    }
+
+    #[test]
+    fn groups_test() {
+        let model = parse(
+            "mtllib test.mtl
+v -1 -1 1
+v 1 -1 1
+v 1 1 1
+v -1 1 1
+vt 0 0
+vn 0 0 -1
+g quad
+usemtl white
+f 1/1/1 4/1/1 3/1/1 2/1/1
+g lamp
+usemtl lamp
+f -4 -1 -2
+usemtl glass
+f 1//1 4//1 3//1
+",
+        )
+        .unwrap();
+        assert_eq!(model.groups.len(), 3);
+        assert_eq!(model.groups[0].name, "quad");
+        assert_eq!(model.groups[1].name, "lamp");
+        assert_eq!(model.groups[2].name, "lamp");
+        assert_eq!(model.meshes[0].num_of_triangles(), 2);
+        assert_eq!(model.materials.len(), 3);
+        assert!(model.materials[1].get_emission() == Vector3::new(10.0, 10.0, 10.0));
+
+        let ray = Ray {
+            origin: Vector3::new(0.5, 0.5, 0.0),
+            direction: Vector3::new(0.0, 0.0, 1.0),
+            time: 0.0,
+        };
+        let intersection = model.meshes[0].intersect(&ray);
+        assert!(tools::equal_error(intersection.t, 1.0));
+        assert!(intersection.surface_normal == Vector3::new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn concave_polygon_test() {
+        // an L shaped hexagon, a fan from the first vertex would cover the notch
+        let model = parse(
+            "v 0 0 0
+v 2 0 0
+v 2 1 0
+v 1 1 0
+v 1 2 0
+v 0 2 0
+f 2 3 4 5 6 1
+",
+        )
+        .unwrap();
+        assert_eq!(model.meshes[0].num_of_triangles(), 4);
+        let ray_at = |x: f32, y: f32| Ray {
+            origin: Vector3::new(x, y, 1.0),
+            direction: Vector3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        assert!(model.meshes[0].intersect(&ray_at(0.5, 1.5)).t > 0.0);
+        assert!(model.meshes[0].intersect(&ray_at(1.5, 0.5)).t > 0.0);
+        assert!(model.meshes[0].intersect(&ray_at(1.2, 1.3)).t < 0.0);
+    }
+
+    #[test]
+    fn error_test() {
+        assert_eq!(error_line("v 1 2"), 1);
+        assert_eq!(error_line("v 1 2 3\nv 1 2 3\nf 1 2 3"), 3);
+        assert_eq!(error_line("v 1 2 3\nv 1 2 3\nv 1 2 3\nf 1 2 4"), 4);
+        assert_eq!(error_line("v 1 2 3\nv 1 2 3\nv 1 2 3\nf 1/1 2/1 3/1"), 4);
+        assert_eq!(error_line("v 1 2 3\nv 1 2 3\nv 1 2 3\nf 1 2 -4"), 4);
+        assert_eq!(error_line("vn 0 x 1"), 1);
+        assert_eq!(error_line("mtllib test.mtl\nusemtl missing"), 2);
+        assert!(matches!(
+            ObjModel::load("missing.obj"),
+            Err(ObjError::Io { .. })
+        ));
+    }
+}
@@ -1,71 +1,233 @@
-use crate::camera::Camera;
+use crate::camera::CameraModel;
 use crate::camera::Ray;
-use crate::film::Film;
+use crate::film::{Film, FilmTile};
+use crate::filter::Filter;
+use crate::material::Material;
+use crate::sampler::SamplerType;
 use crate::scene::Scene;
+use crate::shape::ShapeIntersection;
+use crate::tonemap::ToneMapper;
 use crate::tools;
 use crate::tools::Sampler;
 use crate::vector::Vector3;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
 
 pub struct RenderSettings {
     pub image_width: u32,
     pub image_height: u32,
     pub num_of_samples: u32,
+    pub num_of_threads: usize, // 0 uses every available core
+    pub seed: Option<u64>,     // renders with the same seed are identical
+    pub min_depth: u32,        // bounces before paths can be terminated by russian roulette
+    pub max_depth: u32,        // maximum number of bounces of a path
+    pub sampler: SamplerType,
+    pub filter: Filter,          // pixel reconstruction filter
+    pub tone_mapper: ToneMapper, // used when the film is saved as an 8-bit image
 }
 
-pub fn render_scene(scene: &Scene, camera: &Camera, render_settings: &RenderSettings) -> Film {
+const TILE_SIZE: u32 = 16;
+
+impl Default for RenderSettings {
+    fn default() -> RenderSettings {
+        RenderSettings {
+            image_width: 800,
+            image_height: 600,
+            num_of_samples: 16,
+            num_of_threads: 0,
+            seed: None,
+            min_depth: 3,
+            max_depth: 16,
+            sampler: SamplerType::Independent,
+            filter: Filter::default(),
+            tone_mapper: ToneMapper::default(),
+        }
+    }
+}
+
+pub fn render_scene<C: CameraModel + ?Sized>(
+    scene: &Scene,
+    camera: &C,
+    render_settings: &RenderSettings,
+) -> Film {
+    let num_of_threads = match render_settings.num_of_threads {
+        0 => thread::available_parallelism().map_or(1, |threads| threads.get()),
+        threads => threads,
+    };
     println!(
-        "Starting rendering image [{}, {}], with {} samples",
-        render_settings.image_width, render_settings.image_height, render_settings.num_of_samples
+        "Starting rendering image [{}, {}], with {} samples on {} threads",
+        render_settings.image_width,
+        render_settings.image_height,
+        render_settings.num_of_samples,
+        num_of_threads
     );
-    let mut film = Film::new(render_settings.image_width, render_settings.image_height);
-    let mut sampler = Sampler::default();
-
-    let total_samples =
-        render_settings.image_width * render_settings.image_height * render_settings.num_of_samples;
-    for x in 0..render_settings.image_width {
-        for y in 0..render_settings.image_height {
-            for _ in 0..render_settings.num_of_samples {
-                let sample = sampler.get_sample_2d();
-                let film_x = (sample.s + x as f32) / render_settings.image_width as f32;
-                let film_y = (sample.t + y as f32) / render_settings.image_height as f32;
-                let ray = camera.generate_ray(film_x, film_y);
-                let radiance = trace_ray(&ray, scene, &mut sampler);
-                film.add_sample(x, y, &radiance);
-            }
+    let mut film = Film::with_filter(
+        render_settings.image_width,
+        render_settings.image_height,
+        render_settings.filter,
+    );
+    let seed = render_settings.seed.unwrap_or_else(rand::random);
+
+    let mut tiles = Vec::new();
+    for y0 in (0..render_settings.image_height).step_by(TILE_SIZE as usize) {
+        for x0 in (0..render_settings.image_width).step_by(TILE_SIZE as usize) {
+            let x1 = u32::min(x0 + TILE_SIZE, render_settings.image_width);
+            let y1 = u32::min(y0 + TILE_SIZE, render_settings.image_height);
+            tiles.push(Mutex::new(film.create_tile(x0, y0, x1, y1)));
+        }
+    }
+
+    let total_samples = render_settings.image_width as u64
+        * render_settings.image_height as u64
+        * render_settings.num_of_samples as u64;
+    let samples_done = AtomicU64::new(0);
+    let next_tile = AtomicUsize::new(0);
+    thread::scope(|scope| {
+        for _ in 0..num_of_threads {
+            scope.spawn(|| loop {
+                let tile_index = next_tile.fetch_add(1, Ordering::Relaxed);
+                let tile = match tiles.get(tile_index) {
+                    Some(tile) => tile,
+                    None => break,
+                };
+                let mut tile = tile.lock().unwrap();
+                let mut sampler = render_settings
+                    .sampler
+                    .create(render_settings.num_of_samples, seed);
+                render_tile(scene, camera, render_settings, &mut tile, sampler.as_mut());
+
+                let tile_samples =
+                    tile.num_of_pixels() as u64 * render_settings.num_of_samples as u64;
+                let done = samples_done.fetch_add(tile_samples, Ordering::Relaxed) + tile_samples;
+                print!("\rProgress: {} samples left.", total_samples - done);
+            });
         }
-        let samples_done = x * render_settings.image_height * render_settings.num_of_samples;
-        print!("\rProgress: {} samples left.", total_samples - samples_done);
+    });
+
+    for tile in tiles {
+        film.merge_tile(&tile.into_inner().unwrap());
     }
 
     println!("\nDone!");
     film
 }
 
-fn trace_ray(camera_ray: &Ray, scene: &Scene, sampler: &mut Sampler) -> Vector3 {
-    const MAX_DEPTH: u32 = 8;
+fn render_tile<C: CameraModel + ?Sized>(
+    scene: &Scene,
+    camera: &C,
+    render_settings: &RenderSettings,
+    tile: &mut FilmTile,
+    sampler: &mut dyn Sampler,
+) {
+    let (x0, y0, x1, y1) = tile.bounds();
+    for x in x0..x1 {
+        for y in y0..y1 {
+            for sample_index in 0..render_settings.num_of_samples {
+                sampler.start_pixel_sample(x, y, sample_index);
+                let sample = sampler.get_sample_2d();
+                let film_x = sample.s + x as f32;
+                let film_y = sample.t + y as f32;
+                let ray = camera.generate_ray(
+                    film_x / render_settings.image_width as f32,
+                    film_y / render_settings.image_height as f32,
+                    &sampler.get_sample_2d(),
+                    sampler.get_sample(),
+                );
+                let radiance = if ray.direction.is_zero() {
+                    Vector3::zero_vector()
+                } else {
+                    trace_ray(&ray, scene, render_settings, sampler)
+                };
+                tile.add_sample(film_x, film_y, &radiance);
+            }
+        }
+    }
+}
+
+const RAY_EPSILON: f32 = 0.001;
+
+// Sample dimensions read by each part of a path. They are fixed, so that a low discrepancy
+// sampler distributes the same decision of all the samples of a pixel together
+const CAMERA_DIMENSIONS: u32 = 5; // position in the pixel and on the lens, and time
+const LIGHT_DIMENSIONS: u32 = 3; // light selection and position on the light
+const MATERIAL_DIMENSIONS: u32 = 3; // Material::sample_material can read up to 3 dimensions
+const BOUNCE_DIMENSIONS: u32 = LIGHT_DIMENSIONS + MATERIAL_DIMENSIONS + 1; // and russian roulette
+
+fn trace_ray(
+    camera_ray: &Ray,
+    scene: &Scene,
+    render_settings: &RenderSettings,
+    sampler: &mut dyn Sampler,
+) -> Vector3 {
     let mut ray = *camera_ray;
     let mut throughput = Vector3 {
         x: 1.0,
         y: 1.0,
         z: 1.0,
     };
+    let mut radiance = Vector3::zero_vector();
+    // emission reached through a delta bounce cannot be light sampled and gets the full weight
+    let mut specular_bounce = true;
+    let mut previous_point = ray.origin;
+    let mut material_pdf = 0.0;
+    // absorption of the medium the ray travels through, the camera is outside of every object
+    let mut medium = Vector3::zero_vector();
 
-    for _ in 0..MAX_DEPTH {
+    for depth in 0..render_settings.max_depth {
         let intersection = scene.trace(&ray);
         if intersection.shape_intersection.t < 0.0 {
-            return &throughput * &scene.sky;
+            // a ray escaping an absorbing medium travels forever through it, like the light
+            // samples towards lights at infinity
+            throughput *= &transmittance(&medium, f32::INFINITY);
+            let weight = if specular_bounce {
+                1.0
+            } else {
+                power_heuristic(material_pdf, scene.environment_pdf(&ray.direction))
+            };
+            let environment_radiance = scene.environment_radiance(&ray.direction);
+            return &radiance + &(&throughput * &(&environment_radiance * weight));
         }
+        throughput *= &transmittance(&medium, intersection.shape_intersection.t);
 
         let emission = intersection.material.get_emission();
         if !emission.is_zero() {
-            return &throughput * &emission;
+            let weight = if specular_bounce {
+                1.0
+            } else {
+                let light_pdf =
+                    scene.light_pdf(&previous_point, &ray.direction, intersection.shape);
+                power_heuristic(material_pdf, light_pdf)
+            };
+            radiance += &(&throughput * &(&emission * weight));
+            return radiance;
         }
 
-        let material_sample = intersection.material.sample_material(
-            &(-&ray.direction),
-            &intersection.shape_intersection,
-            sampler,
-        );
+        let wo = -&ray.direction;
+        let intersection_point =
+            &ray.origin + &(&ray.direction * intersection.shape_intersection.t);
+
+        let bounce_dimension = CAMERA_DIMENSIONS + depth * BOUNCE_DIMENSIONS;
+        if !intersection.material.is_delta() {
+            sampler.set_dimension(bounce_dimension);
+            let direct_lighting = estimate_direct_lighting(
+                scene,
+                intersection.material,
+                &ray,
+                &intersection_point,
+                &intersection.shape_intersection,
+                &medium,
+                sampler,
+            );
+            radiance += &(&throughput * &direct_lighting);
+        }
+        specular_bounce = intersection.material.is_delta();
+
+        sampler.set_dimension(bounce_dimension + LIGHT_DIMENSIONS);
+        let material_sample =
+            intersection
+                .material
+                .sample_material(&wo, &intersection.shape_intersection, sampler);
         let wi_dot_n = f32::abs(
             material_sample
                 .sample_direction
@@ -80,13 +242,513 @@ fn trace_ray(camera_ray: &Ray, scene: &Scene, sampler: &mut Sampler) -> Vector3
 
         let new_throughput = &material_sample.brdf * (wi_dot_n / material_sample.pdf);
         throughput *= &new_throughput;
+        medium = medium_after_scattering(
+            &medium,
+            intersection.material,
+            &wo,
+            &material_sample.sample_direction,
+            &intersection.shape_intersection.surface_normal,
+        );
+        previous_point = intersection_point;
+        material_pdf = material_sample.pdf;
 
-        let intersection_point =
-            &ray.origin + &(&ray.direction * intersection.shape_intersection.t);
+        // russian roulette, surviving paths are weighted to keep the estimate unbiased
+        if depth + 1 >= render_settings.min_depth {
+            let max_throughput = throughput.x.max(throughput.y).max(throughput.z);
+            let survival_probability = f32::min(0.95, max_throughput);
+            sampler.set_dimension(bounce_dimension + LIGHT_DIMENSIONS + MATERIAL_DIMENSIONS);
+            if sampler.get_sample() >= survival_probability {
+                break;
+            }
+            throughput *= 1.0 / survival_probability;
+        }
 
-        ray.origin = &intersection_point + &(&material_sample.sample_direction * 0.001);
+        ray.origin = &intersection_point + &(&material_sample.sample_direction * RAY_EPSILON);
         ray.direction = material_sample.sample_direction;
     }
 
-    Vector3::zero_vector()
+    radiance
+}
+
+// the ray is the one arriving at the intersection
+fn estimate_direct_lighting(
+    scene: &Scene,
+    material: &dyn Material,
+    ray: &Ray,
+    intersection_point: &Vector3,
+    shape_intersection: &ShapeIntersection,
+    medium: &Vector3,
+    sampler: &mut dyn Sampler,
+) -> Vector3 {
+    let light_sample = scene.sample_light(intersection_point, sampler);
+    if light_sample.pdf <= 0.0 {
+        return Vector3::zero_vector();
+    }
+
+    let wo = -&ray.direction;
+    let brdf = material.eval(&wo, &light_sample.direction, shape_intersection);
+    if brdf.is_zero() {
+        return Vector3::zero_vector();
+    }
+
+    let shadow_origin = intersection_point + &(&light_sample.direction * RAY_EPSILON);
+    if scene.is_occluded(
+        &shadow_origin,
+        &light_sample.direction,
+        light_sample.distance - 2.0 * RAY_EPSILON,
+        ray.time,
+    ) {
+        return Vector3::zero_vector();
+    }
+
+    let cos_theta = f32::abs(
+        light_sample
+            .direction
+            .dot(&shape_intersection.surface_normal),
+    );
+    let material_pdf = material.pdf(&wo, &light_sample.direction, shape_intersection);
+    let weight = if light_sample.is_hittable {
+        power_heuristic(light_sample.pdf, material_pdf)
+    } else {
+        1.0
+    };
+    let light_medium = medium_after_scattering(
+        medium,
+        material,
+        &wo,
+        &light_sample.direction,
+        &shape_intersection.surface_normal,
+    );
+    let radiance = &light_sample.radiance * &transmittance(&light_medium, light_sample.distance);
+    &(&brdf * &radiance) * (cos_theta * weight / light_sample.pdf)
+}
+
+// Beer-Lambert law, the fraction of light left after the distance through the medium. The
+// distance is infinite for lights at infinity, which only channels without absorption go through
+#[inline(always)]
+fn transmittance(absorption: &Vector3, distance: f32) -> Vector3 {
+    if absorption.is_zero() {
+        return Vector3::new(1.0, 1.0, 1.0);
+    }
+    let channel = |absorption: f32| {
+        if absorption == 0.0 {
+            1.0
+        } else {
+            f32::exp(-absorption * distance)
+        }
+    };
+    Vector3 {
+        x: channel(absorption.x),
+        y: channel(absorption.y),
+        z: channel(absorption.z),
+    }
+}
+
+// medium of the direction wi leaving the surface. Reflections stay in the current medium,
+// refractions enter the one enclosed by the surface or go back outside. Objects are not nested
+#[inline(always)]
+fn medium_after_scattering(
+    medium: &Vector3,
+    material: &dyn Material,
+    wo: &Vector3,
+    wi: &Vector3,
+    normal: &Vector3,
+) -> Vector3 {
+    let wi_dot_n = wi.dot(normal);
+    if wo.dot(normal) * wi_dot_n >= 0.0 {
+        *medium
+    } else if wi_dot_n < 0.0 {
+        material.get_absorption()
+    } else {
+        Vector3::zero_vector()
+    }
+}
+
+// multiple importance sampling weight of the strategy with pdf f against the one with pdf g
+#[inline(always)]
+fn power_heuristic(f_pdf: f32, g_pdf: f32) -> f32 {
+    let f2 = f_pdf * f_pdf;
+    let g2 = g_pdf * g_pdf;
+    if f2 + g2 <= 0.0 {
+        return 0.0;
+    }
+    f2 / (f2 + g2)
+}
+
+#[cfg(test)]
+mod renderer_tests {
+    use super::{render_scene, transmittance, RenderSettings};
+    use crate::camera::{Camera, OrthographicCamera};
+    use crate::environment::EnvironmentMap;
+    use crate::light::DirectionalLight;
+    use crate::material::{DiffuseMaterial, EmissiveMaterial, TransparentMaterial};
+    use crate::motion::{MovingShape, Placement};
+    use crate::scene::{Entity, Scene};
+    use crate::scene_file::SceneDescription;
+    use crate::shape::Plane;
+    use crate::vector::Vector3;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    #[test]
+    fn thread_count_independence_test() {
+        let description =
+            SceneDescription::parse(include_str!("../scenes/cornell-box.scene"), Path::new(""))
+                .unwrap();
+        let scene = description.build_scene();
+        let mut render_settings = RenderSettings {
+            image_width: 40,
+            image_height: 30,
+            num_of_samples: 4,
+            num_of_threads: 1,
+            seed: Some(42),
+            ..RenderSettings::default()
+        };
+        let single_thread =
+            render_scene(&scene, description.cameras[0].1.as_ref(), &render_settings);
+        render_settings.num_of_threads = 3;
+        let multi_thread =
+            render_scene(&scene, description.cameras[0].1.as_ref(), &render_settings);
+
+        for x in 0..40 {
+            for y in 0..30 {
+                let a = single_thread.pixel_radiance(x, y);
+                let b = multi_thread.pixel_radiance(x, y);
+                assert_eq!((a.x, a.y, a.z), (b.x, b.y, b.z));
+            }
+        }
+
+        // another seed gives another image
+        render_settings.seed = Some(43);
+        let other_seed = render_scene(&scene, description.cameras[0].1.as_ref(), &render_settings);
+        assert!((0..40).any(|x| (0..30)
+            .any(|y| other_seed.pixel_radiance(x, y) != single_thread.pixel_radiance(x, y))));
+    }
+
+    #[test]
+    fn orthographic_test() {
+        // the emissive square covers the middle half of the view, without perspective
+        let plane = Plane::new(
+            Vector3::zero_vector(),
+            Vector3::new(0.0, 1.0, 0.0),
+            Vector3::new(0.0, 0.0, 1.0),
+            2.0,
+            2.0,
+        );
+        let material = EmissiveMaterial::new(&Vector3::new(1.0, 1.0, 1.0), 1.0);
+        let mut scene = Scene::new(Vector3::zero_vector());
+        scene.add_entity(Entity {
+            material: &material,
+            shape: &plane,
+        });
+        let camera = OrthographicCamera::look_at(
+            4.0,
+            1.0,
+            Vector3::new(0.0, 10.0, 0.0),
+            Vector3::zero_vector(),
+            Vector3::new(0.0, 0.0, 1.0),
+        );
+        let render_settings = RenderSettings {
+            image_width: 16,
+            image_height: 16,
+            num_of_samples: 4,
+            num_of_threads: 1,
+            seed: Some(3),
+            ..RenderSettings::default()
+        };
+
+        let film = render_scene(&scene, &camera, &render_settings);
+        for x in 0..16 {
+            for y in 0..16 {
+                let inside = (4..12).contains(&x) && (4..12).contains(&y);
+                let expected = if inside { 1.0 } else { 0.0 };
+                assert_eq!(film.pixel_radiance(x, y).x, expected, "pixel {} {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn motion_blur_test() {
+        // the emissive square moves by its width while the shutter is open, its pixels are lit
+        // for the fraction of the shutter interval they see it
+        let plane = Plane::new(
+            Vector3::zero_vector(),
+            Vector3::new(0.0, 1.0, 0.0),
+            Vector3::new(0.0, 0.0, 1.0),
+            2.0,
+            2.0,
+        );
+        let moving = MovingShape::new(
+            Arc::new(plane),
+            Vector3::new(0.0, 1.0, 0.0),
+            Placement::identity(),
+            Placement {
+                translation: Vector3::new(2.0, 0.0, 0.0),
+                angle: 0.0,
+            },
+        );
+        let material = EmissiveMaterial::new(&Vector3::new(1.0, 1.0, 1.0), 1.0);
+        let mut scene = Scene::new(Vector3::zero_vector());
+        scene.add_entity(Entity {
+            material: &material,
+            shape: &moving,
+        });
+        let camera = OrthographicCamera::look_at(
+            8.0,
+            1.0,
+            Vector3::new(0.0, 10.0, 0.0),
+            Vector3::zero_vector(),
+            Vector3::new(0.0, 0.0, 1.0),
+        );
+        let render_settings = RenderSettings {
+            image_width: 16,
+            image_height: 16,
+            num_of_samples: 256,
+            num_of_threads: 1,
+            seed: Some(5),
+            ..RenderSettings::default()
+        };
+
+        // pixel x covers [0.5 x - 4, 0.5 x - 3.5] in the scene
+        let film = render_scene(&scene, &camera, &render_settings);
+        let row: Vec<f32> = (0..16).map(|x| film.pixel_radiance(x, 8).x).collect();
+        let sum: f32 = row.iter().sum();
+        assert!((sum - 4.0).abs() < 0.1, "{:?}", row);
+        assert!((row[9] - 0.875).abs() < 0.05, "{:?}", row);
+        assert!((row[10] - 0.875).abs() < 0.05, "{:?}", row);
+        assert!((row[7] - 0.375).abs() < 0.05, "{:?}", row);
+        assert_eq!(row[5], 0.0);
+        assert_eq!(row[14], 0.0);
+    }
+
+    #[test]
+    fn russian_roulette_test() {
+        // a diffuse plane under a white sky reflects its albedo, whatever the path termination
+        let plane = Plane::new(
+            Vector3::zero_vector(),
+            Vector3::new(0.0, 1.0, 0.0),
+            Vector3::new(0.0, 0.0, 1.0),
+            1000.0,
+            1000.0,
+        );
+        let material = DiffuseMaterial {
+            color: Vector3::new(0.5, 0.5, 0.5),
+        };
+        let mut scene = Scene::new(Vector3::new(1.0, 1.0, 1.0));
+        scene.add_entity(Entity {
+            material: &material,
+            shape: &plane,
+        });
+        let camera = Camera::new(
+            std::f32::consts::PI / 4.0,
+            1.0,
+            Vector3::new(0.0, 1.0, 0.0),
+            Vector3::new(0.0, -1.0, 0.0),
+            Vector3::new(0.0, 0.0, 1.0),
+        );
+        let render_settings = RenderSettings {
+            image_width: 16,
+            image_height: 16,
+            num_of_samples: 64,
+            num_of_threads: 1,
+            seed: Some(7),
+            min_depth: 0,
+            max_depth: 16,
+            ..RenderSettings::default()
+        };
+
+        let film = render_scene(&scene, &camera, &render_settings);
+        let mut sum = 0.0;
+        for x in 0..16 {
+            for y in 0..16 {
+                sum += film.pixel_radiance(x, y).x;
+            }
+        }
+        let mean = sum / 256.0;
+        assert!((mean - 0.5).abs() < 0.01, "mean radiance {}", mean);
+    }
+
+    #[test]
+    fn absorption_test() {
+        // a slab of thickness 2 without refraction keeps exp(-absorption distance) of the white
+        // sky, the distance being 2 for rays orthogonal to the slab and more for the others
+        let front = Plane::new(
+            Vector3::new(0.0, 0.0, 1.0),
+            Vector3::new(0.0, 0.0, -1.0),
+            Vector3::new(0.0, 1.0, 0.0),
+            1000.0,
+            1000.0,
+        );
+        let back = Plane::new(
+            Vector3::new(0.0, 0.0, 3.0),
+            Vector3::new(0.0, 0.0, 1.0),
+            Vector3::new(0.0, 1.0, 0.0),
+            1000.0,
+            1000.0,
+        );
+        let material = TransparentMaterial {
+            color: Vector3::new(1.0, 1.0, 1.0),
+            ior: 1.0,
+            absorption: Vector3::new(0.5, 0.25, 0.0),
+        };
+        let mut scene = Scene::new(Vector3::new(1.0, 1.0, 1.0));
+        for shape in [&front, &back] {
+            scene.add_entity(Entity {
+                material: &material,
+                shape,
+            });
+        }
+        let camera = Camera::new(
+            std::f32::consts::PI / 2.0,
+            1.0,
+            Vector3::zero_vector(),
+            Vector3::new(0.0, 0.0, 1.0),
+            Vector3::new(0.0, 1.0, 0.0),
+        );
+        let render_settings = RenderSettings {
+            image_width: 4,
+            image_height: 4,
+            num_of_samples: 1,
+            num_of_threads: 1,
+            seed: Some(3),
+            ..RenderSettings::default()
+        };
+
+        let film = render_scene(&scene, &camera, &render_settings);
+        for x in 0..4 {
+            for y in 0..4 {
+                let radiance = film.pixel_radiance(x, y);
+                assert!(radiance.x > 0.0 && radiance.x <= f32::exp(-1.0) + 1e-4);
+                assert!((radiance.x.ln() - 2.0 * radiance.y.ln()).abs() < 1e-3);
+                assert!((radiance.z - 1.0).abs() < 1e-4, "{:?}", radiance);
+            }
+        }
+
+        // lights at infinity only go through the channels without absorption, also when the
+        // environment is seen through a single surface which leaves the ray in the medium
+        let absorption = Vector3::new(0.5, 0.0, 0.0);
+        assert!(transmittance(&absorption, f32::INFINITY) == Vector3::new(0.0, 1.0, 1.0));
+        let material = TransparentMaterial {
+            color: Vector3::new(1.0, 1.0, 1.0),
+            ior: 1.0,
+            absorption,
+        };
+        let environment =
+            EnvironmentMap::new(8, 4, vec![Vector3::new(1.0, 1.0, 1.0); 32], 1.0, 0.0);
+        let mut scene = Scene::new(Vector3::zero_vector());
+        scene.add_entity(Entity {
+            material: &material,
+            shape: &front,
+        });
+        scene.set_environment(&environment);
+        let film = render_scene(&scene, &camera, &render_settings);
+        for x in 0..4 {
+            for y in 0..4 {
+                let radiance = film.pixel_radiance(x, y);
+                assert!(radiance == Vector3::new(0.0, 1.0, 1.0), "{:?}", radiance);
+            }
+        }
+    }
+
+    #[test]
+    fn directional_light_test() {
+        // a diffuse plane facing a light of irradiance pi reflects its albedo, a sun disk only
+        // softens the shadows
+        let plane = Plane::new(
+            Vector3::zero_vector(),
+            Vector3::new(0.0, 1.0, 0.0),
+            Vector3::new(0.0, 0.0, 1.0),
+            1000.0,
+            1000.0,
+        );
+        let material = DiffuseMaterial {
+            color: Vector3::new(0.5, 0.5, 0.5),
+        };
+        let camera = Camera::new(
+            std::f32::consts::PI / 2.0,
+            1.0,
+            Vector3::new(0.0, 1.0, 0.0),
+            Vector3::new(0.0, -1.0, 0.0),
+            Vector3::new(0.0, 0.0, 1.0),
+        );
+        let render_settings = RenderSettings {
+            image_width: 8,
+            image_height: 8,
+            num_of_samples: 16,
+            num_of_threads: 1,
+            seed: Some(5),
+            ..RenderSettings::default()
+        };
+        for angular_diameter in [0.0, 0.1] {
+            let light = DirectionalLight::new(
+                Vector3::new(0.0, -1.0, 0.0),
+                &Vector3::new(1.0, 1.0, 1.0),
+                std::f32::consts::PI,
+                angular_diameter,
+            );
+            let mut scene = Scene::new(Vector3::zero_vector());
+            scene.add_entity(Entity {
+                material: &material,
+                shape: &plane,
+            });
+            scene.add_light(&light);
+
+            let film = render_scene(&scene, &camera, &render_settings);
+            for x in 0..8 {
+                for y in 0..8 {
+                    let radiance = film.pixel_radiance(x, y).x;
+                    assert!((radiance - 0.5).abs() < 0.01, "radiance {}", radiance);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn environment_map_test() {
+        // the white environment seen by the diffuse plane is split between light and material
+        // samples, which still sum to the albedo
+        let plane = Plane::new(
+            Vector3::zero_vector(),
+            Vector3::new(0.0, 1.0, 0.0),
+            Vector3::new(0.0, 0.0, 1.0),
+            1000.0,
+            1000.0,
+        );
+        let material = DiffuseMaterial {
+            color: Vector3::new(0.5, 0.5, 0.5),
+        };
+        let pixels = vec![Vector3::new(1.0, 1.0, 1.0); 8 * 4];
+        let environment = EnvironmentMap::new(8, 4, pixels, 1.0, 0.0);
+        let mut scene = Scene::new(Vector3::zero_vector());
+        scene.add_entity(Entity {
+            material: &material,
+            shape: &plane,
+        });
+        scene.set_environment(&environment);
+        let camera = Camera::new(
+            std::f32::consts::PI / 4.0,
+            1.0,
+            Vector3::new(0.0, 1.0, 0.0),
+            Vector3::new(0.0, -1.0, 0.0),
+            Vector3::new(0.0, 0.0, 1.0),
+        );
+        let render_settings = RenderSettings {
+            image_width: 16,
+            image_height: 16,
+            num_of_samples: 64,
+            num_of_threads: 1,
+            seed: Some(9),
+            ..RenderSettings::default()
+        };
+
+        let film = render_scene(&scene, &camera, &render_settings);
+        let mut sum = 0.0;
+        for x in 0..16 {
+            for y in 0..16 {
+                sum += film.pixel_radiance(x, y).x;
+            }
+        }
+        let mean = sum / 256.0;
+        assert!((mean - 0.5).abs() < 0.01, "mean radiance {}", mean);
+    }
 }
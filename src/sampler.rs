@@ -0,0 +1,517 @@
+use crate::tools::{mix_bits, Pcg32, Sample2D, Sampler};
+
+// Sampler used by a render, see SamplerType::create
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SamplerType {
+    Independent, // uniform random numbers
+    Stratified,  // one jittered stratum of each dimension per pixel sample
+    Halton,      // Owen scrambled Halton sequence
+    Sobol,       // Owen scrambled Sobol (0, 2) sequence, padded with shuffled copies
+}
+
+impl SamplerType {
+    pub fn from_name(name: &str) -> Option<SamplerType> {
+        match name {
+            "independent" => Some(SamplerType::Independent),
+            "stratified" => Some(SamplerType::Stratified),
+            "halton" => Some(SamplerType::Halton),
+            "sobol" => Some(SamplerType::Sobol),
+            _ => None,
+        }
+    }
+
+    pub fn create(&self, samples_per_pixel: u32, seed: u64) -> Box<dyn Sampler> {
+        match self {
+            SamplerType::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerType::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel, seed)),
+            SamplerType::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerType::Sobol => Box::new(SobolSampler::new(seed)),
+        }
+    }
+}
+
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+#[inline(always)]
+fn to_unit_float(value: u32) -> f32 {
+    (value >> 8) as f32 * (1.0 / 16777216.0)
+}
+
+fn pixel_hash(seed: u64, x: u32, y: u32) -> u64 {
+    mix_bits((((x as u64) << 32) | y as u64) ^ mix_bits(seed))
+}
+
+// scrambling seed of a dimension, the same for every sample of a pixel
+#[inline(always)]
+fn dimension_hash(pixel_hash: u64, dimension: u32) -> u64 {
+    mix_bits(pixel_hash ^ dimension as u64)
+}
+
+#[inline(always)]
+fn hashed_uniform(hash: u64, index: u32) -> f32 {
+    to_unit_float((mix_bits(hash ^ ((index as u64) << 32)) >> 32) as u32)
+}
+
+// i-th element of a random permutation of [0, length) chosen by the hash, Kensler's
+// "Correlated Multi-Jittered Sampling"
+fn permutation_element(i: u32, length: u32, hash: u32) -> u32 {
+    debug_assert!(i < length);
+    let mut mask = length - 1;
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+    let mut i = i;
+    // cycle walking until the permuted value is in range
+    loop {
+        i ^= hash;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= hash >> 16;
+        i ^= (i & mask) >> 4;
+        i ^= hash >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= hash >> 23;
+        i ^= (i & mask) >> 1;
+        i = i.wrapping_mul(1 | hash >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & mask) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= mask;
+        i ^= i >> 5;
+        if i < length {
+            break;
+        }
+    }
+    ((i as u64 + hash as u64) % length as u64) as u32
+}
+
+// numbers reserved for one pixel sample, a path never gets close to it
+const SAMPLE_STREAM_LENGTH: u64 = 1 << 16;
+
+// Uniform random numbers, each pixel sample reads its own part of a PCG32 stream so the
+// numbers do not depend on the order pixels are rendered in
+pub struct IndependentSampler {
+    seed: u64,
+    pixel_sample_start: Pcg32,
+    rgen: Pcg32,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> IndependentSampler {
+        let rgen = Pcg32::new(mix_bits(seed), seed);
+        IndependentSampler {
+            seed,
+            pixel_sample_start: rgen,
+            rgen,
+        }
+    }
+}
+
+impl Default for IndependentSampler {
+    fn default() -> Self {
+        IndependentSampler::new(rand::random())
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+        self.pixel_sample_start = Pcg32::new(pixel_hash(self.seed, x, y), self.seed);
+        self.pixel_sample_start
+            .advance(sample_index as u64 * SAMPLE_STREAM_LENGTH);
+        self.rgen = self.pixel_sample_start;
+    }
+
+    fn set_dimension(&mut self, dimension: u32) {
+        self.rgen = self.pixel_sample_start;
+        self.rgen.advance(dimension as u64);
+    }
+
+    #[inline(always)]
+    fn get_sample(&mut self) -> f32 {
+        self.rgen.next_f32()
+    }
+
+    #[inline(always)]
+    fn get_sample_2d(&mut self) -> Sample2D {
+        Sample2D {
+            s: self.get_sample(),
+            t: self.get_sample(),
+        }
+    }
+}
+
+// Jittered stratification: the samples of a pixel fall in different strata of every dimension,
+// 1D dimensions have samples_per_pixel strata and 2D ones a square grid of at least as many
+pub struct StratifiedSampler {
+    samples_per_pixel: u32,
+    seed: u64,
+    pixel_hash: u64,
+    sample_index: u32,
+    dimension: u32,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: u32, seed: u64) -> StratifiedSampler {
+        debug_assert!(samples_per_pixel > 0);
+        StratifiedSampler {
+            samples_per_pixel,
+            seed,
+            pixel_hash: pixel_hash(seed, 0, 0),
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    // stratum of the current sample among num_of_strata, and the hash of its jitter
+    fn stratum(&self, num_of_strata: u32) -> (u32, u64) {
+        // samples past samples_per_pixel start another round of strata
+        let round = self.sample_index / self.samples_per_pixel;
+        let index = self.sample_index % self.samples_per_pixel;
+        let hash = mix_bits(dimension_hash(self.pixel_hash, self.dimension) ^ round as u64);
+        let stratum = permutation_element(index, num_of_strata, hash as u32);
+        (stratum, hash)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+        self.pixel_hash = pixel_hash(self.seed, x, y);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn set_dimension(&mut self, dimension: u32) {
+        self.dimension = dimension;
+    }
+
+    fn get_sample(&mut self) -> f32 {
+        let (stratum, hash) = self.stratum(self.samples_per_pixel);
+        self.dimension += 1;
+        let jitter = hashed_uniform(hash, self.sample_index);
+        f32::min(
+            (stratum as f32 + jitter) / self.samples_per_pixel as f32,
+            ONE_MINUS_EPSILON,
+        )
+    }
+
+    fn get_sample_2d(&mut self) -> Sample2D {
+        // strata in excess of samples_per_pixel are left empty, which keeps the samples uniform
+        let resolution = (self.samples_per_pixel as f32).sqrt().ceil() as u32;
+        let (stratum, hash) = self.stratum(resolution * resolution);
+        self.dimension += 2;
+        let jitter_s = hashed_uniform(hash, 2 * self.sample_index);
+        let jitter_t = hashed_uniform(hash, 2 * self.sample_index + 1);
+        Sample2D {
+            s: f32::min(
+                ((stratum % resolution) as f32 + jitter_s) / resolution as f32,
+                ONE_MINUS_EPSILON,
+            ),
+            t: f32::min(
+                ((stratum / resolution) as f32 + jitter_t) / resolution as f32,
+                ONE_MINUS_EPSILON,
+            ),
+        }
+    }
+}
+
+const PRIMES: [u32; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+    197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307,
+    311,
+];
+
+// radical inverse of index in the given base, with the digits permuted depending on the digits
+// before them (Owen scrambling)
+fn owen_scrambled_radical_inverse(base: u32, index: u64, hash: u32) -> f32 {
+    let inv_base = 1.0 / base as f32;
+    let mut inv_base_m = 1.0;
+    let mut reversed_digits: u64 = 0;
+    let mut index = index;
+    // stop once the digits are below the f32 precision
+    while 1.0 - (base - 1) as f32 * inv_base_m < 1.0 {
+        let next = index / base as u64;
+        let digit = (index - next * base as u64) as u32;
+        let digit_hash = mix_bits(hash as u64 ^ reversed_digits) as u32;
+        let digit = permutation_element(digit, base, digit_hash);
+        reversed_digits = reversed_digits * base as u64 + digit as u64;
+        inv_base_m *= inv_base;
+        index = next;
+    }
+    f32::min(inv_base_m * reversed_digits as f32, ONE_MINUS_EPSILON)
+}
+
+// Halton sequence of each pixel, dimension i uses the radical inverse in the i-th prime base.
+// The first PRIMES.len() dimensions are covered, the following ones are uniform random
+pub struct HaltonSampler {
+    seed: u64,
+    pixel_hash: u64,
+    sample_index: u32,
+    dimension: u32,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> HaltonSampler {
+        HaltonSampler {
+            seed,
+            pixel_hash: pixel_hash(seed, 0, 0),
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+        self.pixel_hash = pixel_hash(self.seed, x, y);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn set_dimension(&mut self, dimension: u32) {
+        self.dimension = dimension;
+    }
+
+    fn get_sample(&mut self) -> f32 {
+        let hash = dimension_hash(self.pixel_hash, self.dimension);
+        let sample = match PRIMES.get(self.dimension as usize) {
+            Some(&base) => {
+                owen_scrambled_radical_inverse(base, self.sample_index as u64, hash as u32)
+            }
+            None => hashed_uniform(hash, self.sample_index),
+        };
+        self.dimension += 1;
+        sample
+    }
+
+    fn get_sample_2d(&mut self) -> Sample2D {
+        Sample2D {
+            s: self.get_sample(),
+            t: self.get_sample(),
+        }
+    }
+}
+
+// hash based nested uniform scrambling of the bits of x, from Burley's "Practical Hash-based
+// Owen Scrambling"
+#[inline(always)]
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits();
+    x ^= x.wrapping_mul(0x3d20adea);
+    x = x.wrapping_add(seed);
+    x = x.wrapping_mul((seed >> 16) | 1);
+    x ^= x.wrapping_mul(0x05526c56);
+    x ^= x.wrapping_mul(0x53a22864);
+    x.reverse_bits()
+}
+
+// second dimension of the Sobol sequence, the first one is index.reverse_bits()
+fn sobol_second_dimension(index: u32) -> u32 {
+    let mut sample = 0;
+    let mut direction: u32 = 1 << 31;
+    let mut index = index;
+    while index != 0 {
+        if index & 1 == 1 {
+            sample ^= direction;
+        }
+        index >>= 1;
+        direction ^= direction >> 1;
+    }
+    sample
+}
+
+// Every 1D or 2D request reads the first one or two dimensions of the Sobol sequence, with the
+// sample order shuffled and the points Owen scrambled by a hash of the pixel and dimension.
+// Each pair of dimensions is a (0, 2) sequence, best with a power of two samples per pixel
+pub struct SobolSampler {
+    seed: u64,
+    pixel_hash: u64,
+    sample_index: u32,
+    dimension: u32,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> SobolSampler {
+        SobolSampler {
+            seed,
+            pixel_hash: pixel_hash(seed, 0, 0),
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    // scrambled sample index and the hash of the scrambling of the current dimension
+    fn shuffled_index(&self) -> (u32, u64) {
+        let hash = dimension_hash(self.pixel_hash, self.dimension);
+        (
+            nested_uniform_scramble(self.sample_index, hash as u32),
+            hash,
+        )
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: u32) {
+        self.pixel_hash = pixel_hash(self.seed, x, y);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn set_dimension(&mut self, dimension: u32) {
+        self.dimension = dimension;
+    }
+
+    fn get_sample(&mut self) -> f32 {
+        let (index, hash) = self.shuffled_index();
+        self.dimension += 1;
+        to_unit_float(nested_uniform_scramble(
+            index.reverse_bits(),
+            (hash >> 32) as u32,
+        ))
+    }
+
+    fn get_sample_2d(&mut self) -> Sample2D {
+        let (index, hash) = self.shuffled_index();
+        self.dimension += 2;
+        let t_seed = mix_bits(hash) as u32;
+        Sample2D {
+            s: to_unit_float(nested_uniform_scramble(
+                index.reverse_bits(),
+                (hash >> 32) as u32,
+            )),
+            t: to_unit_float(nested_uniform_scramble(
+                sobol_second_dimension(index),
+                t_seed,
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod sampler_tests {
+    use super::{permutation_element, SamplerType};
+
+    const SAMPLER_TYPES: [SamplerType; 4] = [
+        SamplerType::Independent,
+        SamplerType::Stratified,
+        SamplerType::Halton,
+        SamplerType::Sobol,
+    ];
+
+    #[test]
+    fn main_test() {
+        for sampler_type in SAMPLER_TYPES {
+            let mut sampler = sampler_type.create(16, 1234);
+            let mut other_sampler = sampler_type.create(16, 1234);
+
+            // a pixel sample gets the same numbers whatever was drawn before
+            other_sampler.start_pixel_sample(5, 9, 3);
+            let numbers: Vec<f32> = (0..8).map(|_| other_sampler.get_sample()).collect();
+            for _ in 0..100 {
+                sampler.get_sample();
+            }
+            sampler.start_pixel_sample(5, 9, 3);
+            assert!((0..8).all(|i| sampler.get_sample() == numbers[i]));
+            sampler.set_dimension(5);
+            assert_eq!(sampler.get_sample(), numbers[5], "{:?}", sampler_type);
+
+            // and different pixels get different numbers
+            sampler.start_pixel_sample(9, 5, 3);
+            assert!(sampler.get_sample() != numbers[0]);
+
+            // every dimension is uniform, including the ones past the Halton primes
+            for dimension in [0, 1, 7, 100] {
+                let mut sum = 0.0;
+                for pixel in 0..64 {
+                    for sample_index in 0..16 {
+                        sampler.start_pixel_sample(pixel, 0, sample_index);
+                        sampler.set_dimension(dimension);
+                        let sample = sampler.get_sample_2d();
+                        assert!((0.0..1.0).contains(&sample.s) && (0.0..1.0).contains(&sample.t));
+                        sum += sample.s + sample.t;
+                    }
+                }
+                let mean = sum / (2.0 * 64.0 * 16.0);
+                assert!((mean - 0.5).abs() < 0.02, "{:?} {}", sampler_type, mean);
+            }
+        }
+    }
+
+    #[test]
+    fn stratification_test() {
+        // the 16 samples of a pixel have one point in each 4x4 cell and in each of 16 intervals
+        for sampler_type in [SamplerType::Stratified, SamplerType::Sobol] {
+            let mut sampler = sampler_type.create(16, 7);
+            for dimension in [0, 2, 9] {
+                let mut cells = [0; 16];
+                let mut intervals = [0; 16];
+                for sample_index in 0..16 {
+                    sampler.start_pixel_sample(3, 4, sample_index);
+                    sampler.set_dimension(dimension);
+                    let sample = sampler.get_sample_2d();
+                    cells[(sample.s * 4.0) as usize * 4 + (sample.t * 4.0) as usize] += 1;
+                    sampler.set_dimension(dimension);
+                    intervals[(sampler.get_sample() * 16.0) as usize] += 1;
+                }
+                assert!(cells.iter().all(|&count| count == 1), "{:?}", sampler_type);
+                assert!(
+                    intervals.iter().all(|&count| count == 1),
+                    "{:?}",
+                    sampler_type
+                );
+            }
+        }
+
+        // the first 6 Halton points fill each cell of 2 by 3 in the first two dimensions
+        let mut sampler = SamplerType::Halton.create(6, 7);
+        let mut cells = [0; 6];
+        for sample_index in 0..6 {
+            sampler.start_pixel_sample(3, 4, sample_index);
+            let sample = sampler.get_sample_2d();
+            cells[(sample.s * 2.0) as usize * 3 + (sample.t * 3.0) as usize] += 1;
+        }
+        assert!(cells.iter().all(|&count| count == 1));
+
+        let mut permutation: Vec<u32> = (0..10).map(|i| permutation_element(i, 10, 42)).collect();
+        permutation.sort();
+        assert!(permutation == (0..10).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn convergence_test() {
+        // low discrepancy samplers integrate a smooth function with a smaller error
+        let function = |s: f32, t: f32| (s * t * 3.0).sin() + s * s;
+        let reference = 0.852_066;
+        let squared_error = |sampler_type: SamplerType| {
+            let mut sampler = sampler_type.create(64, 3);
+            let mut squared_error = 0.0;
+            for pixel in 0..100 {
+                let mut sum = 0.0;
+                for sample_index in 0..64 {
+                    sampler.start_pixel_sample(pixel, 0, sample_index);
+                    let sample = sampler.get_sample_2d();
+                    sum += function(sample.s, sample.t);
+                }
+                squared_error += (sum / 64.0 - reference) * (sum / 64.0 - reference);
+            }
+            squared_error
+        };
+        let independent_error = squared_error(SamplerType::Independent);
+        for sampler_type in [
+            SamplerType::Stratified,
+            SamplerType::Halton,
+            SamplerType::Sobol,
+        ] {
+            assert!(
+                squared_error(sampler_type) < 0.25 * independent_error,
+                "{:?}",
+                sampler_type
+            );
+        }
+    }
+}
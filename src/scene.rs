@@ -1,9 +1,14 @@
+use crate::bvh::{Aabb, Bvh};
 use crate::camera::Ray;
+use crate::light::{EnvironmentLight, Light, LightSample};
 use crate::material::Material;
 use crate::material::NoMaterial;
+use crate::shape::NoShape;
 use crate::shape::Shape;
 use crate::shape::ShapeIntersection;
+use crate::tools::Sampler;
 use crate::vector::Vector3;
+use std::sync::OnceLock;
 
 #[derive(Copy, Clone)]
 pub struct Entity<'a> {
@@ -12,13 +17,18 @@ pub struct Entity<'a> {
 }
 
 pub struct Scene<'a> {
-    pub sky: Vector3,
+    pub sky: Vector3, // radiance of the rays leaving the scene without an environment light
     entities: Vec<Entity<'a>>,
+    emissive_entities: Vec<Entity<'a>>,
+    lights: Vec<&'a dyn Light>,
+    environment: Option<&'a dyn EnvironmentLight>,
+    bvh: OnceLock<Bvh>, // built on the first trace after the entities change
 }
 
 pub struct EntityIntersection<'a> {
     pub shape_intersection: ShapeIntersection,
     pub material: &'a dyn Material,
+    pub shape: &'a dyn Shape,
 }
 
 impl<'a> Default for EntityIntersection<'a> {
@@ -26,6 +36,7 @@ impl<'a> Default for EntityIntersection<'a> {
         EntityIntersection {
             shape_intersection: ShapeIntersection::default(),
             material: &NoMaterial,
+            shape: &NoShape,
         }
     }
 }
@@ -34,27 +45,158 @@ impl<'a> Scene<'a> {
     pub fn new(sky: Vector3) -> Scene<'a> {
         Scene {
             entities: Vec::new(),
+            emissive_entities: Vec::new(),
+            lights: Vec::new(),
+            environment: None,
+            bvh: OnceLock::new(),
             sky,
         }
     }
 
     pub fn add_entity(&mut self, entity: Entity<'a>) {
         self.entities.push(entity);
+        if !entity.material.get_emission().is_zero() {
+            self.emissive_entities.push(entity);
+        }
+        self.bvh = OnceLock::new();
+    }
+
+    pub fn add_light(&mut self, light: &'a dyn Light) {
+        self.lights.push(light);
+    }
+
+    // replaces the constant sky
+    pub fn set_environment(&mut self, environment: &'a dyn EnvironmentLight) {
+        self.environment = Some(environment);
+    }
+
+    fn num_of_lights(&self) -> usize {
+        self.emissive_entities.len() + self.lights.len() + self.environment.iter().count()
     }
 
-    pub fn trace(&self, ray: &Ray) -> EntityIntersection {
+    fn bvh(&self) -> &Bvh {
+        self.bvh.get_or_init(|| {
+            let bounds: Vec<Aabb> = self
+                .entities
+                .iter()
+                .map(|entity| entity.shape.bounds())
+                .collect();
+            Bvh::build(&bounds)
+        })
+    }
+
+    pub fn trace(&self, ray: &Ray) -> EntityIntersection<'_> {
         let mut t = f32::MAX;
         let mut entity_intersection = EntityIntersection::default();
 
-        for entity in self.entities.iter() {
+        self.bvh().traverse(ray, |index| {
+            let entity = &self.entities[index];
             let intersection = entity.shape.intersect(ray);
+            let intersection_t = intersection.t;
             if intersection.t >= 0.0 && intersection.t < t {
                 t = intersection.t;
                 entity_intersection.shape_intersection = intersection;
                 entity_intersection.material = entity.material;
+                entity_intersection.shape = entity.shape;
             }
-        }
+            intersection_t
+        });
 
         entity_intersection
     }
+
+    // pick one of the emissive entities, lights or the environment uniformly and sample a point on
+    // it
+    pub fn sample_light(
+        &self,
+        reference_point: &Vector3,
+        sampler: &mut dyn Sampler,
+    ) -> LightSample {
+        let num_of_lights = self.num_of_lights();
+        if num_of_lights == 0 {
+            return LightSample::invalid_sample();
+        }
+
+        let light_index = usize::min(
+            (sampler.get_sample() * num_of_lights as f32) as usize,
+            num_of_lights - 1,
+        );
+        if light_index >= self.emissive_entities.len() {
+            let light: &dyn Light =
+                match self.lights.get(light_index - self.emissive_entities.len()) {
+                    Some(light) => *light,
+                    None => self.environment.unwrap(),
+                };
+            let mut light_sample = light.sample(reference_point, &sampler.get_sample_2d());
+            light_sample.pdf /= num_of_lights as f32;
+            return light_sample;
+        }
+
+        let light = &self.emissive_entities[light_index];
+        let shape_sample = light
+            .shape
+            .sample(reference_point, &sampler.get_sample_2d());
+        if shape_sample.pdf <= 0.0 {
+            return LightSample::invalid_sample();
+        }
+
+        let to_light = &shape_sample.point - reference_point;
+        let distance = to_light.length();
+        if distance <= 0.0 {
+            return LightSample::invalid_sample();
+        }
+        LightSample {
+            direction: &to_light / distance,
+            distance,
+            radiance: light.material.get_emission(),
+            pdf: shape_sample.pdf / num_of_lights as f32,
+            is_hittable: true,
+        }
+    }
+
+    // pdf of sample_light returning the direction towards the emissive shape
+    pub fn light_pdf(
+        &self,
+        reference_point: &Vector3,
+        direction: &Vector3,
+        shape: &dyn Shape,
+    ) -> f32 {
+        if self.emissive_entities.is_empty() {
+            return 0.0;
+        }
+        shape.pdf(reference_point, direction) / self.num_of_lights() as f32
+    }
+
+    // radiance of a ray leaving the scene
+    pub fn environment_radiance(&self, direction: &Vector3) -> Vector3 {
+        match self.environment {
+            Some(environment) => environment.radiance(direction),
+            None => self.sky,
+        }
+    }
+
+    // pdf of sample_light returning the direction towards the environment
+    pub fn environment_pdf(&self, direction: &Vector3) -> f32 {
+        match self.environment {
+            Some(environment) => environment.pdf(direction) / self.num_of_lights() as f32,
+            None => 0.0,
+        }
+    }
+
+    // shadow rays are traced at the time of the path they belong to
+    pub fn is_occluded(
+        &self,
+        origin: &Vector3,
+        direction: &Vector3,
+        distance: f32,
+        time: f32,
+    ) -> bool {
+        let ray = Ray {
+            origin: *origin,
+            direction: *direction,
+            time,
+        };
+        let t = self.trace(&ray).shape_intersection.t;
+        t >= 0.0 && t < distance
+    }
 }
@@ -0,0 +1,1408 @@
+//! Text scene description format.
+//!
+//! A scene file is a list of statements, one per line. Everything after a `#` is a comment and
+//! blank lines are ignored. A statement starts with a directive, followed by the positional
+//! arguments of that directive and then by named parameters. A parameter is a key followed by
+//! its values, numbers or "quoted strings".
+//!
+//! ```text
+//! # directive  positional...      parameters...
+//! camera       perspective        fov 90 position 0 0 -7 look 0 0 1 up 0 1 0
+//! settings                        width 800 height 600 samples 2048
+//! sky          0.05 0.05 0.1
+//! environment  "sky.exr"          intensity 1 rotation 90
+//! output       "cornell-box.png"
+//! tonemap      aces               exposure 0.5
+//! filter       mitchell           radius 2
+//! sampler      sobol
+//! material     <name> <type>      ...
+//! shape        <name> <type>      ...
+//! entity       <shape> <material>
+//! light        <type>             ...
+//! obj          "<path>"
+//! ```
+//!
+//! Camera types:
+//! - `perspective`: `fov` (degrees, default 90), `position`, `look`, `up`. An `aperture` radius
+//!   (default 0 for a pinhole where everything is sharp) blurs what is not at `focus_distance`.
+//!   The aperture is round, or a polygon with `blades` sides turned by `blade_rotation` degrees,
+//!   or the grayscale `aperture_image` "<path>" which gives its shape to the bokeh.
+//! - `orthographic`: `scale` (width of the view in scene units), `position`, `look`, `up`.
+//! - `equirectangular`: `position`, `look`, `up`. Sees every direction, for images twice as wide
+//!   as high.
+//! - `fisheye`: `fov` (degrees, default 180, up to 360), `mapping` (`"equidistant"`, the default,
+//!   or `"equisolid"`), `position`, `look`, `up`. The image circle spans the width of the image.
+//! - `cubemap`: `position`. Renders six square images, one per face, named after the output with
+//!   `_px`, `_nx`, `_py`, `_ny`, `_pz` or `_nz` appended.
+//!
+//! Every camera sees the scene from `shutter_open` to `shutter_close` (default 0 and 1), the
+//! times of the motion of the shapes.
+//!
+//! Settings: `width`, `height`, `samples` (samples per pixel), `threads` (default 0 for every core),
+//! `seed` (a fixed seed makes renders reproducible), `min_depth` (bounces before russian roulette
+//! can end a path, default 3) and `max_depth` (default 16). `output` names the image the
+//! render is saved to when none is given on the command line.
+//!
+//! `sky` is the radiance of the rays leaving the scene. `environment` replaces it with an
+//! equirectangular HDR or EXR image, relative to the scene file, whose top row is the zenith (+y),
+//! scaled by `intensity` (default 1) and turned by `rotation` degrees around the y axis
+//! (default 0). The environment lights the scene and is importance sampled.
+//!
+//! `sky preetham` is a procedural daylight sky with its sun: `sun` (direction towards the sun,
+//! above the horizon), `turbidity` (default 3, from 2 for a clear sky to 10 for haze), ground
+//! `albedo` (default 0.2 0.2 0.2), `intensity` (default 1, radiances are in kcd/m^2 so a negative
+//! exposure is usually needed) and `sun_diameter` (degrees, default 0.53). The sun disk is seen by
+//! the camera and in reflections, a sun of diameter 0 only lights the scene.
+//!
+//! `tonemap` picks the curve applied to 8-bit images: `clamp` (default), `reinhard`,
+//! `extended_reinhard`, `hable` or `aces`, with an `exposure` in stops (default 0) and a `white`
+//! point (default 4) for `extended_reinhard` and `hable`.
+//!
+//! `filter` picks the pixel reconstruction filter and its `radius` in pixels: `box` (default,
+//! radius 0.5), `tent` (1), `gaussian` (1.5, falloff `alpha` 2), `mitchell` (2, `b` and `c` 1/3)
+//! or `lanczos` (3).
+//!
+//! `sampler` picks how the random numbers of the paths are generated: `independent` (default),
+//! `stratified`, `halton` or `sobol`.
+//!
+//! Material types:
+//! - `diffuse`: `color`.
+//! - `emissive`: `color`, `intensity` (default 1).
+//! - `reflective`: `color`.
+//! - `transparent`: `color`, `ior`, `roughness` (default 0 for clear glass, frosted above) and
+//!   `absorption` (default 0 0 0), the fraction of light absorbed per unit of distance travelled
+//!   inside, which colors thick glass more than thin glass.
+//! - `conductor`: `preset` (`"gold"`, `"copper"`, `"aluminium"` or `"silver"`) or the complex index
+//!   of refraction `eta` and `k` per channel, `roughness` (default 0 for a mirror) and
+//!   `roughness_y` (default `roughness`) for anisotropic metals.
+//!
+//! Shape types:
+//! - `sphere`: `position`, `radius`.
+//! - `plane`: `position`, `normal`, `up`, `width`, `height`.
+//! - `triangle`: `v0`, `v1`, `v2`, counter-clockwise when seen from the front.
+//! - `instance <shape>`: the shape declared before, shared rather than copied.
+//!
+//! Every shape can be placed by a `scale` (per axis, default 1 1 1), then a `rotate` (degrees
+//! around `rotate_axis`, default 0 1 0) and then a `translate`, all around the origin.
+//!
+//! Shapes move while the shutter is open, which blurs them, with `move` (translation from the
+//! declared position at time 0 to the one at time 1) and `spin` (degrees around `spin_axis`,
+//! default 0 1 0, through the center of the shape). Moving emissive shapes do not light the
+//! scene through shadow rays, only through the rays bouncing on the other surfaces.
+//!
+//! Light types, with a `color` (default 1 1 1) and an `intensity` (default 1):
+//! - `point`: `position`.
+//! - `spot`: `position`, `direction`, `angle` (degrees from the axis, default 30) and `falloff`,
+//!   the angle where the light starts fading out (default `angle` - 5).
+//! - `directional`: `direction` the light travels towards and `angular_diameter` (degrees,
+//!   default 0, 0.53 for the sun) which softens shadows. The intensity is the irradiance of a
+//!   surface facing the light.
+//!
+//! Lights have no geometry: they are not seen by the camera nor in reflections.
+//!
+//! `obj` imports a Wavefront OBJ file, relative to the scene file, adding one entity per group
+//! with the materials of its MTL files.
+//!
+//! Materials and shapes must be declared before the entities that use them, and each name can
+//! only be declared once. See `scenes/cornell-box.scene` for a complete example.
+
+use crate::camera::{
+    Aperture, Camera, CameraModel, CubeFace, EquirectangularCamera, FisheyeCamera, FisheyeMapping,
+    OrthographicCamera, Shutter, ThinLens,
+};
+use crate::environment::{EnvironmentMap, PreethamSky};
+use crate::filter::Filter;
+use crate::instance::Instance;
+use crate::light::{DirectionalLight, EnvironmentLight, Light, PointLight, SpotLight};
+use crate::material::{
+    ConductorMaterial, DiffuseMaterial, EmissiveMaterial, Material, ReflectiveMaterial,
+    RoughTransparentMaterial, TransparentMaterial,
+};
+use crate::motion::{MovingShape, Placement};
+use crate::obj::{ObjError, ObjModel};
+use crate::renderer::RenderSettings;
+use crate::sampler::SamplerType;
+use crate::scene::{Entity, Scene};
+use crate::shape::{Plane, Shape, Sphere, Triangle};
+use crate::tonemap::{ToneMapper, ToneMappingOperator};
+use crate::tools;
+use crate::vector::{Transform, Vector3};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub enum SceneFileError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+    Obj { line: usize, error: ObjError },
+}
+
+pub struct SceneDescription {
+    pub cameras: Vec<(&'static str, Box<dyn CameraModel>)>, // image name suffix and camera
+    pub render_settings: RenderSettings,
+    pub sky: Vector3,
+    pub output: Option<String>,
+    environment: Option<Box<dyn EnvironmentLight>>,
+    materials: Vec<Box<dyn Material>>,
+    shapes: Vec<Arc<dyn Shape>>,
+    entities: Vec<(usize, usize)>, // (shape index, material index)
+    lights: Vec<Box<dyn Light>>,
+    models: Vec<ObjModel>,
+}
+
+impl fmt::Display for SceneFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneFileError::Io(error) => write!(f, "{}", error),
+            SceneFileError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            SceneFileError::Obj { line, error } => write!(f, "line {}: {}", line, error),
+        }
+    }
+}
+
+impl std::error::Error for SceneFileError {}
+
+impl From<std::io::Error> for SceneFileError {
+    fn from(error: std::io::Error) -> SceneFileError {
+        SceneFileError::Io(error)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Number(f32),
+    Text(String), // quoted string
+    Word(String),
+}
+
+struct Statement {
+    line: usize,
+    directive: String,
+    tokens: Vec<Value>,
+    position: usize,
+}
+
+struct Parameters {
+    line: usize,
+    values: HashMap<String, Vec<Value>>,
+}
+
+fn parse_error<T>(line: usize, message: String) -> Result<T, SceneFileError> {
+    Err(SceneFileError::Parse { line, message })
+}
+
+// placement of a shape, scaled then turned and moved around the origin. None when the shape stays
+// as declared
+fn parse_transform(parameters: &mut Parameters) -> Result<Option<Transform>, SceneFileError> {
+    let scale = parameters.vector_or("scale", Vector3::new(1.0, 1.0, 1.0))?;
+    if scale.x == 0.0 || scale.y == 0.0 || scale.z == 0.0 {
+        return parameters.error("'scale' must not be zero along an axis".to_string());
+    }
+    let angle = parameters.float_or("rotate", 0.0)?.to_radians();
+    let axis = parameters.direction_or("rotate_axis", Vector3::new(0.0, 1.0, 0.0))?;
+    let translation = parameters.vector_or("translate", Vector3::zero_vector())?;
+    if scale == Vector3::new(1.0, 1.0, 1.0) && angle == 0.0 && translation.is_zero() {
+        return Ok(None);
+    }
+    Ok(Some(
+        Transform::scaling(&scale)
+            .then(&Transform::rotation(&axis, angle))
+            .then(&Transform::translation(&translation)),
+    ))
+}
+
+fn tokenize(line_number: usize, line: &str) -> Result<Vec<String>, SceneFileError> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c == '#' {
+            break;
+        } else if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let mut token = String::from("\"");
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(c) => token.push(c),
+                    None => return parse_error(line_number, "unterminated string".to_string()),
+                }
+            }
+            tokens.push(token);
+        } else {
+            let mut token = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '#' || c == '"' {
+                    break;
+                }
+                token.push(c);
+                chars.next();
+            }
+            tokens.push(token);
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn is_number_token(token: &str) -> bool {
+    token.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '+' || c == '.')
+}
+
+fn to_value(line: usize, token: &str) -> Result<Value, SceneFileError> {
+    if let Some(text) = token.strip_prefix('"') {
+        return Ok(Value::Text(text.to_string()));
+    }
+    if is_number_token(token) {
+        return match token.parse::<f32>() {
+            Ok(number) => Ok(Value::Number(number)),
+            Err(_) => parse_error(line, format!("invalid number '{}'", token)),
+        };
+    }
+    Ok(Value::Word(token.to_string()))
+}
+
+impl Statement {
+    fn parse(line: usize, source: &str) -> Result<Option<Statement>, SceneFileError> {
+        let tokens = tokenize(line, source)?;
+        let mut tokens = tokens.iter();
+        let directive = match tokens.next() {
+            Some(directive) => directive.clone(),
+            None => return Ok(None),
+        };
+        let tokens = tokens
+            .map(|token| to_value(line, token))
+            .collect::<Result<Vec<Value>, SceneFileError>>()?;
+        Ok(Some(Statement {
+            line,
+            directive,
+            tokens,
+            position: 0,
+        }))
+    }
+
+    fn error<T>(&self, message: String) -> Result<T, SceneFileError> {
+        parse_error(self.line, message)
+    }
+
+    fn name(&mut self, what: &str) -> Result<String, SceneFileError> {
+        match self.tokens.get(self.position) {
+            Some(Value::Word(word)) => {
+                self.position += 1;
+                Ok(word.clone())
+            }
+            _ => self.error(format!("'{}' expects a {}", self.directive, what)),
+        }
+    }
+
+    fn text(&mut self) -> Result<String, SceneFileError> {
+        match self.tokens.get(self.position) {
+            Some(Value::Text(text)) => {
+                self.position += 1;
+                Ok(text.clone())
+            }
+            _ => self.error(format!("'{}' expects a quoted string", self.directive)),
+        }
+    }
+
+    fn numbers(&mut self, count: usize) -> Result<Vec<f32>, SceneFileError> {
+        let mut numbers = Vec::with_capacity(count);
+        for _ in 0..count {
+            match self.tokens.get(self.position) {
+                Some(Value::Number(number)) => numbers.push(*number),
+                _ => return self.error(format!("'{}' expects {} numbers", self.directive, count)),
+            }
+            self.position += 1;
+        }
+        Ok(numbers)
+    }
+
+    fn parameters(&mut self) -> Result<Parameters, SceneFileError> {
+        let mut values: HashMap<String, Vec<Value>> = HashMap::new();
+        let mut key: Option<&String> = None;
+        for token in &self.tokens[self.position..] {
+            match (token, key) {
+                (Value::Word(word), _) => {
+                    if values.insert(word.clone(), Vec::new()).is_some() {
+                        return self.error(format!("parameter '{}' given twice", word));
+                    }
+                    key = Some(word);
+                }
+                (value, Some(key)) => values.get_mut(key).unwrap().push(value.clone()),
+                (_, None) => return self.error("expected a parameter name".to_string()),
+            }
+        }
+        self.position = self.tokens.len();
+        Ok(Parameters {
+            line: self.line,
+            values,
+        })
+    }
+
+    fn finish(&self) -> Result<(), SceneFileError> {
+        if self.position < self.tokens.len() {
+            return self.error(format!("unexpected arguments to '{}'", self.directive));
+        }
+        Ok(())
+    }
+}
+
+impl Parameters {
+    fn error<T>(&self, message: String) -> Result<T, SceneFileError> {
+        parse_error(self.line, message)
+    }
+
+    fn numbers(&mut self, key: &str, count: usize) -> Result<Option<Vec<f32>>, SceneFileError> {
+        let values = match self.values.remove(key) {
+            Some(values) => values,
+            None => return Ok(None),
+        };
+        if values.len() != count {
+            return self.error(format!("'{}' expects {} numbers", key, count));
+        }
+        let mut numbers = Vec::with_capacity(count);
+        for value in values {
+            match value {
+                Value::Number(number) => numbers.push(number),
+                _ => return self.error(format!("'{}' expects numbers", key)),
+            }
+        }
+        Ok(Some(numbers))
+    }
+
+    fn optional_float(&mut self, key: &str) -> Result<Option<f32>, SceneFileError> {
+        Ok(self.numbers(key, 1)?.map(|numbers| numbers[0]))
+    }
+
+    fn float_or(&mut self, key: &str, default: f32) -> Result<f32, SceneFileError> {
+        Ok(self.optional_float(key)?.unwrap_or(default))
+    }
+
+    fn float(&mut self, key: &str) -> Result<f32, SceneFileError> {
+        match self.optional_float(key)? {
+            Some(number) => Ok(number),
+            None => self.error(format!("missing parameter '{}'", key)),
+        }
+    }
+
+    fn positive_float(&mut self, key: &str) -> Result<f32, SceneFileError> {
+        let number = self.float(key)?;
+        if !tools::is_positive_error(number) {
+            return self.error(format!("'{}' must be positive", key));
+        }
+        Ok(number)
+    }
+
+    fn optional_uint(&mut self, key: &str) -> Result<Option<u32>, SceneFileError> {
+        match self.optional_float(key)? {
+            // integers above 2^24 cannot be read exactly as f32
+            Some(number) if (0.0..=16777216.0).contains(&number) && number.fract() == 0.0 => {
+                Ok(Some(number as u32))
+            }
+            Some(_) => self.error(format!(
+                "'{}' expects an integer between 0 and 16777216",
+                key
+            )),
+            None => Ok(None),
+        }
+    }
+
+    fn uint_or(&mut self, key: &str, default: u32) -> Result<u32, SceneFileError> {
+        Ok(self.optional_uint(key)?.unwrap_or(default))
+    }
+
+    fn optional_vector(&mut self, key: &str) -> Result<Option<Vector3>, SceneFileError> {
+        Ok(self
+            .numbers(key, 3)?
+            .map(|numbers| Vector3::new(numbers[0], numbers[1], numbers[2])))
+    }
+
+    fn vector_or(&mut self, key: &str, default: Vector3) -> Result<Vector3, SceneFileError> {
+        Ok(self.optional_vector(key)?.unwrap_or(default))
+    }
+
+    fn vector(&mut self, key: &str) -> Result<Vector3, SceneFileError> {
+        match self.optional_vector(key)? {
+            Some(vector) => Ok(vector),
+            None => self.error(format!("missing parameter '{}'", key)),
+        }
+    }
+
+    fn direction(&mut self, key: &str) -> Result<Vector3, SceneFileError> {
+        let vector = self.vector(key)?;
+        if vector.is_zero() {
+            return self.error(format!("'{}' must not be a zero vector", key));
+        }
+        Ok(vector.unit())
+    }
+
+    fn direction_or(&mut self, key: &str, default: Vector3) -> Result<Vector3, SceneFileError> {
+        let vector = self.vector_or(key, default)?;
+        if vector.is_zero() {
+            return self.error(format!("'{}' must not be a zero vector", key));
+        }
+        Ok(vector.unit())
+    }
+
+    fn optional_text(&mut self, key: &str) -> Result<Option<String>, SceneFileError> {
+        match self.values.remove(key).as_deref() {
+            Some([Value::Text(text)]) => Ok(Some(text.clone())),
+            Some(_) => self.error(format!("'{}' expects a quoted string", key)),
+            None => Ok(None),
+        }
+    }
+
+    fn finish(self) -> Result<(), SceneFileError> {
+        let mut unknown: Vec<&String> = self.values.keys().collect();
+        unknown.sort();
+        match unknown.first() {
+            Some(key) => self.error(format!("unknown parameter '{}'", key)),
+            None => Ok(()),
+        }
+    }
+}
+
+enum Projection {
+    Perspective { fov: f32, lens: ThinLens },
+    Orthographic { scale: f32 },
+    Equirectangular,
+    Fisheye { fov: f32, mapping: FisheyeMapping },
+    Cubemap,
+}
+
+struct CameraParameters {
+    line: usize,
+    projection: Projection,
+    position: Vector3,
+    look: Vector3,
+    up: Vector3,
+    shutter: Shutter,
+}
+
+#[derive(Default)]
+struct Parser {
+    directory: PathBuf,
+    camera: Option<CameraParameters>,
+    render_settings: Option<RenderSettings>,
+    tone_mapper: Option<ToneMapper>,
+    filter: Option<Filter>,
+    sampler: Option<SamplerType>,
+    sky: Option<Vector3>,
+    environment: Option<Box<dyn EnvironmentLight>>,
+    output: Option<String>,
+    materials: Vec<Box<dyn Material>>,
+    shapes: Vec<Arc<dyn Shape>>,
+    entities: Vec<(usize, usize)>,
+    lights: Vec<Box<dyn Light>>,
+    models: Vec<ObjModel>,
+    material_names: HashMap<String, usize>,
+    shape_names: HashMap<String, usize>,
+}
+
+impl Parser {
+    fn parse_statement(&mut self, mut statement: Statement) -> Result<(), SceneFileError> {
+        match statement.directive.as_str() {
+            "camera" => self.parse_camera(&mut statement)?,
+            "settings" => self.parse_settings(&mut statement)?,
+            "tonemap" => self.parse_tone_mapper(&mut statement)?,
+            "filter" => self.parse_filter(&mut statement)?,
+            "sampler" => {
+                if self.sampler.is_some() {
+                    return statement.error("sampler declared twice".to_string());
+                }
+                let sampler_type = statement.name("sampler type")?;
+                match SamplerType::from_name(&sampler_type) {
+                    Some(sampler) => self.sampler = Some(sampler),
+                    None => {
+                        return statement.error(format!("unknown sampler type '{}'", sampler_type))
+                    }
+                }
+            }
+            "sky" => self.parse_sky(&mut statement)?,
+            "environment" => self.parse_environment(&mut statement)?,
+            "output" => {
+                if self.output.is_some() {
+                    return statement.error("output declared twice".to_string());
+                }
+                self.output = Some(statement.text()?);
+            }
+            "material" => self.parse_material(&mut statement)?,
+            "shape" => self.parse_shape(&mut statement)?,
+            "entity" => {
+                let shape_name = statement.name("shape name")?;
+                let material_name = statement.name("material name")?;
+                let shape = match self.shape_names.get(&shape_name) {
+                    Some(shape) => *shape,
+                    None => return statement.error(format!("unknown shape '{}'", shape_name)),
+                };
+                let material = match self.material_names.get(&material_name) {
+                    Some(material) => *material,
+                    None => {
+                        return statement.error(format!("unknown material '{}'", material_name))
+                    }
+                };
+                self.entities.push((shape, material));
+            }
+            "light" => self.parse_light(&mut statement)?,
+            "obj" => {
+                let path = self.directory.join(statement.text()?);
+                match ObjModel::load(path) {
+                    Ok(model) => self.models.push(model),
+                    Err(error) => {
+                        return Err(SceneFileError::Obj {
+                            line: statement.line,
+                            error,
+                        })
+                    }
+                }
+            }
+            directive => return statement.error(format!("unknown directive '{}'", directive)),
+        }
+
+        statement.finish()
+    }
+
+    fn parse_camera(&mut self, statement: &mut Statement) -> Result<(), SceneFileError> {
+        if self.camera.is_some() {
+            return statement.error("camera declared twice".to_string());
+        }
+        let camera_type = statement.name("camera type")?;
+        let mut parameters = statement.parameters()?;
+        let projection = match camera_type.as_str() {
+            "perspective" => Projection::Perspective {
+                fov: parameters.float_or("fov", 90.0)?.to_radians(),
+                lens: self.parse_lens(&mut parameters)?,
+            },
+            "orthographic" => Projection::Orthographic {
+                scale: parameters.positive_float("scale")?,
+            },
+            "equirectangular" => Projection::Equirectangular,
+            "fisheye" => {
+                let fov = parameters.float_or("fov", 180.0)?;
+                if fov <= 0.0 || fov > 360.0 {
+                    return statement.error("'fov' must be between 0 and 360".to_string());
+                }
+                let mapping = match parameters.optional_text("mapping")?.as_deref() {
+                    None | Some("equidistant") => FisheyeMapping::Equidistant,
+                    Some("equisolid") => FisheyeMapping::Equisolid,
+                    Some(mapping) => {
+                        return statement.error(format!("unknown fisheye mapping '{}'", mapping))
+                    }
+                };
+                Projection::Fisheye {
+                    fov: fov.to_radians(),
+                    mapping,
+                }
+            }
+            "cubemap" => Projection::Cubemap,
+            _ => return statement.error(format!("unknown camera type '{}'", camera_type)),
+        };
+        let position = parameters.vector("position")?;
+        // the faces of a cubemap are aligned with the axes
+        let (look, up) = match projection {
+            Projection::Cubemap => (Vector3::new(0.0, 0.0, 1.0), Vector3::new(0.0, 1.0, 0.0)),
+            _ => (parameters.direction("look")?, parameters.direction("up")?),
+        };
+        let shutter = Shutter {
+            open: parameters.float_or("shutter_open", 0.0)?,
+            close: parameters.float_or("shutter_close", 1.0)?,
+        };
+        if shutter.close < shutter.open {
+            return statement
+                .error("'shutter_close' must not be before 'shutter_open'".to_string());
+        }
+        let camera = CameraParameters {
+            line: statement.line,
+            projection,
+            position,
+            look,
+            up,
+            shutter,
+        };
+        if !tools::equal_error(camera.look.dot(&camera.up), 0.0) {
+            return statement.error("camera 'look' and 'up' must be perpendicular".to_string());
+        }
+        parameters.finish()?;
+        self.camera = Some(camera);
+        Ok(())
+    }
+
+    fn parse_lens(&self, parameters: &mut Parameters) -> Result<ThinLens, SceneFileError> {
+        let aperture_radius = parameters.float_or("aperture", 0.0)?;
+        if aperture_radius < 0.0 {
+            return parameters.error("'aperture' must not be negative".to_string());
+        }
+        let focus_distance = if aperture_radius > 0.0 {
+            parameters.positive_float("focus_distance")?
+        } else {
+            parameters.float_or("focus_distance", 1.0)?
+        };
+        let blades = parameters.uint_or("blades", 0)?;
+        if blades == 1 || blades == 2 {
+            return parameters.error("'blades' must be 0 for a circle or at least 3".to_string());
+        }
+        let rotation = parameters.float_or("blade_rotation", 0.0)?.to_radians();
+        let aperture = match parameters.optional_text("aperture_image")? {
+            Some(name) => {
+                let path = self.directory.join(name);
+                match Aperture::load(&path) {
+                    Ok(aperture) => aperture,
+                    Err(error) => {
+                        return parameters.error(format!(
+                            "cannot load aperture '{}': {}",
+                            path.display(),
+                            error
+                        ))
+                    }
+                }
+            }
+            None if blades > 0 => Aperture::Polygon { blades, rotation },
+            None => Aperture::Circle,
+        };
+        Ok(ThinLens {
+            aperture_radius,
+            focus_distance,
+            aperture,
+        })
+    }
+
+    fn parse_settings(&mut self, statement: &mut Statement) -> Result<(), SceneFileError> {
+        if self.render_settings.is_some() {
+            return statement.error("settings declared twice".to_string());
+        }
+        let mut parameters = statement.parameters()?;
+        let defaults = RenderSettings::default();
+        let render_settings = RenderSettings {
+            image_width: parameters.uint_or("width", defaults.image_width)?,
+            image_height: parameters.uint_or("height", defaults.image_height)?,
+            num_of_samples: parameters.uint_or("samples", defaults.num_of_samples)?,
+            num_of_threads: parameters.uint_or("threads", 0)? as usize,
+            seed: parameters.optional_uint("seed")?.map(u64::from),
+            min_depth: parameters.uint_or("min_depth", defaults.min_depth)?,
+            max_depth: parameters.uint_or("max_depth", defaults.max_depth)?,
+            sampler: defaults.sampler,
+            filter: defaults.filter,
+            tone_mapper: defaults.tone_mapper,
+        };
+        if render_settings.max_depth == 0 {
+            return statement.error("'max_depth' must be positive".to_string());
+        }
+        if render_settings.image_width == 0
+            || render_settings.image_height == 0
+            || render_settings.num_of_samples == 0
+        {
+            return statement.error("'width', 'height' and 'samples' must be positive".to_string());
+        }
+        parameters.finish()?;
+        self.render_settings = Some(render_settings);
+        Ok(())
+    }
+
+    fn parse_tone_mapper(&mut self, statement: &mut Statement) -> Result<(), SceneFileError> {
+        if self.tone_mapper.is_some() {
+            return statement.error("tonemap declared twice".to_string());
+        }
+        let operator_name = statement.name("tone mapping operator")?;
+        let operator = match ToneMappingOperator::from_name(&operator_name) {
+            Some(operator) => operator,
+            None => {
+                return statement
+                    .error(format!("unknown tone mapping operator '{}'", operator_name))
+            }
+        };
+        let mut parameters = statement.parameters()?;
+        let defaults = ToneMapper::default();
+        let tone_mapper = ToneMapper {
+            operator,
+            exposure: parameters.float_or("exposure", defaults.exposure)?,
+            white_point: parameters.float_or("white", defaults.white_point)?,
+        };
+        if !tools::is_positive_error(tone_mapper.white_point) {
+            return statement.error("'white' must be positive".to_string());
+        }
+        parameters.finish()?;
+        self.tone_mapper = Some(tone_mapper);
+        Ok(())
+    }
+
+    fn parse_filter(&mut self, statement: &mut Statement) -> Result<(), SceneFileError> {
+        if self.filter.is_some() {
+            return statement.error("filter declared twice".to_string());
+        }
+        let filter_type = statement.name("filter type")?;
+        let mut filter = match Filter::from_name(&filter_type) {
+            Some(filter) => filter,
+            None => return statement.error(format!("unknown filter type '{}'", filter_type)),
+        };
+        let mut parameters = statement.parameters()?;
+        match &mut filter {
+            Filter::Gaussian { alpha, .. } => *alpha = parameters.float_or("alpha", *alpha)?,
+            Filter::Mitchell { b, c, .. } => {
+                *b = parameters.float_or("b", *b)?;
+                *c = parameters.float_or("c", *c)?;
+            }
+            _ => {}
+        }
+        let radius = parameters.float_or("radius", filter.radius())?;
+        if !tools::is_positive_error(radius) {
+            return statement.error("'radius' must be positive".to_string());
+        }
+        filter.set_radius(radius);
+        parameters.finish()?;
+        self.filter = Some(filter);
+        Ok(())
+    }
+
+    fn parse_material(&mut self, statement: &mut Statement) -> Result<(), SceneFileError> {
+        let name = statement.name("material name")?;
+        if self.material_names.contains_key(&name) {
+            return statement.error(format!("material '{}' declared twice", name));
+        }
+        let material_type = statement.name("material type")?;
+        let mut parameters = statement.parameters()?;
+        let material: Box<dyn Material> = match material_type.as_str() {
+            "diffuse" => Box::new(DiffuseMaterial {
+                color: parameters.vector("color")?,
+            }),
+            "emissive" => Box::new(EmissiveMaterial::new(
+                &parameters.vector("color")?,
+                parameters.float_or("intensity", 1.0)?,
+            )),
+            "reflective" => Box::new(ReflectiveMaterial {
+                color: parameters.vector_or("color", Vector3::new(1.0, 1.0, 1.0))?,
+            }),
+            "transparent" => {
+                let color = parameters.vector_or("color", Vector3::new(1.0, 1.0, 1.0))?;
+                let ior = parameters.positive_float("ior")?;
+                let absorption = parameters.vector_or("absorption", Vector3::zero_vector())?;
+                if absorption.x < 0.0 || absorption.y < 0.0 || absorption.z < 0.0 {
+                    return statement.error("absorption must not be negative".to_string());
+                }
+                let roughness = parameters.float_or("roughness", 0.0)?;
+                if roughness < 0.0 {
+                    return statement.error("roughness must not be negative".to_string());
+                }
+                if roughness > 0.0 {
+                    let mut material = RoughTransparentMaterial::new(color, ior, roughness);
+                    material.absorption = absorption;
+                    Box::new(material)
+                } else {
+                    Box::new(TransparentMaterial {
+                        color,
+                        ior,
+                        absorption,
+                    })
+                }
+            }
+            "conductor" => {
+                let roughness = parameters.float_or("roughness", 0.0)?;
+                let roughness_y = parameters.float_or("roughness_y", roughness)?;
+                if roughness < 0.0 || roughness_y < 0.0 {
+                    return statement.error("roughness must not be negative".to_string());
+                }
+                match parameters.optional_text("preset")? {
+                    Some(preset) => {
+                        let mut material =
+                            match ConductorMaterial::from_preset(&preset, roughness, roughness_y) {
+                                Some(material) => material,
+                                None => {
+                                    return statement
+                                        .error(format!("unknown conductor preset '{}'", preset))
+                                }
+                            };
+                        material.eta = parameters.vector_or("eta", material.eta)?;
+                        material.k = parameters.vector_or("k", material.k)?;
+                        Box::new(material)
+                    }
+                    None => Box::new(ConductorMaterial::new(
+                        parameters.vector("eta")?,
+                        parameters.vector("k")?,
+                        roughness,
+                        roughness_y,
+                    )),
+                }
+            }
+            _ => return statement.error(format!("unknown material type '{}'", material_type)),
+        };
+        parameters.finish()?;
+        self.material_names.insert(name, self.materials.len());
+        self.materials.push(material);
+        Ok(())
+    }
+
+    fn parse_shape(&mut self, statement: &mut Statement) -> Result<(), SceneFileError> {
+        let name = statement.name("shape name")?;
+        if self.shape_names.contains_key(&name) {
+            return statement.error(format!("shape '{}' declared twice", name));
+        }
+        let shape_type = statement.name("shape type")?;
+        let instanced_name = if shape_type == "instance" {
+            Some(statement.name("instanced shape name")?)
+        } else {
+            None
+        };
+        let mut parameters = statement.parameters()?;
+        let shape: Arc<dyn Shape> = match shape_type.as_str() {
+            "sphere" => Arc::new(Sphere {
+                position: parameters.vector("position")?,
+                radius: parameters.positive_float("radius")?,
+            }),
+            "plane" => {
+                let position = parameters.vector("position")?;
+                let normal = parameters.direction("normal")?;
+                let up = parameters.direction("up")?;
+                if !tools::equal_error(normal.dot(&up), 0.0) {
+                    return statement
+                        .error("plane 'normal' and 'up' must be perpendicular".to_string());
+                }
+                let width = parameters.positive_float("width")?;
+                let height = parameters.positive_float("height")?;
+                Arc::new(Plane::new(position, normal, up, width, height))
+            }
+            "triangle" => Arc::new(Triangle {
+                v0: parameters.vector("v0")?,
+                v1: parameters.vector("v1")?,
+                v2: parameters.vector("v2")?,
+            }),
+            // shared with the instanced shape, only the transform is stored
+            "instance" => {
+                let instanced_name = instanced_name.unwrap();
+                match self.shape_names.get(&instanced_name) {
+                    Some(shape) => self.shapes[*shape].clone(),
+                    None => return statement.error(format!("unknown shape '{}'", instanced_name)),
+                }
+            }
+            _ => return statement.error(format!("unknown shape type '{}'", shape_type)),
+        };
+        let shape: Arc<dyn Shape> = match parse_transform(&mut parameters)? {
+            Some(transform) => Arc::new(Instance::new(shape, transform)),
+            None => shape,
+        };
+        // motion from the declared placement at time 0
+        let translation = parameters.vector_or("move", Vector3::zero_vector())?;
+        let angle = parameters.float_or("spin", 0.0)?.to_radians();
+        let axis = parameters.direction_or("spin_axis", Vector3::new(0.0, 1.0, 0.0))?;
+        let shape: Arc<dyn Shape> = if translation.is_zero() && angle == 0.0 {
+            shape
+        } else {
+            Arc::new(MovingShape::new(
+                shape,
+                axis,
+                Placement::identity(),
+                Placement { translation, angle },
+            ))
+        };
+        parameters.finish()?;
+        self.shape_names.insert(name, self.shapes.len());
+        self.shapes.push(shape);
+        Ok(())
+    }
+
+    fn parse_sky(&mut self, statement: &mut Statement) -> Result<(), SceneFileError> {
+        if self.sky.is_some() {
+            return statement.error("sky declared twice".to_string());
+        }
+        if !matches!(statement.tokens.first(), Some(Value::Word(_))) {
+            let color = statement.numbers(3)?;
+            self.sky = Some(Vector3::new(color[0], color[1], color[2]));
+            return Ok(());
+        }
+
+        let sky_type = statement.name("sky type")?;
+        if sky_type != "preetham" {
+            return statement.error(format!("unknown sky type '{}'", sky_type));
+        }
+        if self.environment.is_some() {
+            return statement.error("the scene already has an environment".to_string());
+        }
+        let mut parameters = statement.parameters()?;
+        let sun_direction = parameters.direction("sun")?;
+        if sun_direction.y < 0.0 {
+            return statement.error("'sun' must be above the horizon".to_string());
+        }
+        let turbidity = parameters.float_or("turbidity", 3.0)?;
+        // the range the model is fitted to, its zenith gets negative for clearer skies
+        if !(2.0..=10.0).contains(&turbidity) {
+            return statement.error("'turbidity' must be in [2, 10]".to_string());
+        }
+        let albedo = parameters.vector_or("albedo", Vector3::new(0.2, 0.2, 0.2))?;
+        let intensity = parameters.float_or("intensity", 1.0)?;
+        let sun_diameter = parameters.float_or("sun_diameter", 0.53)?;
+        if intensity < 0.0 || !(0.0..=180.0).contains(&sun_diameter) {
+            return statement.error(
+                "'intensity' must not be negative and 'sun_diameter' must be in [0, 180]"
+                    .to_string(),
+            );
+        }
+        parameters.finish()?;
+
+        let sky = PreethamSky::new(
+            sun_direction,
+            turbidity,
+            &albedo,
+            intensity,
+            sun_diameter.to_radians(),
+        );
+        if let Some(sun) = sky.sun() {
+            self.lights.push(Box::new(sun));
+        }
+        self.environment = Some(Box::new(sky));
+        self.sky = Some(Vector3::zero_vector());
+        Ok(())
+    }
+
+    fn parse_environment(&mut self, statement: &mut Statement) -> Result<(), SceneFileError> {
+        if self.environment.is_some() {
+            return statement.error("the scene already has an environment".to_string());
+        }
+        let path = self.directory.join(statement.text()?);
+        let mut parameters = statement.parameters()?;
+        let intensity = parameters.float_or("intensity", 1.0)?;
+        if intensity < 0.0 {
+            return statement.error("'intensity' must not be negative".to_string());
+        }
+        let rotation = parameters.float_or("rotation", 0.0)?.to_radians();
+        parameters.finish()?;
+        match EnvironmentMap::load(&path, intensity, rotation) {
+            Ok(environment) => self.environment = Some(Box::new(environment)),
+            Err(error) => {
+                return statement.error(format!(
+                    "cannot load environment '{}': {}",
+                    path.display(),
+                    error
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_light(&mut self, statement: &mut Statement) -> Result<(), SceneFileError> {
+        let light_type = statement.name("light type")?;
+        let mut parameters = statement.parameters()?;
+        let color = parameters.vector_or("color", Vector3::new(1.0, 1.0, 1.0))?;
+        let intensity = parameters.float_or("intensity", 1.0)?;
+        let light: Box<dyn Light> = match light_type.as_str() {
+            "point" => Box::new(PointLight::new(
+                parameters.vector("position")?,
+                &color,
+                intensity,
+            )),
+            "spot" => {
+                let position = parameters.vector("position")?;
+                let direction = parameters.direction("direction")?;
+                let angle = parameters.float_or("angle", 30.0)?;
+                let falloff = parameters.float_or("falloff", f32::max(0.0, angle - 5.0))?;
+                if !(0.0..=180.0).contains(&angle) || !(0.0..=angle).contains(&falloff) {
+                    return statement.error(
+                        "spot 'angle' must be in [0, 180] and 'falloff' in [0, angle]".to_string(),
+                    );
+                }
+                Box::new(SpotLight::new(
+                    position,
+                    direction,
+                    &color,
+                    intensity,
+                    angle.to_radians(),
+                    falloff.to_radians(),
+                ))
+            }
+            "directional" => {
+                let direction = parameters.direction("direction")?;
+                let angular_diameter = parameters.float_or("angular_diameter", 0.0)?;
+                if !(0.0..=180.0).contains(&angular_diameter) {
+                    return statement.error("'angular_diameter' must be in [0, 180]".to_string());
+                }
+                Box::new(DirectionalLight::new(
+                    direction,
+                    &color,
+                    intensity,
+                    angular_diameter.to_radians(),
+                ))
+            }
+            _ => return statement.error(format!("unknown light type '{}'", light_type)),
+        };
+        parameters.finish()?;
+        self.lights.push(light);
+        Ok(())
+    }
+
+    fn finish(self, last_line: usize) -> Result<SceneDescription, SceneFileError> {
+        let camera = match self.camera {
+            Some(camera) => camera,
+            None => return parse_error(last_line, "missing camera".to_string()),
+        };
+        let mut render_settings = self.render_settings.unwrap_or_default();
+        if let Some(tone_mapper) = self.tone_mapper {
+            render_settings.tone_mapper = tone_mapper;
+        }
+        if let Some(filter) = self.filter {
+            render_settings.filter = filter;
+        }
+        if let Some(sampler) = self.sampler {
+            render_settings.sampler = sampler;
+        }
+        let aspect_ratio = render_settings.image_width as f32 / render_settings.image_height as f32;
+        // one image per camera, saved with the suffix appended to the output name
+        let mut cameras: Vec<(&'static str, Box<dyn CameraModel>)> = Vec::new();
+        match camera.projection {
+            Projection::Perspective { fov, lens } => cameras.push((
+                "",
+                Box::new(
+                    Camera::with_lens(
+                        fov,
+                        aspect_ratio,
+                        camera.position,
+                        camera.look,
+                        camera.up,
+                        lens,
+                    )
+                    .with_shutter(camera.shutter),
+                ),
+            )),
+            Projection::Orthographic { scale } => cameras.push((
+                "",
+                Box::new(
+                    OrthographicCamera::new(
+                        scale,
+                        aspect_ratio,
+                        camera.position,
+                        camera.look,
+                        camera.up,
+                    )
+                    .with_shutter(camera.shutter),
+                ),
+            )),
+            Projection::Equirectangular => cameras.push((
+                "",
+                Box::new(
+                    EquirectangularCamera::new(camera.position, camera.look, camera.up)
+                        .with_shutter(camera.shutter),
+                ),
+            )),
+            Projection::Fisheye { fov, mapping } => cameras.push((
+                "",
+                Box::new(
+                    FisheyeCamera::new(
+                        fov,
+                        aspect_ratio,
+                        mapping,
+                        camera.position,
+                        camera.look,
+                        camera.up,
+                    )
+                    .with_shutter(camera.shutter),
+                ),
+            )),
+            Projection::Cubemap => {
+                if render_settings.image_width != render_settings.image_height {
+                    return parse_error(
+                        camera.line,
+                        "the faces of a cubemap must be square".to_string(),
+                    );
+                }
+                for face in CubeFace::ALL {
+                    let face_camera = face.camera(camera.position).with_shutter(camera.shutter);
+                    cameras.push((face.suffix(), Box::new(face_camera)));
+                }
+            }
+        }
+        Ok(SceneDescription {
+            cameras,
+            render_settings,
+            sky: self.sky.unwrap_or_else(Vector3::zero_vector),
+            output: self.output,
+            environment: self.environment,
+            materials: self.materials,
+            shapes: self.shapes,
+            entities: self.entities,
+            lights: self.lights,
+            models: self.models,
+        })
+    }
+}
+
+impl SceneDescription {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<SceneDescription, SceneFileError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)?;
+        SceneDescription::parse(&source, path.parent().unwrap_or_else(|| Path::new("")))
+    }
+
+    // paths in the scene are relative to directory
+    pub fn parse(source: &str, directory: &Path) -> Result<SceneDescription, SceneFileError> {
+        let mut parser = Parser {
+            directory: directory.to_path_buf(),
+            ..Parser::default()
+        };
+        let mut last_line = 0;
+        for (index, line) in source.lines().enumerate() {
+            last_line = index + 1;
+            if let Some(statement) = Statement::parse(last_line, line)? {
+                parser.parse_statement(statement)?;
+            }
+        }
+
+        parser.finish(last_line)
+    }
+
+    pub fn build_scene(&self) -> Scene<'_> {
+        let mut scene = Scene::new(self.sky);
+        for (shape, material) in self.entities.iter() {
+            scene.add_entity(Entity {
+                material: self.materials[*material].as_ref(),
+                shape: self.shapes[*shape].as_ref(),
+            });
+        }
+        for light in self.lights.iter() {
+            scene.add_light(light.as_ref());
+        }
+        if let Some(environment) = &self.environment {
+            scene.set_environment(environment.as_ref());
+        }
+        for model in self.models.iter() {
+            model.add_to_scene(&mut scene);
+        }
+
+        scene
+    }
+}
+
+#[cfg(test)]
+mod scene_file_tests {
+    use super::{SceneDescription, SceneFileError};
+    use crate::filter::Filter;
+    use crate::sampler::SamplerType;
+    use crate::tonemap::ToneMappingOperator;
+    use crate::tools::Sample2D;
+    use crate::vector::Vector3;
+    use std::path::Path;
+
+    fn error_line(source: &str) -> usize {
+        match SceneDescription::parse(source, Path::new("")) {
+            Err(SceneFileError::Parse { line, .. }) => line,
+            Err(error) => panic!("unexpected error {}", error),
+            Ok(_) => panic!("parsing should fail"),
+        }
+    }
+
+    #[test]
+    fn cornell_box_test() {
+        let description =
+            SceneDescription::parse(include_str!("../scenes/cornell-box.scene"), Path::new(""))
+                .unwrap();
+        assert_eq!(description.render_settings.image_width, 800);
+        assert_eq!(description.render_settings.image_height, 600);
+        assert_eq!(description.render_settings.num_of_samples, 2048);
+        assert!(description.sky == Vector3::new(0.05, 0.05, 0.1));
+        assert_eq!(description.output.as_deref(), Some("cornell-box.png"));
+        assert_eq!(description.materials.len(), 6);
+        assert_eq!(description.shapes.len(), 8);
+        assert_eq!(description.entities.len(), 8);
+        description.build_scene();
+    }
+
+    #[test]
+    fn render_options_test() {
+        let source = "tonemap hable exposure -1.5 white 8\n\
+                      camera perspective position 0 0 0 look 0 0 1 up 0 1 0 aperture 0.1 \
+                      focus_distance 5 blades 6 blade_rotation 15\n\
+                      settings width 64 height 32\n\
+                      filter gaussian radius 2 alpha 3\n\
+                      sampler halton\n\
+                      material gold conductor preset \"gold\" roughness 0.3 roughness_y 0.1\n\
+                      material metal conductor eta 1.5 1 0.5 k 4 3 2\n\
+                      material frosted transparent ior 1.5 roughness 0.3\n\
+                      material tinted transparent ior 1.5 absorption 0.1 0.2 0.3\n\
+                      shape ball sphere position 0 0 0 radius 1 move 1 0 0 spin 90\n\
+                      shape copy instance ball scale 1 2 1 rotate 45 rotate_axis 1 0 0 \
+                      translate 0 5 0\n\
+                      light point position 0 1 0 intensity 10\n\
+                      light spot position 0 1 0 direction 0 -1 0 color 1 0.5 0 angle 20\n\
+                      light directional direction 1 -1 0 intensity 3 angular_diameter 0.53\n\
+                      sky preetham sun 1 1 0 turbidity 4 intensity 0.01";
+        let description = SceneDescription::parse(source, Path::new("")).unwrap();
+        let tone_mapper = description.render_settings.tone_mapper;
+        assert_eq!(tone_mapper.operator, ToneMappingOperator::Hable);
+        assert_eq!(tone_mapper.exposure, -1.5);
+        assert_eq!(tone_mapper.white_point, 8.0);
+        assert_eq!(description.render_settings.image_width, 64);
+        assert_eq!(description.render_settings.sampler, SamplerType::Halton);
+        assert_eq!(description.materials.len(), 4);
+        assert_eq!(description.shapes.len(), 2);
+        // the sun disk is part of the sky
+        assert_eq!(description.lights.len(), 3);
+        assert!(description.environment.is_some());
+        assert_eq!(
+            description.render_settings.filter,
+            Filter::Gaussian {
+                radius: 2.0,
+                alpha: 3.0
+            }
+        );
+
+        // one camera per face of the cubemap
+        let source = "camera cubemap position 0 1 0\nsettings width 16 height 16";
+        let description = SceneDescription::parse(source, Path::new("")).unwrap();
+        let suffixes: Vec<&str> = description
+            .cameras
+            .iter()
+            .map(|(suffix, _)| *suffix)
+            .collect();
+        assert_eq!(suffixes, ["px", "nx", "py", "ny", "pz", "nz"]);
+
+        // the shutter interval gives the times of the rays
+        let source = "camera perspective position 0 0 0 look 0 0 1 up 0 1 0 shutter_open 0.25 \
+                      shutter_close 0.5";
+        let description = SceneDescription::parse(source, Path::new("")).unwrap();
+        let camera = description.cameras[0].1.as_ref();
+        let lens_sample = Sample2D { s: 0.5, t: 0.5 };
+        assert_eq!(camera.generate_ray(0.5, 0.5, &lens_sample, 0.0).time, 0.25);
+        assert_eq!(camera.generate_ray(0.5, 0.5, &lens_sample, 1.0).time, 0.5);
+    }
+
+    #[test]
+    fn error_test() {
+        let camera = "camera perspective position 0 0 0 look 0 0 1 up 0 1 0\n";
+        assert_eq!(error_line(""), 0);
+        assert_eq!(error_line(&format!("{}\nfoo", camera)), 3);
+        assert_eq!(error_line(&format!("{}sky 1 1", camera)), 2);
+        assert_eq!(
+            error_line(&format!("{}material m diffuse colour 1 1 1", camera)),
+            2
+        );
+        assert_eq!(
+            error_line(&format!("{}material m diffuse color 1 x 1", camera)),
+            2
+        );
+        assert_eq!(
+            error_line(&format!("{}shape s sphere position 0 0 0", camera)),
+            2
+        );
+        assert_eq!(error_line(&format!("{}# comment\nentity s m", camera)), 3);
+        assert_eq!(
+            error_line("camera perspective position 0 0 0 look 0 0 1 up 0 0 1"),
+            1
+        );
+        assert_eq!(error_line("camera perspective fov \"90\""), 1);
+        assert_eq!(error_line("output \"image.png"), 1);
+        assert_eq!(error_line(&format!("{}tonemap filmic", camera)), 2);
+        assert_eq!(error_line(&format!("{}tonemap hable white 0", camera)), 2);
+        assert_eq!(error_line(&format!("{}filter tent alpha 2", camera)), 2);
+        assert_eq!(error_line(&format!("{}filter box radius 0", camera)), 2);
+        assert_eq!(error_line(&format!("{}sampler sobol 16", camera)), 2);
+        assert_eq!(
+            error_line(&format!("{}material m conductor preset \"brass\"", camera)),
+            2
+        );
+        assert_eq!(
+            error_line(&format!("{}material m conductor preset gold", camera)),
+            2
+        );
+        assert_eq!(
+            error_line(&format!("{}material m conductor eta 1 1 1", camera)),
+            2
+        );
+        assert_eq!(
+            error_line(&format!(
+                "{}material m transparent ior 1.5 roughness -1",
+                camera
+            )),
+            2
+        );
+        assert_eq!(
+            error_line(&format!(
+                "{}material m transparent ior 1.5 absorption 1 -1 1",
+                camera
+            )),
+            2
+        );
+        assert_eq!(error_line(&format!("{}light area", camera)), 2);
+        assert_eq!(
+            error_line(&format!("{}environment \"missing.exr\"", camera)),
+            2
+        );
+        assert_eq!(error_line(&format!("{}sky hosek sun 0 1 0", camera)), 2);
+        assert_eq!(error_line(&format!("{}sky preetham sun 0 -1 0", camera)), 2);
+        assert_eq!(
+            error_line(&format!("{}sky preetham sun 0 1 0 turbidity 1", camera)),
+            2
+        );
+        assert_eq!(
+            error_line(&format!("{}sky preetham sun 0 1 0\nsky 1 1 1", camera)),
+            3
+        );
+        assert_eq!(error_line(&format!("{}light point intensity 2", camera)), 2);
+        assert_eq!(
+            error_line(&format!(
+                "{}shape s sphere position 0 0 0 radius 1 spin 90 spin_axis 0 0 0",
+                camera
+            )),
+            2
+        );
+        assert_eq!(error_line(&format!("{}shape s instance t", camera)), 2);
+        assert_eq!(
+            error_line(&format!(
+                "{}shape s sphere position 0 0 0 radius 1 scale 1 0 1",
+                camera
+            )),
+            2
+        );
+        assert_eq!(
+            error_line("camera orthographic position 0 0 0 look 0 0 1 up 0 1 0"),
+            1
+        );
+        assert_eq!(
+            error_line("camera fisheye fov 400 position 0 0 0 look 0 0 1 up 0 1 0"),
+            1
+        );
+        assert_eq!(
+            error_line(
+                "camera equirectangular position 0 0 0 look 0 0 1 up 0 1 0 shutter_open 1 \
+                 shutter_close 0"
+            ),
+            1
+        );
+        assert_eq!(
+            error_line(
+                "camera fisheye mapping \"stereographic\" position 0 0 0 look 0 0 1 up 0 1 0"
+            ),
+            1
+        );
+        assert_eq!(
+            error_line("settings width 64 height 32\ncamera cubemap position 0 1 0"),
+            2
+        );
+        assert_eq!(
+            error_line("camera perspective position 0 0 0 look 0 0 1 up 0 1 0 aperture 0.1"),
+            1
+        );
+        assert_eq!(
+            error_line(
+                "camera perspective position 0 0 0 look 0 0 1 up 0 1 0 aperture 0.1 \
+                 focus_distance 2 blades 2"
+            ),
+            1
+        );
+        assert_eq!(
+            error_line(
+                "camera perspective position 0 0 0 look 0 0 1 up 0 1 0 \
+                 aperture_image \"missing.png\""
+            ),
+            1
+        );
+        assert_eq!(
+            error_line(&format!(
+                "{}light spot position 0 0 0 direction 0 -1 0 angle 20 falloff 30",
+                camera
+            )),
+            2
+        );
+        assert!(matches!(
+            SceneDescription::parse(&format!("{}obj \"missing.obj\"", camera), Path::new("")),
+            Err(SceneFileError::Obj { line: 2, .. })
+        ));
+    }
+}
@@ -1,14 +1,46 @@
+use crate::bvh::{Aabb, Bvh};
 use crate::camera::Ray;
 use crate::tools;
+use crate::tools::Sample2D;
 use crate::vector::Vector3;
+use std::f32::consts::PI;
 
 pub struct ShapeIntersection {
     pub t: f32, // negative t means no intersection
     pub surface_normal: Vector3,
 }
 
-pub trait Shape {
+#[derive(Debug)]
+pub struct ShapeSample {
+    pub point: Vector3,
+    pub normal: Vector3,
+    pub pdf: f32, // with respect to solid angle at the reference point, 0 for an invalid sample
+}
+
+pub trait Shape: Send + Sync {
     fn intersect(&self, ray: &Ray) -> ShapeIntersection;
+    fn bounds(&self) -> Aabb;
+
+    // sample a point on the surface as seen from reference_point
+    fn sample(&self, _reference_point: &Vector3, _sample: &Sample2D) -> ShapeSample {
+        ShapeSample::invalid_sample()
+    }
+
+    // solid angle pdf of sample() returning the point hit by the ray from reference_point
+    fn pdf(&self, _reference_point: &Vector3, _direction: &Vector3) -> f32 {
+        0.0
+    }
+}
+
+pub struct NoShape;
+impl Shape for NoShape {
+    fn intersect(&self, _ray: &Ray) -> ShapeIntersection {
+        ShapeIntersection::default()
+    }
+
+    fn bounds(&self) -> Aabb {
+        Aabb::empty()
+    }
 }
 
 pub struct Sphere {
@@ -26,6 +58,20 @@ pub struct Plane {
     right: Vector3,
 }
 
+pub struct Triangle {
+    pub v0: Vector3,
+    pub v1: Vector3,
+    pub v2: Vector3,
+}
+
+pub struct TriangleMesh {
+    positions: Vec<Vector3>,
+    normals: Option<Vec<Vector3>>, // per vertex normals, interpolated across each triangle
+    indices: Vec<u32>,             // three indices per triangle
+    bvh: Bvh,
+    area_cdf: Vec<f32>, // running sum of the triangle areas, used to sample triangles by area
+}
+
 impl Default for ShapeIntersection {
     fn default() -> ShapeIntersection {
         ShapeIntersection {
@@ -39,6 +85,61 @@ impl Default for ShapeIntersection {
     }
 }
 
+impl ShapeSample {
+    pub fn invalid_sample() -> ShapeSample {
+        ShapeSample {
+            point: Vector3::zero_vector(),
+            normal: Vector3::zero_vector(),
+            pdf: 0.0,
+        }
+    }
+}
+
+#[inline(always)]
+pub fn area_to_solid_angle_pdf(
+    pdf_area: f32,
+    reference_point: &Vector3,
+    point: &Vector3,
+    normal: &Vector3,
+) -> f32 {
+    let to_point = point - reference_point;
+    let distance2 = to_point.dot(&to_point);
+    if tools::equal_error(distance2, 0.0) {
+        return 0.0;
+    }
+    let cos_theta = f32::abs(normal.dot(&to_point)) / f32::sqrt(distance2);
+    if tools::equal_error(cos_theta, 0.0) {
+        return 0.0;
+    }
+    pdf_area * distance2 / cos_theta
+}
+
+#[inline(always)]
+fn sample_triangle(p0: &Vector3, p1: &Vector3, p2: &Vector3, sample: &Sample2D) -> Vector3 {
+    let su = f32::sqrt(sample.s);
+    let b0 = 1.0 - su;
+    let b1 = sample.t * su;
+    &(&(p0 * b0) + &(p1 * b1)) + &(p2 * (1.0 - b0 - b1))
+}
+
+impl Sphere {
+    #[inline(always)]
+    fn cos_theta_max(&self, reference_point: &Vector3) -> Option<f32> {
+        let to_center = &self.position - reference_point;
+        let distance2 = to_center.dot(&to_center);
+        let radius2 = self.radius * self.radius;
+        if distance2 <= radius2 {
+            return None; // inside the sphere, the cone covers every direction
+        }
+        Some(f32::sqrt(1.0 - radius2 / distance2))
+    }
+
+    fn area_pdf_at(&self, reference_point: &Vector3, point: &Vector3, normal: &Vector3) -> f32 {
+        let pdf_area = 1.0 / (4.0 * PI * self.radius * self.radius);
+        area_to_solid_angle_pdf(pdf_area, reference_point, point, normal)
+    }
+}
+
 impl Shape for Sphere {
     fn intersect(&self, ray: &Ray) -> ShapeIntersection {
         let mut intersection = ShapeIntersection::default();
@@ -71,6 +172,87 @@ impl Shape for Sphere {
 
         intersection
     }
+
+    fn bounds(&self) -> Aabb {
+        let extent = Vector3::new(self.radius, self.radius, self.radius);
+        Aabb {
+            min: &self.position - &extent,
+            max: &self.position + &extent,
+        }
+    }
+
+    fn sample(&self, reference_point: &Vector3, sample: &Sample2D) -> ShapeSample {
+        let cos_theta_max = match self.cos_theta_max(reference_point) {
+            Some(cos_theta_max) => cos_theta_max,
+            None => {
+                // uniform area sampling
+                let z = 1.0 - 2.0 * sample.s;
+                let r = f32::sqrt(f32::max(0.0, 1.0 - z * z));
+                let phi = 2.0 * PI * sample.t;
+                let normal = Vector3::new(r * f32::cos(phi), r * f32::sin(phi), z);
+                let point = &self.position + &(&normal * self.radius);
+                let pdf = self.area_pdf_at(reference_point, &point, &normal);
+                return ShapeSample { point, normal, pdf };
+            }
+        };
+
+        // uniform sampling of the cone of directions subtended by the sphere
+        let axis = (&self.position - reference_point).unit();
+        let mut b = Vector3::zero_vector();
+        let mut t = Vector3::zero_vector();
+        axis.create_basis(&mut b, &mut t);
+        let cos_theta = 1.0 - sample.s * (1.0 - cos_theta_max);
+        let sin_theta = f32::sqrt(f32::max(0.0, 1.0 - cos_theta * cos_theta));
+        let phi = 2.0 * PI * sample.t;
+        let direction = Vector3::to_basis(
+            &Vector3::new(
+                sin_theta * f32::cos(phi),
+                sin_theta * f32::sin(phi),
+                cos_theta,
+            ),
+            &axis,
+            &t,
+            &b,
+        )
+        .unit();
+
+        let ray = Ray {
+            origin: *reference_point,
+            direction,
+            time: 0.0,
+        };
+        let mut distance = self.intersect(&ray).t;
+        if distance < 0.0 {
+            // grazing direction missed by rounding, use the closest point along the ray
+            distance = direction.dot(&(&self.position - reference_point));
+        }
+        let point = &ray.origin + &(&direction * distance);
+        ShapeSample {
+            normal: (&point - &self.position).unit(),
+            point,
+            pdf: 1.0 / (2.0 * PI * (1.0 - cos_theta_max)),
+        }
+    }
+
+    fn pdf(&self, reference_point: &Vector3, direction: &Vector3) -> f32 {
+        let ray = Ray {
+            origin: *reference_point,
+            direction: *direction,
+            time: 0.0,
+        };
+        let intersection = self.intersect(&ray);
+        if intersection.t < 0.0 {
+            return 0.0;
+        }
+
+        match self.cos_theta_max(reference_point) {
+            Some(cos_theta_max) => 1.0 / (2.0 * PI * (1.0 - cos_theta_max)),
+            None => {
+                let point = &ray.origin + &(direction * intersection.t);
+                self.area_pdf_at(reference_point, &point, &intersection.surface_normal)
+            }
+        }
+    }
 }
 
 impl Plane {
@@ -123,6 +305,384 @@ impl Shape for Plane {
 
         intersection
     }
+
+    fn bounds(&self) -> Aabb {
+        let right = &self.right * self.half_width;
+        let up = &self.up * self.half_height;
+        Aabb::from_points(&[
+            &(&self.position + &right) + &up,
+            &(&self.position + &right) - &up,
+            &(&self.position - &right) + &up,
+            &(&self.position - &right) - &up,
+        ])
+    }
+
+    // uniform area sampling
+    fn sample(&self, reference_point: &Vector3, sample: &Sample2D) -> ShapeSample {
+        let right = &self.right * ((2.0 * sample.s - 1.0) * self.half_width);
+        let up = &self.up * ((2.0 * sample.t - 1.0) * self.half_height);
+        let point = &(&self.position + &right) + &up;
+        let pdf_area = 1.0 / (4.0 * self.half_width * self.half_height);
+        ShapeSample {
+            pdf: area_to_solid_angle_pdf(pdf_area, reference_point, &point, &self.normal),
+            normal: self.normal,
+            point,
+        }
+    }
+
+    fn pdf(&self, reference_point: &Vector3, direction: &Vector3) -> f32 {
+        let ray = Ray {
+            origin: *reference_point,
+            direction: *direction,
+            time: 0.0,
+        };
+        let intersection = self.intersect(&ray);
+        if intersection.t < 0.0 {
+            return 0.0;
+        }
+        let point = &ray.origin + &(direction * intersection.t);
+        let pdf_area = 1.0 / (4.0 * self.half_width * self.half_height);
+        area_to_solid_angle_pdf(pdf_area, reference_point, &point, &self.normal)
+    }
+}
+
+struct TriangleHit {
+    t: f32,
+    barycentrics: [f32; 3],
+}
+
+// Watertight ray-triangle intersection (Woop, Benthin and Wald 2013): rays hitting a shared edge or
+// vertex always hit at least one of the adjacent triangles.
+#[inline(always)]
+fn intersect_triangle(ray: &Ray, p0: &Vector3, p1: &Vector3, p2: &Vector3) -> Option<TriangleHit> {
+    // permute the axes so that the ray direction is largest along z
+    let abs_direction = Vector3::new(
+        ray.direction.x.abs(),
+        ray.direction.y.abs(),
+        ray.direction.z.abs(),
+    );
+    let kz = if abs_direction.x > abs_direction.y {
+        if abs_direction.x > abs_direction.z {
+            0
+        } else {
+            2
+        }
+    } else if abs_direction.y > abs_direction.z {
+        1
+    } else {
+        2
+    };
+    let mut kx = (kz + 1) % 3;
+    let mut ky = (kx + 1) % 3;
+    if ray.direction[kz] < 0.0 {
+        std::mem::swap(&mut kx, &mut ky);
+    }
+
+    // shear so that the ray points down +z
+    let sx = ray.direction[kx] / ray.direction[kz];
+    let sy = ray.direction[ky] / ray.direction[kz];
+    let sz = 1.0 / ray.direction[kz];
+
+    let a = p0 - &ray.origin;
+    let b = p1 - &ray.origin;
+    let c = p2 - &ray.origin;
+    let ax = a[kx] - sx * a[kz];
+    let ay = a[ky] - sy * a[kz];
+    let bx = b[kx] - sx * b[kz];
+    let by = b[ky] - sy * b[kz];
+    let cx = c[kx] - sx * c[kz];
+    let cy = c[ky] - sy * c[kz];
+
+    let mut u = cx * by - cy * bx;
+    let mut v = ax * cy - ay * cx;
+    let mut w = bx * ay - by * ax;
+    if u == 0.0 || v == 0.0 || w == 0.0 {
+        // fall back to double precision on the edges
+        u = (cx as f64 * by as f64 - cy as f64 * bx as f64) as f32;
+        v = (ax as f64 * cy as f64 - ay as f64 * cx as f64) as f32;
+        w = (bx as f64 * ay as f64 - by as f64 * ax as f64) as f32;
+    }
+
+    if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+        return None;
+    }
+
+    let det = u + v + w;
+    if det == 0.0 {
+        return None;
+    }
+
+    let t_scaled = u * sz * a[kz] + v * sz * b[kz] + w * sz * c[kz];
+    if (det < 0.0 && t_scaled > 0.0) || (det > 0.0 && t_scaled < 0.0) {
+        return None;
+    }
+
+    let inv_det = 1.0 / det;
+    Some(TriangleHit {
+        t: t_scaled * inv_det,
+        barycentrics: [u * inv_det, v * inv_det, w * inv_det],
+    })
+}
+
+#[inline(always)]
+fn triangle_normal(p0: &Vector3, p1: &Vector3, p2: &Vector3) -> Vector3 {
+    (p1 - p0).cross(&(p2 - p0))
+}
+
+impl Shape for Triangle {
+    fn intersect(&self, ray: &Ray) -> ShapeIntersection {
+        let mut intersection = ShapeIntersection::default();
+        let normal = triangle_normal(&self.v0, &self.v1, &self.v2);
+        if normal.is_zero() {
+            return intersection; // degenerate triangle
+        }
+
+        if let Some(hit) = intersect_triangle(ray, &self.v0, &self.v1, &self.v2) {
+            intersection.t = hit.t;
+            intersection.surface_normal = normal.unit();
+        }
+
+        intersection
+    }
+
+    fn bounds(&self) -> Aabb {
+        Aabb::from_points(&[self.v0, self.v1, self.v2])
+    }
+
+    // uniform area sampling
+    fn sample(&self, reference_point: &Vector3, sample: &Sample2D) -> ShapeSample {
+        let normal = triangle_normal(&self.v0, &self.v1, &self.v2);
+        if normal.is_zero() {
+            return ShapeSample::invalid_sample();
+        }
+        let pdf_area = 2.0 / normal.length();
+        let normal = normal.unit();
+        let point = sample_triangle(&self.v0, &self.v1, &self.v2, sample);
+        ShapeSample {
+            pdf: area_to_solid_angle_pdf(pdf_area, reference_point, &point, &normal),
+            normal,
+            point,
+        }
+    }
+
+    fn pdf(&self, reference_point: &Vector3, direction: &Vector3) -> f32 {
+        let normal = triangle_normal(&self.v0, &self.v1, &self.v2);
+        let ray = Ray {
+            origin: *reference_point,
+            direction: *direction,
+            time: 0.0,
+        };
+        let intersection = self.intersect(&ray);
+        if intersection.t < 0.0 {
+            return 0.0;
+        }
+        let point = &ray.origin + &(direction * intersection.t);
+        let pdf_area = 2.0 / normal.length();
+        area_to_solid_angle_pdf(pdf_area, reference_point, &point, &normal.unit())
+    }
+}
+
+impl TriangleMesh {
+    pub fn new(
+        positions: Vec<Vector3>,
+        normals: Option<Vec<Vector3>>,
+        indices: Vec<u32>,
+    ) -> TriangleMesh {
+        debug_assert!(indices.len() % 3 == 0);
+        debug_assert!(indices
+            .iter()
+            .all(|index| (*index as usize) < positions.len()));
+        debug_assert!(normals
+            .as_ref()
+            .map_or(true, |normals| normals.len() == positions.len()));
+        let triangle_bounds: Vec<Aabb> = indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                Aabb::from_points(&[
+                    positions[triangle[0] as usize],
+                    positions[triangle[1] as usize],
+                    positions[triangle[2] as usize],
+                ])
+            })
+            .collect();
+        let mut area_cdf = Vec::with_capacity(indices.len() / 3);
+        let mut total_area = 0.0;
+        for triangle in indices.chunks_exact(3) {
+            total_area += 0.5
+                * triangle_normal(
+                    &positions[triangle[0] as usize],
+                    &positions[triangle[1] as usize],
+                    &positions[triangle[2] as usize],
+                )
+                .length();
+            area_cdf.push(total_area);
+        }
+        TriangleMesh {
+            positions,
+            normals,
+            indices,
+            bvh: Bvh::build(&triangle_bounds),
+            area_cdf,
+        }
+    }
+
+    fn total_area(&self) -> f32 {
+        self.area_cdf.last().copied().unwrap_or(0.0)
+    }
+
+    fn geometric_normal(&self, triangle: usize) -> Vector3 {
+        let [i0, i1, i2] = self.triangle_indices(triangle);
+        triangle_normal(
+            &self.positions[i0],
+            &self.positions[i1],
+            &self.positions[i2],
+        )
+        .unit()
+    }
+
+    fn closest_hit(&self, ray: &Ray) -> Option<(usize, TriangleHit)> {
+        let mut closest: Option<(usize, TriangleHit)> = None;
+        self.bvh.traverse(ray, |triangle| {
+            match self.intersect_triangle(triangle, ray) {
+                Some(hit) => {
+                    let t = hit.t;
+                    if closest
+                        .as_ref()
+                        .map_or(true, |(_, closest)| hit.t < closest.t)
+                    {
+                        closest = Some((triangle, hit));
+                    }
+                    t
+                }
+                None => -1.0,
+            }
+        });
+        closest
+    }
+
+    pub fn num_of_triangles(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    #[inline(always)]
+    fn triangle_indices(&self, triangle: usize) -> [usize; 3] {
+        [
+            self.indices[triangle * 3] as usize,
+            self.indices[triangle * 3 + 1] as usize,
+            self.indices[triangle * 3 + 2] as usize,
+        ]
+    }
+
+    fn intersect_triangle(&self, triangle: usize, ray: &Ray) -> Option<TriangleHit> {
+        let [i0, i1, i2] = self.triangle_indices(triangle);
+        intersect_triangle(
+            ray,
+            &self.positions[i0],
+            &self.positions[i1],
+            &self.positions[i2],
+        )
+    }
+
+    fn surface_normal(&self, triangle: usize, hit: &TriangleHit) -> Vector3 {
+        let [i0, i1, i2] = self.triangle_indices(triangle);
+        let geometric_normal = self.geometric_normal(triangle);
+        let normals = match &self.normals {
+            Some(normals) => normals,
+            None => return geometric_normal,
+        };
+
+        let [b0, b1, b2] = hit.barycentrics;
+        let shading_normal = &(&(&normals[i0] * b0) + &(&normals[i1] * b1)) + &(&normals[i2] * b2);
+        if shading_normal.is_zero() {
+            return geometric_normal;
+        }
+
+        // keep the interpolated normal on the same side as the triangle winding
+        let shading_normal = shading_normal.unit();
+        if shading_normal.dot(&geometric_normal) < 0.0 {
+            -&shading_normal
+        } else {
+            shading_normal
+        }
+    }
+}
+
+impl Shape for TriangleMesh {
+    fn intersect(&self, ray: &Ray) -> ShapeIntersection {
+        let mut intersection = ShapeIntersection::default();
+        if let Some((triangle, hit)) = self.closest_hit(ray) {
+            intersection.t = hit.t;
+            intersection.surface_normal = self.surface_normal(triangle, &hit);
+        }
+
+        intersection
+    }
+
+    fn bounds(&self) -> Aabb {
+        self.bvh.bounds()
+    }
+
+    // uniform area sampling over the whole mesh
+    fn sample(&self, reference_point: &Vector3, sample: &Sample2D) -> ShapeSample {
+        let total_area = self.total_area();
+        if total_area <= 0.0 {
+            return ShapeSample::invalid_sample();
+        }
+
+        // pick a triangle proportionally to its area and reuse the sample to place the point
+        let target = sample.s * total_area;
+        let triangle = self
+            .area_cdf
+            .partition_point(|area| *area <= target)
+            .min(self.area_cdf.len() - 1);
+        let area_before = if triangle > 0 {
+            self.area_cdf[triangle - 1]
+        } else {
+            0.0
+        };
+        let triangle_area = self.area_cdf[triangle] - area_before;
+        if triangle_area <= 0.0 {
+            return ShapeSample::invalid_sample();
+        }
+        let remapped_sample = Sample2D {
+            s: ((target - area_before) / triangle_area).clamp(0.0, 1.0),
+            t: sample.t,
+        };
+
+        let [i0, i1, i2] = self.triangle_indices(triangle);
+        let point = sample_triangle(
+            &self.positions[i0],
+            &self.positions[i1],
+            &self.positions[i2],
+            &remapped_sample,
+        );
+        let normal = self.geometric_normal(triangle);
+        ShapeSample {
+            pdf: area_to_solid_angle_pdf(1.0 / total_area, reference_point, &point, &normal),
+            normal,
+            point,
+        }
+    }
+
+    fn pdf(&self, reference_point: &Vector3, direction: &Vector3) -> f32 {
+        let ray = Ray {
+            origin: *reference_point,
+            direction: *direction,
+            time: 0.0,
+        };
+        match self.closest_hit(&ray) {
+            Some((triangle, hit)) => {
+                let point = &ray.origin + &(direction * hit.t);
+                area_to_solid_angle_pdf(
+                    1.0 / self.total_area(),
+                    reference_point,
+                    &point,
+                    &self.geometric_normal(triangle),
+                )
+            }
+            None => 0.0,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -130,8 +690,9 @@ mod shape_tests {
     use super::Plane;
     use super::Shape;
     use super::Sphere;
-    use crate::camera::Camera;
+    use super::{Triangle, TriangleMesh};
     use crate::camera::Ray;
+    use crate::camera::{Camera, CameraModel};
     use crate::film::Film;
     use crate::tools;
     use crate::vector::Vector3;
@@ -157,6 +718,7 @@ mod shape_tests {
                 y: 0.0,
                 z: 1.0,
             },
+            time: 0.0,
         };
         let sphere_intersection = sphere.intersect(&ray);
         assert!(sphere_intersection.t > 0.0);
@@ -224,7 +786,8 @@ mod shape_tests {
             let film_x = (x as f32 + 0.5) / (width as f32);
             for y in 0..height {
                 let film_y = (y as f32 + 0.5) / (height as f32);
-                let ray = camera.generate_ray(film_x, film_y);
+                let ray =
+                    camera.generate_ray(film_x, film_y, &tools::Sample2D { s: 0.5, t: 0.5 }, 0.0);
                 let intersection = sphere.intersect(&ray);
                 if intersection.t >= 0.0 {
                     assert!(tools::equal_error(
@@ -236,7 +799,7 @@ mod shape_tests {
                         y: 0.05,
                         z: 1.0 / intersection.t,
                     };
-                    film.add_sample(x, y, &sample_radiance);
+                    film.add_sample(x as f32 + 0.5, y as f32 + 0.5, &sample_radiance);
                 }
             }
         }
@@ -295,7 +858,8 @@ mod shape_tests {
             let film_x = (x as f32 + 0.5) / (width as f32);
             for y in 0..height {
                 let film_y = (y as f32 + 0.5) / (height as f32);
-                let ray = camera.generate_ray(film_x, film_y);
+                let ray =
+                    camera.generate_ray(film_x, film_y, &tools::Sample2D { s: 0.5, t: 0.5 }, 0.0);
                 let intersection = plane.intersect(&ray);
                 if intersection.t > 0.0 {
                     let sample_radiance = Vector3 {
@@ -303,11 +867,180 @@ mod shape_tests {
                         y: 0.1,
                         z: 1.0 / intersection.t,
                     };
-                    film.add_sample(x, y, &sample_radiance);
+                    film.add_sample(x as f32 + 0.5, y as f32 + 0.5, &sample_radiance);
                 }
             }
         }
 
         film.save_image("camera_ray_plane_intersections_test.png");
     }
+
+    #[test]
+    fn triangle_test() {
+        let triangle = Triangle {
+            v0: Vector3::new(-1.0, -1.0, 2.0),
+            v1: Vector3::new(1.0, -1.0, 2.0),
+            v2: Vector3::new(0.0, 1.0, 2.0),
+        };
+        let mut ray = Ray {
+            origin: Vector3::zero_vector(),
+            direction: Vector3::new(0.0, 0.0, 1.0),
+            time: 0.0,
+        };
+        let intersection = triangle.intersect(&ray);
+        assert!(tools::equal_error(intersection.t, 2.0));
+        assert!(intersection.surface_normal == Vector3::new(0.0, 0.0, 1.0));
+
+        ray.direction = Vector3::new(0.0, 0.0, -1.0);
+        assert!(triangle.intersect(&ray).t < 0.0);
+
+        ray.direction = Vector3::new(1.0, 1.0, 2.0).unit();
+        assert!(triangle.intersect(&ray).t < 0.0);
+    }
+
+    #[test]
+    fn triangle_mesh_watertight_test() {
+        // a 4x4 grid of quads on the z = 1 plane, rays are aimed exactly at interior edges and vertices
+        let mut positions = Vec::new();
+        for y in 0..5 {
+            for x in 0..5 {
+                positions.push(Vector3::new(
+                    x as f32 * 0.5 - 1.0,
+                    y as f32 * 0.5 - 1.0,
+                    1.0,
+                ));
+            }
+        }
+        let mut indices = Vec::new();
+        for y in 0..4 {
+            for x in 0..4 {
+                let i = y * 5 + x;
+                indices.extend_from_slice(&[i, i + 1, i + 6, i, i + 6, i + 5]);
+            }
+        }
+        let mesh = TriangleMesh::new(positions, None, indices);
+        assert_eq!(mesh.num_of_triangles(), 32);
+
+        let origin = Vector3::new(0.013, -0.027, -1.0);
+        for y in 1..16 {
+            for x in 1..16 {
+                let target = Vector3::new(x as f32 * 0.125 - 1.0, y as f32 * 0.125 - 1.0, 1.0);
+                let ray = Ray {
+                    origin,
+                    direction: (&target - &origin).unit(),
+                    time: 0.0,
+                };
+                let intersection = mesh.intersect(&ray);
+                assert!(intersection.t > 0.0, "ray towards {:?} missed", target);
+                assert!(intersection.surface_normal == Vector3::new(0.0, 0.0, 1.0));
+            }
+        }
+    }
+
+    #[test]
+    fn triangle_mesh_smooth_normal_test() {
+        let positions = vec![
+            Vector3::new(-1.0, 0.0, 0.0),
+            Vector3::new(1.0, 0.0, 0.0),
+            Vector3::new(0.0, 0.0, 1.0),
+        ];
+        let normals = vec![
+            Vector3::new(-1.0, 1.0, 0.0).unit(),
+            Vector3::new(1.0, 1.0, 0.0).unit(),
+            Vector3::new(0.0, 1.0, 0.0),
+        ];
+        let mesh = TriangleMesh::new(positions, Some(normals), vec![0, 2, 1]);
+        let ray = Ray {
+            origin: Vector3::new(0.0, 1.0, 0.25),
+            direction: Vector3::new(0.0, -1.0, 0.0),
+            time: 0.0,
+        };
+        let intersection = mesh.intersect(&ray);
+        assert!(tools::equal_error(intersection.t, 1.0));
+        assert!(intersection.surface_normal == Vector3::new(0.0, 1.0, 0.0));
+
+        let ray = Ray {
+            origin: Vector3::new(0.5, 1.0, 0.25),
+            direction: Vector3::new(0.0, -1.0, 0.0),
+            time: 0.0,
+        };
+        let intersection = mesh.intersect(&ray);
+        assert!(tools::equal_error(
+            intersection.surface_normal.length(),
+            1.0
+        ));
+        assert!(intersection.surface_normal.x > 0.0 && intersection.surface_normal.y > 0.0);
+    }
+
+    #[test]
+    fn sampling_test() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        // the mean of 1 / pdf estimates the solid angle subtended by the shape
+        fn solid_angle(shape: &dyn Shape, reference_point: &Vector3) -> f32 {
+            let mut rgen = StdRng::seed_from_u64(3);
+            let num_of_samples = 20000;
+            let mut sum = 0.0;
+            for _ in 0..num_of_samples {
+                let sample = tools::Sample2D {
+                    s: rgen.gen(),
+                    t: rgen.gen(),
+                };
+                let shape_sample = shape.sample(reference_point, &sample);
+                assert!(shape_sample.pdf > 0.0);
+                let direction = (&shape_sample.point - reference_point).unit();
+                let pdf = shape.pdf(reference_point, &direction);
+                assert!((pdf - shape_sample.pdf).abs() <= 0.01 * pdf);
+                sum += 1.0 / shape_sample.pdf;
+            }
+            sum / num_of_samples as f32
+        }
+
+        let reference_point = Vector3::zero_vector();
+        let sphere = Sphere {
+            position: Vector3::new(0.0, 0.0, 4.0),
+            radius: 2.0,
+        };
+        let cone = 2.0 * std::f32::consts::PI * (1.0 - f32::sqrt(1.0 - 0.25));
+        assert!((solid_angle(&sphere, &reference_point) - cone).abs() < 1e-3);
+
+        // a rectangle a x b centered in front of the point at distance d
+        let (a, b, d) = (2.0_f32, 3.0_f32, 1.5_f32);
+        let rectangle =
+            4.0 * f32::asin(a * b / f32::sqrt((a * a + 4.0 * d * d) * (b * b + 4.0 * d * d)));
+        let plane = Plane::new(
+            Vector3::new(0.0, 0.0, d),
+            Vector3::new(0.0, 0.0, -1.0),
+            Vector3::new(0.0, 1.0, 0.0),
+            a,
+            b,
+        );
+        assert!((solid_angle(&plane, &reference_point) - rectangle).abs() < 0.05 * rectangle);
+
+        let mesh = TriangleMesh::new(
+            vec![
+                Vector3::new(-1.0, -1.5, d),
+                Vector3::new(1.0, -1.5, d),
+                Vector3::new(1.0, 1.5, d),
+                Vector3::new(-1.0, 1.5, d),
+            ],
+            None,
+            vec![0, 2, 1, 0, 3, 2],
+        );
+        assert!((solid_angle(&mesh, &reference_point) - rectangle).abs() < 0.05 * rectangle);
+
+        let triangle = Triangle {
+            v0: Vector3::new(-1.0, -1.5, d),
+            v1: Vector3::new(1.0, 1.5, d),
+            v2: Vector3::new(1.0, -1.5, d),
+        };
+        assert!(
+            (solid_angle(&triangle, &reference_point) - 0.5 * rectangle).abs() < 0.05 * rectangle
+        );
+
+        // from inside, a sphere covers every direction
+        let inside = solid_angle(&sphere, &Vector3::new(0.0, 0.5, 4.0));
+        assert!((inside - 4.0 * std::f32::consts::PI).abs() < 0.05 * 4.0 * std::f32::consts::PI);
+    }
 }
@@ -0,0 +1,147 @@
+use crate::vector::Vector3;
+
+// Curve used to bring the linear radiance of the film into the [0, 1] range of 8-bit images
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToneMappingOperator {
+    Clamp,
+    Reinhard,
+    ExtendedReinhard, // Reinhard reaching 1 at the white point
+    Hable,            // Uncharted 2 filmic curve, normalized by the white point
+    Aces,             // Narkowicz fit of the ACES reference rendering transform
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneMapper {
+    pub operator: ToneMappingOperator,
+    pub exposure: f32, // in stops, the radiance is scaled by 2^exposure before the curve
+    pub white_point: f32, // exposed radiance mapped to white by the extended Reinhard and Hable
+}
+
+impl ToneMappingOperator {
+    pub fn from_name(name: &str) -> Option<ToneMappingOperator> {
+        match name {
+            "clamp" => Some(ToneMappingOperator::Clamp),
+            "reinhard" => Some(ToneMappingOperator::Reinhard),
+            "extended_reinhard" => Some(ToneMappingOperator::ExtendedReinhard),
+            "hable" => Some(ToneMappingOperator::Hable),
+            "aces" => Some(ToneMappingOperator::Aces),
+            _ => None,
+        }
+    }
+}
+
+impl Default for ToneMapper {
+    // keeps the linear radiance as it is, clamped to 1
+    fn default() -> ToneMapper {
+        ToneMapper {
+            operator: ToneMappingOperator::Clamp,
+            exposure: 0.0,
+            white_point: 4.0,
+        }
+    }
+}
+
+fn hable_curve(x: f32) -> f32 {
+    const A: f32 = 0.15; // shoulder strength
+    const B: f32 = 0.50; // linear strength
+    const C: f32 = 0.10; // linear angle
+    const D: f32 = 0.20; // toe strength
+    const E: f32 = 0.02; // toe numerator
+    const F: f32 = 0.30; // toe denominator
+    ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+}
+
+impl ToneMapper {
+    fn map_component(&self, x: f32) -> f32 {
+        let x = x.max(0.0);
+        let white_point = self.white_point;
+        let mapped = match self.operator {
+            ToneMappingOperator::Clamp => x,
+            ToneMappingOperator::Reinhard => x / (1.0 + x),
+            ToneMappingOperator::ExtendedReinhard => {
+                x * (1.0 + x / (white_point * white_point)) / (1.0 + x)
+            }
+            ToneMappingOperator::Hable => hable_curve(x) / hable_curve(white_point),
+            ToneMappingOperator::Aces => (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14),
+        };
+        mapped.clamp(0.0, 1.0)
+    }
+
+    // linear radiance to linear display values in [0, 1], to_srgb still needs to be applied
+    pub fn apply(&self, linear_color: &Vector3) -> Vector3 {
+        let exposed = linear_color * self.exposure.exp2();
+        Vector3 {
+            x: self.map_component(exposed.x),
+            y: self.map_component(exposed.y),
+            z: self.map_component(exposed.z),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tonemap_tests {
+    use super::{ToneMapper, ToneMappingOperator};
+    use crate::tools;
+    use crate::vector::Vector3;
+
+    #[test]
+    fn operators_test() {
+        let operators = [
+            ToneMappingOperator::Clamp,
+            ToneMappingOperator::Reinhard,
+            ToneMappingOperator::ExtendedReinhard,
+            ToneMappingOperator::Hable,
+            ToneMappingOperator::Aces,
+        ];
+        for operator in operators {
+            let tone_mapper = ToneMapper {
+                operator,
+                ..ToneMapper::default()
+            };
+            // every curve is monotonic and stays in the displayable range
+            let mut previous = 0.0;
+            for i in 0..1000 {
+                let radiance = i as f32 * 0.02;
+                let mapped = tone_mapper.apply(&Vector3::new(radiance, radiance, radiance));
+                assert!(mapped.x >= previous && mapped.x <= 1.0, "{:?}", operator);
+                previous = mapped.x;
+            }
+            assert!(tone_mapper.apply(&Vector3::zero_vector()).x.abs() < 1e-3);
+        }
+
+        let clamp = ToneMapper::default();
+        assert!(clamp.apply(&Vector3::new(0.25, 0.5, 3.0)) == Vector3::new(0.25, 0.5, 1.0));
+
+        // the white point is mapped to 1
+        for operator in [
+            ToneMappingOperator::ExtendedReinhard,
+            ToneMappingOperator::Hable,
+        ] {
+            let tone_mapper = ToneMapper {
+                operator,
+                exposure: 0.0,
+                white_point: 6.0,
+            };
+            let white = tone_mapper.apply(&Vector3::new(6.0, 6.0, 6.0));
+            assert!(tools::equal_error(white.x, 1.0));
+            assert!(tone_mapper.apply(&Vector3::new(5.0, 5.0, 5.0)).x < 1.0);
+        }
+
+        // one stop of exposure doubles the radiance
+        let exposed = ToneMapper {
+            operator: ToneMappingOperator::Reinhard,
+            exposure: 1.0,
+            white_point: 4.0,
+        };
+        assert!(tools::equal_error(
+            exposed.apply(&Vector3::new(1.0, 1.0, 1.0)).x,
+            2.0 / 3.0
+        ));
+
+        assert_eq!(
+            ToneMappingOperator::from_name("aces"),
+            Some(ToneMappingOperator::Aces)
+        );
+        assert_eq!(ToneMappingOperator::from_name("filmic"), None);
+    }
+}
@@ -1,8 +1,3 @@
-use rand::{
-    distributions::uniform::{UniformFloat, UniformSampler},
-    rngs::ThreadRng,
-};
-
 const ERROR: f32 = 0.0001;
 
 pub fn equal_error(f0: f32, f1: f32) -> bool {
@@ -25,9 +20,75 @@ pub fn is_negative_error(f0: f32) -> bool {
     f0 < -ERROR
 }
 
-pub struct Sampler {
-    rgen: ThreadRng,
-    distribution: UniformFloat<f32>,
+// splitmix64 finalizer, turns related integers into unrelated ones
+pub fn mix_bits(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+const PCG_MULTIPLIER: u64 = 0x5851F42D4C957F2D;
+
+// PCG32 generator (XSH RR output) of O'Neill, with 2^63 independent streams that can be
+// advanced to any position in logarithmic time
+#[derive(Debug, Clone, Copy)]
+pub struct Pcg32 {
+    state: u64,
+    increment: u64,
+}
+
+impl Pcg32 {
+    pub fn new(stream: u64, seed: u64) -> Pcg32 {
+        let mut rgen = Pcg32 {
+            state: 0,
+            increment: (stream << 1) | 1,
+        };
+        rgen.next_u32();
+        rgen.state = rgen.state.wrapping_add(seed);
+        rgen.next_u32();
+        rgen
+    }
+
+    #[inline(always)]
+    pub fn next_u32(&mut self) -> u32 {
+        let old_state = self.state;
+        self.state = old_state
+            .wrapping_mul(PCG_MULTIPLIER)
+            .wrapping_add(self.increment);
+        let xor_shifted = (((old_state >> 18) ^ old_state) >> 27) as u32;
+        let rotation = (old_state >> 59) as u32;
+        xor_shifted.rotate_right(rotation)
+    }
+
+    // uniform in [0, 1)
+    #[inline(always)]
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 * (1.0 / 16777216.0)
+    }
+
+    // skips delta numbers, by composing the linear congruential steps
+    pub fn advance(&mut self, delta: u64) {
+        let mut multiplier = PCG_MULTIPLIER;
+        let mut increment = self.increment;
+        let mut accumulated_multiplier: u64 = 1;
+        let mut accumulated_increment: u64 = 0;
+        let mut delta = delta;
+        while delta > 0 {
+            if delta & 1 == 1 {
+                accumulated_multiplier = accumulated_multiplier.wrapping_mul(multiplier);
+                accumulated_increment = accumulated_increment
+                    .wrapping_mul(multiplier)
+                    .wrapping_add(increment);
+            }
+            increment = multiplier.wrapping_add(1).wrapping_mul(increment);
+            multiplier = multiplier.wrapping_mul(multiplier);
+            delta >>= 1;
+        }
+        self.state = accumulated_multiplier
+            .wrapping_mul(self.state)
+            .wrapping_add(accumulated_increment);
+    }
 }
 
 pub struct Sample2D {
@@ -35,26 +96,208 @@ pub struct Sample2D {
     pub t: f32,
 }
 
-impl Default for Sampler {
-    fn default() -> Self {
-        Self {
-            rgen: rand::thread_rng(),
-            distribution: UniformFloat::new(0.0, 1.0),
+// maps the unit square to the unit disk keeping the strata of the sample undistorted,
+// Shirley and Chiu's concentric mapping
+pub fn concentric_sample_disk(sample: &Sample2D) -> (f32, f32) {
+    let s = 2.0 * sample.s - 1.0;
+    let t = 2.0 * sample.t - 1.0;
+    if s == 0.0 && t == 0.0 {
+        return (0.0, 0.0);
+    }
+    let (radius, theta) = if s.abs() > t.abs() {
+        (s, std::f32::consts::FRAC_PI_4 * (t / s))
+    } else {
+        (
+            t,
+            std::f32::consts::FRAC_PI_2 - std::f32::consts::FRAC_PI_4 * (s / t),
+        )
+    };
+    (radius * theta.cos(), radius * theta.sin())
+}
+
+// Piecewise constant distribution over [0, 1), proportional to non-negative function values. A
+// function that is zero everywhere gives the uniform distribution
+#[derive(Debug, Clone)]
+pub struct Distribution1D {
+    function: Vec<f32>,
+    cdf: Vec<f32>, // one more entry than the function, from 0 to 1
+    integral: f32,
+}
+
+impl Distribution1D {
+    pub fn new(function: &[f32]) -> Distribution1D {
+        let count = function.len();
+        let mut cdf = Vec::with_capacity(count + 1);
+        cdf.push(0.0);
+        for (i, value) in function.iter().enumerate() {
+            cdf.push(cdf[i] + value / count as f32);
+        }
+        let integral = cdf[count];
+        for (i, value) in cdf.iter_mut().enumerate() {
+            *value = if integral > 0.0 {
+                *value / integral
+            } else {
+                i as f32 / count as f32
+            };
+        }
+        Distribution1D {
+            function: function.to_vec(),
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn integral(&self) -> f32 {
+        self.integral
+    }
+
+    fn density(&self, offset: usize) -> f32 {
+        if self.integral > 0.0 {
+            self.function[offset] / self.integral
+        } else {
+            1.0
+        }
+    }
+
+    // point in [0, 1) with its pdf, and the index of the constant piece it falls in
+    pub fn sample(&self, u: f32) -> (f32, f32, usize) {
+        let count = self.function.len();
+        let offset = usize::min(
+            self.cdf
+                .partition_point(|value| *value <= u)
+                .saturating_sub(1),
+            count - 1,
+        );
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        let du = if width > 0.0 {
+            (u - self.cdf[offset]) / width
+        } else {
+            0.0
+        };
+        let x = f32::min((offset as f32 + du) / count as f32, 1.0 - f32::EPSILON);
+        (x, self.density(offset), offset)
+    }
+
+    pub fn pdf(&self, x: f32) -> f32 {
+        let count = self.function.len();
+        self.density(usize::min((x * count as f32) as usize, count - 1))
+    }
+}
+
+// Piecewise constant distribution over [0, 1)^2, for a function given row by row. Rows are
+// picked with the marginal distribution, then the point in the row with its conditional one
+#[derive(Debug, Clone)]
+pub struct Distribution2D {
+    conditionals: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(function: &[f32], width: usize, height: usize) -> Distribution2D {
+        debug_assert!(function.len() == width * height);
+        let conditionals: Vec<Distribution1D> =
+            function.chunks(width).map(Distribution1D::new).collect();
+        let row_integrals: Vec<f32> = conditionals.iter().map(|row| row.integral()).collect();
+        Distribution2D {
+            conditionals,
+            marginal: Distribution1D::new(&row_integrals),
         }
     }
+
+    // point (s along a row, t across the rows) with its pdf
+    pub fn sample(&self, sample: &Sample2D) -> (Sample2D, f32) {
+        let (t, marginal_pdf, row) = self.marginal.sample(sample.t);
+        let (s, conditional_pdf, _) = self.conditionals[row].sample(sample.s);
+        (Sample2D { s, t }, marginal_pdf * conditional_pdf)
+    }
+
+    pub fn pdf(&self, point: &Sample2D) -> f32 {
+        let height = self.conditionals.len();
+        let row = usize::min((point.t * height as f32) as usize, height - 1);
+        self.marginal.pdf(point.t) * self.conditionals[row].pdf(point.s)
+    }
 }
 
-impl Sampler {
-    #[inline(always)]
-    pub fn get_sample(&mut self) -> f32 {
-        self.distribution.sample(&mut self.rgen)
+// Source of the random numbers of a render. Samples are organized in dimensions: every pixel
+// sample is a point in a high dimensional unit cube and each random decision of a path reads
+// the coordinates of one or two dimensions, always the same for the same decision
+pub trait Sampler {
+    // moves to the first dimension of the sample_index-th sample of pixel (x, y)
+    fn start_pixel_sample(&mut self, x: u32, y: u32, sample_index: u32);
+
+    // jumps to the given dimension of the current pixel sample
+    fn set_dimension(&mut self, dimension: u32);
+
+    // next dimension, uniform in [0, 1)
+    fn get_sample(&mut self) -> f32;
+
+    // next two dimensions, distributed together
+    fn get_sample_2d(&mut self) -> Sample2D;
+}
+
+#[cfg(test)]
+mod tools_tests {
+    use super::{Distribution1D, Distribution2D, Pcg32, Sample2D};
+
+    #[test]
+    fn pcg_test() {
+        // first outputs of the pcg32 reference implementation, seeded with 42 on stream 54
+        let mut rgen = Pcg32::new(54, 42);
+        let expected: [u32; 6] = [
+            0xa15c02b7, 0x7b47f409, 0xba1d3330, 0x83d2f293, 0xbfa4784b, 0xcbed606e,
+        ];
+        for number in expected {
+            assert_eq!(rgen.next_u32(), number);
+        }
+
+        let mut stepped = Pcg32::new(7, 3);
+        let mut advanced = stepped;
+        for _ in 0..1000 {
+            stepped.next_u32();
+        }
+        advanced.advance(1000);
+        assert_eq!(stepped.next_u32(), advanced.next_u32());
     }
 
-    #[inline(always)]
-    pub fn get_sample_2d(&mut self) -> Sample2D {
-        Sample2D {
-            s: self.get_sample(),
-            t: self.get_sample(),
+    #[test]
+    fn distribution_test() {
+        let distribution = Distribution1D::new(&[1.0, 3.0, 0.0, 4.0]);
+        assert_eq!(distribution.integral(), 2.0);
+        let (x, pdf, offset) = distribution.sample(0.0);
+        assert_eq!((x, pdf, offset), (0.0, 0.5, 0));
+        // the empty piece is never sampled
+        let (x, pdf, offset) = distribution.sample(0.5);
+        assert_eq!(offset, 3);
+        assert!((x - 0.75).abs() < 1e-6 && pdf == 2.0);
+        assert_eq!(distribution.pdf(0.6), 0.0);
+        let (x, _, offset) = distribution.sample(0.999_999);
+        assert!(x < 1.0 && offset == 3);
+
+        let uniform = Distribution1D::new(&[0.0, 0.0]);
+        assert_eq!(uniform.sample(0.25), (0.25, 1.0, 0));
+
+        // the sampled points follow the function, and the pdf matches sample
+        let function = [1.0, 2.0, 3.0, 0.0, 5.0, 1.0];
+        let distribution = Distribution2D::new(&function, 3, 2);
+        let mut histogram = [0.0; 6];
+        let mut rgen = Pcg32::new(1, 2);
+        for _ in 0..60000 {
+            let sample = Sample2D {
+                s: rgen.next_f32(),
+                t: rgen.next_f32(),
+            };
+            let (point, pdf) = distribution.sample(&sample);
+            assert!((pdf - distribution.pdf(&point)).abs() < 1e-4);
+            let cell = (point.t * 2.0) as usize * 3 + (point.s * 3.0) as usize;
+            histogram[cell] += 12.0 / 60000.0;
+        }
+        for (observed, expected) in histogram.iter().zip(function) {
+            assert!(
+                (observed - expected).abs() < 0.1,
+                "{} {}",
+                observed,
+                expected
+            );
         }
     }
 }
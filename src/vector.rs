@@ -9,6 +9,11 @@ pub struct Vector3 {
 }
 
 impl Vector3 {
+    #[inline(always)]
+    pub fn new(x: f32, y: f32, z: f32) -> Vector3 {
+        Vector3 { x, y, z }
+    }
+
     pub fn zero_vector() -> Vector3 {
         Vector3 {
             x: 0.0,
@@ -95,6 +100,27 @@ impl Vector3 {
         *t = Vector3 { x, y, z }.unit();
         *b = t.cross(&normal);
     }
+
+    // rotation by angle radians around the unit axis, counter-clockwise when the axis points
+    // towards the viewer (Rodrigues' formula)
+    pub fn rotate(&self, axis: &Vector3, angle: f32) -> Vector3 {
+        let (sin, cos) = angle.sin_cos();
+        &(&(self * cos) + &(&axis.cross(self) * sin)) + &(axis * (axis.dot(self) * (1.0 - cos)))
+    }
+}
+
+impl ops::Index<usize> for Vector3 {
+    type Output = f32;
+
+    #[inline(always)]
+    fn index(&self, index: usize) -> &f32 {
+        match index {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vector3 index {} out of range", index),
+        }
+    }
 }
 
 impl PartialEq for Vector3 {
@@ -219,10 +245,208 @@ impl ops::Neg for &Vector3 {
     }
 }
 
+// Row major 4x4 matrix, points and vectors are columns multiplied on the right
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Matrix4 {
+    pub m: [[f32; 4]; 4],
+}
+
+impl Matrix4 {
+    pub fn identity() -> Matrix4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+        Matrix4 { m }
+    }
+
+    pub fn transpose(&self) -> Matrix4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.m[j][i];
+            }
+        }
+        Matrix4 { m }
+    }
+
+    // Gauss-Jordan elimination with partial pivoting, None for a singular matrix
+    pub fn inverse(&self) -> Option<Matrix4> {
+        let mut a = self.m;
+        let mut inverse = Matrix4::identity().m;
+        for column in 0..4 {
+            let pivot = (column..4)
+                .max_by(|i, j| a[*i][column].abs().total_cmp(&a[*j][column].abs()))
+                .unwrap();
+            if a[pivot][column].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(column, pivot);
+            inverse.swap(column, pivot);
+
+            let scale = 1.0 / a[column][column];
+            for j in 0..4 {
+                a[column][j] *= scale;
+                inverse[column][j] *= scale;
+            }
+            for row in 0..4 {
+                let factor = a[row][column];
+                if row == column || factor == 0.0 {
+                    continue;
+                }
+                for j in 0..4 {
+                    a[row][j] -= factor * a[column][j];
+                    inverse[row][j] -= factor * inverse[column][j];
+                }
+            }
+        }
+        Some(Matrix4 { m: inverse })
+    }
+
+    // determinant of the upper left 3x3 block, the change of volume of an affine transform
+    pub fn determinant3(&self) -> f32 {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+}
+
+impl ops::Mul<&Matrix4> for &Matrix4 {
+    type Output = Matrix4;
+
+    fn mul(self, rhs: &Matrix4) -> Matrix4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+        Matrix4 { m }
+    }
+}
+
+// Affine transform kept with its inverse, which moves rays into the space of the transformed
+// object
+#[derive(Debug, Clone, Copy)]
+pub struct Transform {
+    matrix: Matrix4,
+    inverse: Matrix4,
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform {
+            matrix: Matrix4::identity(),
+            inverse: Matrix4::identity(),
+        }
+    }
+
+    // None when the matrix cannot be inverted, like a scale by zero
+    pub fn from_matrix(matrix: Matrix4) -> Option<Transform> {
+        Some(Transform {
+            inverse: matrix.inverse()?,
+            matrix,
+        })
+    }
+
+    pub fn translation(offset: &Vector3) -> Transform {
+        let mut matrix = Matrix4::identity();
+        let mut inverse = Matrix4::identity();
+        for i in 0..3 {
+            matrix.m[i][3] = offset[i];
+            inverse.m[i][3] = -offset[i];
+        }
+        Transform { matrix, inverse }
+    }
+
+    // factors must not be zero
+    pub fn scaling(factors: &Vector3) -> Transform {
+        debug_assert!(factors.x != 0.0 && factors.y != 0.0 && factors.z != 0.0);
+        let mut matrix = Matrix4::identity();
+        let mut inverse = Matrix4::identity();
+        for i in 0..3 {
+            matrix.m[i][i] = factors[i];
+            inverse.m[i][i] = 1.0 / factors[i];
+        }
+        Transform { matrix, inverse }
+    }
+
+    // by angle radians around the unit axis, like Vector3::rotate
+    pub fn rotation(axis: &Vector3, angle: f32) -> Transform {
+        let mut matrix = Matrix4::identity();
+        let basis = [
+            Vector3::new(1.0, 0.0, 0.0),
+            Vector3::new(0.0, 1.0, 0.0),
+            Vector3::new(0.0, 0.0, 1.0),
+        ];
+        for (j, vector) in basis.iter().enumerate() {
+            let column = vector.rotate(axis, angle);
+            for i in 0..3 {
+                matrix.m[i][j] = column[i];
+            }
+        }
+        // rotations are orthogonal
+        Transform {
+            inverse: matrix.transpose(),
+            matrix,
+        }
+    }
+
+    pub fn matrix(&self) -> &Matrix4 {
+        &self.matrix
+    }
+
+    pub fn inverse(&self) -> Transform {
+        Transform {
+            matrix: self.inverse,
+            inverse: self.matrix,
+        }
+    }
+
+    // this transform followed by the other one
+    pub fn then(&self, other: &Transform) -> Transform {
+        Transform {
+            matrix: &other.matrix * &self.matrix,
+            inverse: &self.inverse * &other.inverse,
+        }
+    }
+
+    pub fn point(&self, point: &Vector3) -> Vector3 {
+        let m = &self.matrix.m;
+        Vector3::new(
+            m[0][0] * point.x + m[0][1] * point.y + m[0][2] * point.z + m[0][3],
+            m[1][0] * point.x + m[1][1] * point.y + m[1][2] * point.z + m[1][3],
+            m[2][0] * point.x + m[2][1] * point.y + m[2][2] * point.z + m[2][3],
+        )
+    }
+
+    // directions are not translated
+    pub fn vector(&self, vector: &Vector3) -> Vector3 {
+        let m = &self.matrix.m;
+        Vector3::new(
+            m[0][0] * vector.x + m[0][1] * vector.y + m[0][2] * vector.z,
+            m[1][0] * vector.x + m[1][1] * vector.y + m[1][2] * vector.z,
+            m[2][0] * vector.x + m[2][1] * vector.y + m[2][2] * vector.z,
+        )
+    }
+
+    // normals stay perpendicular to the surface with the inverse transpose, the result is not
+    // normalized
+    pub fn normal(&self, normal: &Vector3) -> Vector3 {
+        let m = &self.inverse.m;
+        Vector3::new(
+            m[0][0] * normal.x + m[1][0] * normal.y + m[2][0] * normal.z,
+            m[0][1] * normal.x + m[1][1] * normal.y + m[2][1] * normal.z,
+            m[0][2] * normal.x + m[1][2] * normal.y + m[2][2] * normal.z,
+        )
+    }
+}
+
 #[cfg(test)]
 mod vector_tests {
     use crate::tools;
-    use crate::vector::Vector3;
+    use crate::vector::{Matrix4, Transform, Vector3};
 
     #[test]
     fn main_test() {
@@ -321,4 +545,62 @@ mod vector_tests {
         vec.normalize();
         assert!(vec == unit_vec);
     }
+
+    #[test]
+    fn rotate_test() {
+        // a quarter turn around z takes -x to -y, and any turn keeps the length and the component
+        // along the axis
+        let z_axis = Vector3::new(0.0, 0.0, 1.0);
+        let y_axis = Vector3::new(0.0, 1.0, 0.0);
+        let neg_x_axis = Vector3::new(-1.0, 0.0, 0.0);
+        assert!(neg_x_axis.rotate(&z_axis, std::f32::consts::FRAC_PI_2) == -&y_axis);
+        let a = Vector3::new(3.0, -2.0, 1.5);
+        let rotated = a.rotate(&y_axis, 1.3);
+        assert!(tools::equal_error(rotated.length(), a.length()));
+        assert!(tools::equal_error(rotated.y, a.y));
+        assert!(!tools::equal_error(rotated.x, a.x));
+    }
+
+    #[test]
+    fn transform_test() {
+        let point = Vector3::new(1.0, 2.0, 3.0);
+        let transform = Transform::scaling(&Vector3::new(2.0, 1.0, 0.5))
+            .then(&Transform::rotation(
+                &Vector3::new(0.0, 1.0, 0.0),
+                std::f32::consts::FRAC_PI_2,
+            ))
+            .then(&Transform::translation(&Vector3::new(0.0, 0.0, 1.0)));
+        // scaled to (2, 2, 1.5), turned to (1.5, 2, -2), moved to (1.5, 2, -1)
+        assert!(transform.point(&point) == Vector3::new(1.5, 2.0, -1.0));
+        assert!(transform.vector(&point) == Vector3::new(1.5, 2.0, -2.0));
+        assert!(transform.inverse().point(&transform.point(&point)) == point);
+
+        // the general inverse agrees with the one built along
+        let inverse = transform.matrix().inverse().unwrap();
+        let product = &inverse * transform.matrix();
+        for i in 0..4 {
+            for j in 0..4 {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!(tools::equal_error(product.m[i][j], expected));
+            }
+        }
+        assert!(tools::equal_error(
+            transform.inverse().matrix().m[0][2],
+            inverse.m[0][2]
+        ));
+        assert!(tools::equal_error(transform.matrix().determinant3(), 1.0));
+        let mut singular = Matrix4::identity();
+        singular.m[1][1] = 0.0;
+        assert!(Transform::from_matrix(singular).is_none());
+
+        // normals stay perpendicular to the transformed tangents
+        let tangent = Vector3::new(1.0, -1.0, 0.0);
+        let normal = Vector3::new(1.0, 1.0, 0.0);
+        let transformed_tangent = transform.vector(&tangent);
+        let transformed_normal = transform.normal(&normal);
+        assert!(tools::equal_error(
+            transformed_tangent.dot(&transformed_normal),
+            0.0
+        ));
+    }
 }